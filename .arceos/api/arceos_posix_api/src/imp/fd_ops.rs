@@ -3,6 +3,7 @@ use crate::ctypes::{FD_CLOEXEC, O_NONBLOCK, timespec};
 use crate::imp::fd_ops::poll_flags::*;
 use crate::imp::pipe::Pipe;
 use crate::imp::stdio::{stdin, stdout};
+use alloc::collections::BTreeSet;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use axerrno::{LinuxError, LinuxResult};
@@ -12,7 +13,7 @@ use axns::{ResArc, def_resource};
 use axtask::yield_now;
 use core::ffi::{c_int, c_void};
 use core::mem::replace;
-use core::ops::Deref;
+use core::ops::{Deref, DerefMut};
 use core::ptr::drop_in_place;
 use flatten_objects::FlattenObjects;
 use spin::RwLock;
@@ -29,18 +30,69 @@ pub trait FileLike: Send + Sync {
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
 }
 
+/// A file descriptor table, with the close-on-exec flags of the descriptors.
+pub struct FdTable {
+    files: FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>,
+    /// The descriptors closed by `execve`, i.e. with `FD_CLOEXEC`.
+    cloexec: BTreeSet<usize>,
+}
+
+impl FdTable {
+    fn new() -> Self {
+        Self {
+            files: FlattenObjects::new(),
+            cloexec: BTreeSet::new(),
+        }
+    }
+
+    /// Remove the file of `fd`, along with its close-on-exec flag.
+    pub fn remove(&mut self, fd: usize) -> Option<Arc<dyn FileLike>> {
+        self.cloexec.remove(&fd);
+        self.files.remove(fd)
+    }
+
+    /// Return whether `fd` is closed by `execve`.
+    pub fn is_cloexec(&self, fd: usize) -> bool {
+        self.cloexec.contains(&fd)
+    }
+
+    /// Set whether `fd` is closed by `execve`.
+    pub fn set_cloexec(&mut self, fd: usize, cloexec: bool) {
+        if cloexec {
+            self.cloexec.insert(fd);
+        } else {
+            self.cloexec.remove(&fd);
+        }
+    }
+}
+
+impl Deref for FdTable {
+    type Target = FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.files
+    }
+}
+
+impl DerefMut for FdTable {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.files
+    }
+}
+
 def_resource! {
-    pub static FD_TABLE: ResArc<RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>>> = ResArc::new();
+    pub static FD_TABLE: ResArc<RwLock<FdTable>> = ResArc::new();
 }
 
 impl FD_TABLE {
     /// Return a copy of the inner table.
-    pub fn copy_inner(&self) -> RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> {
+    pub fn copy_inner(&self) -> RwLock<FdTable> {
         let table = self.read();
-        let mut new_table = FlattenObjects::new();
+        let mut new_table = FdTable::new();
         for id in table.ids() {
             let _ = new_table.add_at(id, table.get(id).unwrap().clone());
         }
+        new_table.cloexec = table.cloexec.clone();
         RwLock::new(new_table)
     }
 }
@@ -56,7 +108,16 @@ pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
 
 /// Add a file to the file descriptor table.
 pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).map_err(|_| LinuxError::EMFILE)? as c_int)
+    add_file_like_cloexec(f, false)
+}
+
+/// Add a file to the file descriptor table, closed by `execve` if `cloexec`
+/// is `true`.
+pub fn add_file_like_cloexec(f: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult<c_int> {
+    let mut fd_table = FD_TABLE.write();
+    let fd = fd_table.add(f).map_err(|_| LinuxError::EMFILE)?;
+    fd_table.set_cloexec(fd, cloexec);
+    Ok(fd as c_int)
 }
 
 /// Close a file by `fd`.
@@ -69,6 +130,15 @@ pub fn close_file_like(fd: c_int) -> LinuxResult {
     Ok(())
 }
 
+/// Close the files with `FD_CLOEXEC`, for `execve`.
+pub fn close_cloexec_files() {
+    let mut fd_table = FD_TABLE.write();
+    let cloexec: Vec<_> = fd_table.cloexec.iter().copied().collect();
+    for fd in cloexec {
+        drop(fd_table.remove(fd));
+    }
+}
+
 pub fn close_all_file_like() {
     let ref_count = FD_TABLE.ref_count();
     error!("ref count for FD_TABLE is {}", ref_count);
@@ -94,16 +164,16 @@ pub fn sys_close(fd: c_int) -> c_int {
     syscall_body!(sys_close, close_file_like(fd).map(|_| 0))
 }
 
-fn dup_fd(old_fd: c_int) -> LinuxResult<c_int> {
+fn dup_fd(old_fd: c_int, cloexec: bool) -> LinuxResult<c_int> {
     let f = get_file_like(old_fd)?;
-    let new_fd = add_file_like(f)?;
+    let new_fd = add_file_like_cloexec(f, cloexec)?;
     Ok(new_fd)
 }
 
 /// Duplicate a file descriptor.
 pub fn sys_dup(old_fd: c_int) -> c_int {
     debug!("sys_dup <= {}", old_fd);
-    syscall_body!(sys_dup, dup_fd(old_fd))
+    syscall_body!(sys_dup, dup_fd(old_fd, false))
 }
 
 /// Duplicate a file descriptor, but it uses the file descriptor number specified in `new_fd`.
@@ -134,16 +204,13 @@ pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
 
 /// Manipulate file descriptor.
 ///
-/// TODO: `SET/GET` command is ignored, hard-code stdin/stdout
+/// TODO: `F_GETFL` is hard-coded
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
     debug!("sys_fcntl <= fd: {} cmd: {} arg: {}", fd, cmd, arg);
     syscall_body!(sys_fcntl, {
         match cmd as u32 {
-            ctypes::F_DUPFD => dup_fd(fd),
-            ctypes::F_DUPFD_CLOEXEC => {
-                // TODO: Change fd flags
-                dup_fd(fd)
-            }
+            ctypes::F_DUPFD => dup_fd(fd, false),
+            ctypes::F_DUPFD_CLOEXEC => dup_fd(fd, true),
             ctypes::F_SETFL => {
                 if fd == 0 || fd == 1 || fd == 2 {
                     return Ok(0);
@@ -152,8 +219,19 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
                 Ok(0)
             }
             ctypes::F_GETFD => {
-                warn!("unsupported fcntl parameters: F_GETFD, returning FD_CLOEXEC");
-                Ok(FD_CLOEXEC as _)
+                let fd_table = FD_TABLE.read();
+                fd_table.get(fd as usize).ok_or(LinuxError::EBADF)?;
+                Ok(if fd_table.is_cloexec(fd as usize) {
+                    FD_CLOEXEC as _
+                } else {
+                    0
+                })
+            }
+            ctypes::F_SETFD => {
+                let mut fd_table = FD_TABLE.write();
+                fd_table.get(fd as usize).ok_or(LinuxError::EBADF)?;
+                fd_table.set_cloexec(fd as usize, arg & FD_CLOEXEC as usize != 0);
+                Ok(0)
             }
             ctypes::F_GETFL => {
                 warn!("unsupported fcntl parameters: F_GETFL, returning O_NONBLOCK");
@@ -169,7 +247,7 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
 
 #[ctor_bare::register_ctor]
 fn init_stdio() {
-    let mut fd_table = flatten_objects::FlattenObjects::new();
+    let mut fd_table = FdTable::new();
     fd_table
         .add_at(0, Arc::new(stdin()) as _)
         .unwrap_or_else(|_| panic!()); // stdin
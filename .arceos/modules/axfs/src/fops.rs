@@ -155,6 +155,21 @@ impl File {
         unsafe { self.node.access_unchecked() }
     }
 
+    /// Returns whether the file is opened for reading.
+    pub fn is_readable(&self) -> bool {
+        self.access_node(Cap::READ).is_ok()
+    }
+
+    /// Returns whether the file is opened for writing.
+    pub fn is_writable(&self) -> bool {
+        self.access_node(Cap::WRITE).is_ok()
+    }
+
+    /// Returns whether the file is opened in append mode.
+    pub fn is_append(&self) -> bool {
+        self.is_append
+    }
+
     fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
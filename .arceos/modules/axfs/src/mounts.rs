@@ -46,6 +46,17 @@ pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
     file_over.write_at(0, b"0\n")?;
 
+    // Create /proc/sys/kernel/randomize_va_space, write 0 to disable ASLR
+    proc_root.create("sys/kernel", VfsNodeType::Dir)?;
+    proc_root.create("sys/kernel/randomize_va_space", VfsNodeType::File)?;
+    let file_aslr = proc_root.clone().lookup("./sys/kernel/randomize_va_space")?;
+    file_aslr.write_at(0, b"2\n")?;
+
+    // Create /proc/sys/kernel/pid_max, the upper bound of process IDs
+    proc_root.create("sys/kernel/pid_max", VfsNodeType::File)?;
+    let file_pid_max = proc_root.clone().lookup("./sys/kernel/pid_max")?;
+    file_pid_max.write_at(0, b"32768\n")?;
+
     // Create /proc/meminfo
     // TODO: stub
     proc_root.create("meminfo", VfsNodeType::File)?;
@@ -2,7 +2,9 @@
 //!
 //! TODO: it doesn't work very well if the mount points have containment relationships.
 
+use alloc::collections::BTreeMap;
 use alloc::string::ToString;
+use alloc::sync::Weak;
 use alloc::{string::String, sync::Arc, vec::Vec};
 use axerrno::{AxError, AxResult, ax_err};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
@@ -44,6 +46,9 @@ struct MountPoint {
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
     mounts: RwLock<Vec<MountPoint>>,
+    /// The nodes of the regular files in use by their canonical paths, so that
+    /// a file is looked up as the same node until it is no longer used.
+    files: Mutex<BTreeMap<String, Weak<dyn VfsNodeOps>>>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
@@ -65,6 +70,7 @@ impl RootDirectory {
         Self {
             main_fs,
             mounts: RwLock::new(Vec::new()),
+            files: Mutex::new(BTreeMap::new()),
         }
     }
 
@@ -93,6 +99,28 @@ impl RootDirectory {
         self.mounts.read().iter().any(|mp| mp.path == path)
     }
 
+    /// Returns the node of the regular file at `path` if it is in use.
+    fn lookup_file(&self, path: &str) -> Option<VfsNodeRef> {
+        self.files.lock().get(path).and_then(Weak::upgrade)
+    }
+
+    /// Records the node of the file at `path`, returns the node recorded
+    /// instead if another one is looked up at the same time.
+    fn insert_file(&self, path: String, node: VfsNodeRef) -> VfsNodeRef {
+        let mut files = self.files.lock();
+        if let Some(file) = files.get(&path).and_then(Weak::upgrade) {
+            return file;
+        }
+        files.retain(|_, file| file.strong_count() > 0);
+        files.insert(path, Arc::downgrade(&node));
+        node
+    }
+
+    /// Forgets the node of the file at `path` after it is removed or renamed.
+    fn forget_file(&self, path: &str) {
+        self.files.lock().remove(&file_key(path));
+    }
+
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
     where
         F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
@@ -134,10 +162,20 @@ impl VfsNodeOps for RootDirectory {
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
-        self.lookup_mounted_fs(path, |fs, rest_path| fs.root_dir().lookup(rest_path))
+        let key = file_key(path);
+        if let Some(node) = self.lookup_file(&key) {
+            return Ok(node);
+        }
+        let node = self.lookup_mounted_fs(path, |fs, rest_path| fs.root_dir().lookup(rest_path))?;
+        if node.get_attr()?.is_file() {
+            Ok(self.insert_file(key, node))
+        } else {
+            Ok(node)
+        }
     }
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        self.forget_file(path);
         self.lookup_mounted_fs(path, |fs, rest_path| {
             if rest_path.is_empty() {
                 Ok(()) // already exists
@@ -148,6 +186,7 @@ impl VfsNodeOps for RootDirectory {
     }
 
     fn remove(&self, path: &str) -> VfsResult {
+        self.forget_file(path);
         self.lookup_mounted_fs(path, |fs, rest_path| {
             if rest_path.is_empty() {
                 ax_err!(PermissionDenied) // cannot remove mount points
@@ -158,6 +197,10 @@ impl VfsNodeOps for RootDirectory {
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        // The nodes of some file systems refer to the files by their paths, so
+        // the file is looked up again after it is renamed.
+        self.forget_file(src_path);
+        self.forget_file(dst_path);
         self.lookup_mounted_fs(src_path, |fs, rest_path| {
             if rest_path.is_empty() {
                 ax_err!(PermissionDenied) // cannot rename mount points
@@ -168,6 +211,12 @@ impl VfsNodeOps for RootDirectory {
     }
 }
 
+/// Returns the key of the file at `path`, relative to the root directory, in
+/// [`RootDirectory::files`].
+fn file_key(path: &str) -> String {
+    axfs_vfs::path::canonicalize(&("/".to_string() + path))
+}
+
 pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
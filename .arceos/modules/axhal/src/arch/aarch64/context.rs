@@ -334,6 +334,7 @@ impl TaskContext {
         #[cfg(feature = "uspace")]
         {
             if self.ttbr0_el1 != next_ctx.ttbr0_el1 {
+                crate::tlb::set_active_page_table(next_ctx.ttbr0_el1);
                 unsafe { super::write_page_table_root0(next_ctx.ttbr0_el1) };
             }
         }
@@ -143,10 +143,21 @@ pub unsafe fn write_thread_pointer(tpidr_el0: usize) {
 /// Initializes CPU states on the current CPU.
 ///
 /// On AArch64, it sets the exception vector base address (`VBAR_EL1`) and `TTBR0_EL1`.
+/// If the `uspace` feature is enabled, it also allows EL0 to read the physical
+/// counter (`CNTPCT_EL0`).
 pub fn cpu_init() {
     unsafe extern "C" {
         fn exception_vector_base();
     }
     set_exception_vector_base(exception_vector_base as usize);
     unsafe { write_page_table_root0(0.into()) }; // disable low address access in EL1
+    #[cfg(feature = "uspace")]
+    unsafe {
+        asm!(
+            "mrs {tmp}, cntkctl_el1",
+            "orr {tmp}, {tmp}, #1", // EL0PCTEN
+            "msr cntkctl_el1, {tmp}",
+            tmp = out(reg) _,
+        )
+    };
 }
@@ -5,12 +5,16 @@ use page_table_entry::MappingFlags;
 use tock_registers::interfaces::Readable;
 
 use super::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::UserException;
 
 global_asm!(
     include_str!("trap.S"),
     trapframe_size = const core::mem::size_of::<TrapFrame>(),
     cache_current_task_ptr = sym crate::cpu::cache_current_task_ptr,
 );
+#[cfg(feature = "uspace")]
+global_asm!(include_str!("uaccess.S"));
 
 #[repr(u8)]
 #[derive(Debug)]
@@ -51,7 +55,29 @@ fn handle_irq_exception(tf: &mut TrapFrame, source: TrapSource) {
     crate::trap::post_trap_callback(tf, source.is_from_user());
 }
 
-fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
+/// Forwards an exception raised by user programs to the registered handler.
+#[cfg(feature = "uspace")]
+fn handle_user_exception(tf: &mut TrapFrame, exception: UserException) {
+    if !handle_trap!(USER_EXCEPTION, tf, exception) {
+        panic!(
+            "Unhandled user exception {:?} @ {:#x}:\n{:#x?}",
+            exception, tf.elr, tf
+        );
+    }
+}
+
+/// Returns the exception for a user abort which is not a page fault.
+#[cfg(feature = "uspace")]
+fn user_abort_exception(iss: u64, vaddr: memory_addr::VirtAddr) -> UserException {
+    // IFSC or DFSC 0b100001: Alignment fault
+    if iss & 0b111111 == 0b100001 {
+        UserException::Misaligned(vaddr)
+    } else {
+        UserException::AccessFault(vaddr)
+    }
+}
+
+fn handle_instruction_abort(tf: &mut TrapFrame, iss: u64, is_user: bool) {
     let mut access_flags = MappingFlags::EXECUTE;
     if is_user {
         access_flags |= MappingFlags::USER;
@@ -59,9 +85,12 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     let vaddr = va!(FAR_EL1.get() as usize);
 
     // Only handle Translation fault and Permission fault
-    if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
-        || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
-    {
+    let is_page_fault = matches!(iss & 0b111100, 0b0100 | 0b1100); // IFSC or DFSC bits
+    #[cfg(feature = "uspace")]
+    if is_user && !is_page_fault {
+        return handle_user_exception(tf, user_abort_exception(iss, vaddr));
+    }
+    if !is_page_fault || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
         panic!(
             "Unhandled {} Instruction Abort @ {:#x}, fault_vaddr={:#x}, ISS={:#x} ({:?}):\n{:#x?}",
             if is_user { "EL0" } else { "EL1" },
@@ -74,7 +103,7 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
 }
 
-fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
+fn handle_data_abort(tf: &mut TrapFrame, iss: u64, is_user: bool) {
     let wnr = (iss & (1 << 6)) != 0; // WnR: Write not Read
     let cm = (iss & (1 << 8)) != 0; // CM: Cache maintenance
     let mut access_flags = if wnr & !cm {
@@ -88,9 +117,23 @@ fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     let vaddr = va!(FAR_EL1.get() as usize);
 
     // Only handle Translation fault and Permission fault
-    if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
-        || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
-    {
+    let is_page_fault = matches!(iss & 0b111100, 0b0100 | 0b1100); // IFSC or DFSC bits
+    #[cfg(feature = "uspace")]
+    if is_user && !is_page_fault {
+        return handle_user_exception(tf, user_abort_exception(iss, vaddr));
+    }
+    if !is_page_fault || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+        // Faults in the routines accessing user memory return errors instead.
+        #[cfg(feature = "uspace")]
+        if is_page_fault && !is_user {
+            if let Some(fixup) = crate::uaccess::fixup_exception(tf.elr as usize) {
+                tf.elr = fixup as u64;
+                return;
+            }
+            if handle_trap!(UNFIXED_USER_FAULT, vaddr, access_flags) {
+                return;
+            }
+        }
         panic!(
             "Unhandled {} Data Abort @ {:#x}, fault_vaddr={:#x}, ISS=0b{:08b} ({:?}):\n{:#x?}",
             if is_user { "EL0" } else { "EL1" },
@@ -119,6 +162,38 @@ fn handle_sync_exception(tf: &mut TrapFrame, source: TrapSource) {
         Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => handle_instruction_abort(tf, iss, false),
         Some(ESR_EL1::EC::Value::DataAbortLowerEL) => handle_data_abort(tf, iss, true),
         Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => handle_data_abort(tf, iss, false),
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::Unknown) if source.is_from_user() => {
+            handle_user_exception(tf, UserException::IllegalInstruction(va!(tf.elr as usize)))
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::PCAlignmentFault) if source.is_from_user() => {
+            handle_user_exception(tf, UserException::Misaligned(va!(FAR_EL1.get() as usize)))
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::SPAlignmentFault) if source.is_from_user() => {
+            handle_user_exception(tf, UserException::Misaligned(va!(tf.elr as usize)))
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::TrappedFP64) if source.is_from_user() => {
+            handle_user_exception(tf, UserException::FloatingPoint(va!(tf.elr as usize)))
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::Brk64 | ESR_EL1::EC::Value::BreakpointLowerEL)
+            if source.is_from_user() =>
+        {
+            handle_user_exception(tf, UserException::Breakpoint(va!(tf.elr as usize)))
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::SoftwareStepLowerEL) if source.is_from_user() => {
+            handle_user_exception(tf, UserException::SingleStep(va!(tf.elr as usize)))
+        }
+        // Other exceptions raised by user programs, e.g. trapped system
+        // instructions, are reported as illegal instructions.
+        #[cfg(feature = "uspace")]
+        _ if source.is_from_user() => {
+            handle_user_exception(tf, UserException::IllegalInstruction(va!(tf.elr as usize)))
+        }
         Some(ESR_EL1::EC::Value::Brk64) => {
             debug!("BRK #{:#x} @ {:#x} ", iss, tf.elr);
             tf.elr += 4;
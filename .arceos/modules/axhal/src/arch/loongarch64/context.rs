@@ -327,6 +327,7 @@ impl TaskContext {
         #[cfg(feature = "uspace")]
         {
             if self.pgdl != next_ctx.pgdl {
+                crate::tlb::set_active_page_table(pa!(next_ctx.pgdl));
                 unsafe { super::write_page_table_root0(pa!(next_ctx.pgdl)) };
             }
         }
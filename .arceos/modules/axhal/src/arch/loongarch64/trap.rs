@@ -10,18 +10,31 @@ core::arch::global_asm!(
     include_str!("trap.S"),
     trapframe_size = const (core::mem::size_of::<TrapFrame>()),
 );
+#[cfg(feature = "uspace")]
+core::arch::global_asm!(include_str!("uaccess.S"));
 
 fn handle_breakpoint(era: &mut usize) {
     debug!("Exception(Breakpoint) @ {:#x} ", era);
     *era += 4;
 }
 
-fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
+fn handle_page_fault(tf: &mut TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
     if is_user {
         access_flags |= MappingFlags::USER;
     }
     let vaddr = va!(badv::read().raw());
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+        // Faults in the routines accessing user memory return errors instead.
+        #[cfg(feature = "uspace")]
+        if !is_user {
+            if let Some(fixup) = crate::uaccess::fixup_exception(tf.era) {
+                tf.era = fixup;
+                return;
+            }
+            if handle_trap!(UNFIXED_USER_FAULT, vaddr, access_flags) {
+                return;
+            }
+        }
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
             if is_user { "PLV3" } else { "PLV0" },
@@ -33,6 +46,29 @@ fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bo
     }
 }
 
+/// Forwards an exception raised by user programs to the registered handler.
+#[cfg(feature = "uspace")]
+fn handle_user_exception(tf: &mut TrapFrame, exception: Exception) {
+    use crate::trap::UserException as U;
+    let pc = va!(tf.era);
+    let exception = match exception {
+        Exception::InstructionNotExist => U::IllegalInstruction(pc),
+        Exception::InstructionPrivilegeError => U::PrivilegedInstruction(pc),
+        Exception::AddressNotAligned => U::Misaligned(va!(badv::read().raw())),
+        Exception::FetchInstructionAddressError | Exception::MemoryAccessAddressError => {
+            U::AccessFault(va!(badv::read().raw()))
+        }
+        Exception::Breakpoint => U::Breakpoint(pc),
+        _ => U::IllegalInstruction(pc),
+    };
+    if !handle_trap!(USER_EXCEPTION, tf, exception) {
+        panic!(
+            "Unhandled user exception {:?} @ {:#x}:\n{:#x?}",
+            exception, tf.era, tf
+        );
+    }
+}
+
 #[unsafe(no_mangle)]
 fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
     let estat = estat::read();
@@ -60,6 +96,8 @@ fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
         | Trap::Exception(Exception::PageNonExecutableFault) => {
             handle_page_fault(tf, MappingFlags::EXECUTE, from_user);
         }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(e) if from_user => handle_user_exception(tf, e),
         Trap::Exception(Exception::Breakpoint) => handle_breakpoint(&mut tf.era),
         Trap::Interrupt(_) => {
             let irq_num: usize = estat.is().trailing_zeros() as usize;
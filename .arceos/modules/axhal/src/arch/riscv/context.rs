@@ -379,6 +379,7 @@ impl TaskContext {
         #[cfg(feature = "uspace")]
         unsafe {
             if self.satp != next_ctx.satp {
+                crate::tlb::set_active_page_table(next_ctx.satp);
                 super::write_page_table_root(next_ctx.satp);
             }
         }
@@ -112,10 +112,15 @@ pub unsafe fn write_thread_pointer(tp: usize) {
 
 /// Initializes CPU states on the current CPU.
 ///
-/// On RISC-V, it sets the trap vector base address.
+/// On RISC-V, it sets the trap vector base address. If the `uspace` feature is
+/// enabled, it also allows user mode to read the `time` CSR.
 pub fn cpu_init() {
     unsafe extern "C" {
         fn trap_vector_base();
     }
     set_trap_vector_base(trap_vector_base as usize);
+    #[cfg(feature = "uspace")]
+    unsafe {
+        core::arch::asm!("csrs scounteren, {}", in(reg) 1 << 1) // TM
+    };
 }
@@ -11,6 +11,8 @@ core::arch::global_asm!(
     include_str!("trap.S"),
     trapframe_size = const core::mem::size_of::<TrapFrame>(),
 );
+#[cfg(feature = "uspace")]
+core::arch::global_asm!(include_str!("uaccess.S"));
 
 fn handle_breakpoint(sepc: &mut usize) {
     debug!("Exception(Breakpoint) @ {:#x} ", sepc);
@@ -18,7 +20,7 @@ fn handle_breakpoint(sepc: &mut usize) {
 }
 
 fn handle_page_fault(
-    tf: &TrapFrame,
+    tf: &mut TrapFrame,
     vaddr: VirtAddr,
     mut access_flags: MappingFlags,
     is_user: bool,
@@ -27,6 +29,17 @@ fn handle_page_fault(
         access_flags |= MappingFlags::USER;
     }
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+        // Faults in the routines accessing user memory return errors instead.
+        #[cfg(feature = "uspace")]
+        if !is_user {
+            if let Some(fixup) = crate::uaccess::fixup_exception(tf.sepc) {
+                tf.sepc = fixup;
+                return;
+            }
+            if handle_trap!(UNFIXED_USER_FAULT, vaddr, access_flags) {
+                return;
+            }
+        }
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
             if is_user { "User" } else { "Supervisor" },
@@ -38,6 +51,26 @@ fn handle_page_fault(
     }
 }
 
+/// Forwards an exception raised by user programs to the registered handler.
+#[cfg(feature = "uspace")]
+fn handle_user_exception(tf: &mut TrapFrame, cause: E, stval: VirtAddr) {
+    use crate::trap::UserException as U;
+    let pc = va!(tf.sepc);
+    let exception = match cause {
+        E::IllegalInstruction => U::IllegalInstruction(pc),
+        E::InstructionMisaligned | E::LoadMisaligned | E::StoreMisaligned => U::Misaligned(stval),
+        E::InstructionFault | E::LoadFault | E::StoreFault => U::AccessFault(stval),
+        E::Breakpoint => U::Breakpoint(pc),
+        _ => U::IllegalInstruction(pc),
+    };
+    if !handle_trap!(USER_EXCEPTION, tf, exception) {
+        panic!(
+            "Unhandled user exception {:?} @ {:#x}:\n{:#x?}",
+            exception, tf.sepc, tf
+        );
+    }
+}
+
 #[unsafe(no_mangle)]
 fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
     let scause = scause::read();
@@ -63,6 +96,8 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             Trap::Exception(E::InstructionPageFault) => {
                 handle_page_fault(tf, vaddr, MappingFlags::EXECUTE, from_user)
             }
+            #[cfg(feature = "uspace")]
+            Trap::Exception(e) if from_user => handle_user_exception(tf, e, vaddr),
             Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
             Trap::Interrupt(_) => {
                 handle_trap!(IRQ, scause.bits());
@@ -438,6 +438,7 @@ impl TaskContext {
             x86::msr::wrmsr(x86::msr::IA32_KERNEL_GSBASE, next_ctx.gs_base as u64);
             super::tss_set_rsp0(next_ctx.kstack_top);
             if next_ctx.cr3 != self.cr3 {
+                crate::tlb::set_active_page_table(next_ctx.cr3);
                 super::write_page_table_root(next_ctx.cr3);
             }
         }
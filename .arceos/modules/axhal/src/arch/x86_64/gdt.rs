@@ -35,6 +35,9 @@ impl GdtStruct {
     pub const UCODE64_SELECTOR: SegmentSelector = SegmentSelector::new(6, PrivilegeLevel::Ring3);
     /// TSS segment.
     pub const TSS_SELECTOR: SegmentSelector = SegmentSelector::new(7, PrivilegeLevel::Ring0);
+    /// User data segment whose limit is the CPU number, read with `lsl` in
+    /// user mode.
+    pub const CPU_NUMBER_SELECTOR: SegmentSelector = SegmentSelector::new(9, PrivilegeLevel::Ring3);
 
     /// Constructs a new GDT struct that filled with the default segment
     /// descriptors, including the given TSS segment.
@@ -51,6 +54,10 @@ impl GdtStruct {
             table[7] = low;
             table[8] = high;
         }
+        // A present, accessed, read/write data segment of DPL 3 with byte
+        // granularity, whose 20-bit limit is the CPU number.
+        let cpu_id = crate::cpu::this_cpu_id() as u64;
+        table[9] = 0x0000_f300_0000_0000 | (cpu_id & 0xffff) | ((cpu_id >> 16 & 0xf) << 48);
         Self { table }
     }
 
@@ -6,6 +6,8 @@ use x86_64::structures::idt::PageFaultErrorCode;
 use super::context::TrapFrame;
 
 core::arch::global_asm!(include_str!("trap.S"));
+#[cfg(feature = "uspace")]
+core::arch::global_asm!(include_str!("uaccess.S"));
 
 #[cfg(feature = "uspace")]
 const LEGACY_SYSCALL_VECTOR: u8 = 0x80;
@@ -13,11 +15,22 @@ const LEGACY_SYSCALL_VECTOR: u8 = 0x80;
 const IRQ_VECTOR_START: u8 = 0x20;
 const IRQ_VECTOR_END: u8 = 0xff;
 
-fn handle_page_fault(tf: &TrapFrame) {
+fn handle_page_fault(tf: &mut TrapFrame) {
     let access_flags = err_code_to_flags(tf.error_code)
         .unwrap_or_else(|e| panic!("Invalid #PF error code: {:#x}", e));
     let vaddr = va!(unsafe { cr2() });
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, tf.is_user()) {
+        // Faults in the routines accessing user memory return errors instead.
+        #[cfg(feature = "uspace")]
+        if !tf.is_user() {
+            if let Some(fixup) = crate::uaccess::fixup_exception(tf.rip as usize) {
+                tf.rip = fixup as u64;
+                return;
+            }
+            if handle_trap!(UNFIXED_USER_FAULT, vaddr, access_flags) {
+                return;
+            }
+        }
         panic!(
             "Unhandled {} #PF @ {:#x}, fault_vaddr={:#x}, error_code={:#x} ({:?}):\n{:#x?}",
             if tf.is_user() { "user" } else { "kernel" },
@@ -30,6 +43,35 @@ fn handle_page_fault(tf: &TrapFrame) {
     }
 }
 
+/// Forwards an exception raised by user programs to the registered handler.
+#[cfg(feature = "uspace")]
+fn handle_user_exception(tf: &mut TrapFrame) {
+    use crate::trap::UserException as E;
+    let pc = va!(tf.rip as usize);
+    let exception = match tf.vector as u8 {
+        DIVIDE_ERROR_VECTOR => E::DivideByZero(pc),
+        INVALID_OPCODE_VECTOR => E::IllegalInstruction(pc),
+        GENERAL_PROTECTION_FAULT_VECTOR => E::GeneralProtection(pc),
+        X87_FPU_VECTOR | SIMD_FLOATING_POINT_VECTOR => E::FloatingPoint(pc),
+        ALIGNMENT_CHECK_VECTOR => E::Misaligned(pc),
+        BREAKPOINT_VECTOR => E::Breakpoint(pc),
+        DEBUG_VECTOR => E::SingleStep(pc),
+        // Other exceptions are reported as `SIGSEGV`, like general protection
+        // faults.
+        _ => E::GeneralProtection(pc),
+    };
+    if !handle_trap!(USER_EXCEPTION, tf, exception) {
+        panic!(
+            "Unhandled user exception {:?} ({}, error_code={:#x}) @ {:#x}:\n{:#x?}",
+            exception,
+            vec_to_str(tf.vector),
+            tf.error_code,
+            tf.rip,
+            tf
+        );
+    }
+}
+
 #[unsafe(no_mangle)]
 fn x86_trap_handler(tf: &mut TrapFrame) {
     #[cfg(feature = "uspace")]
@@ -39,6 +81,17 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
     }
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
+        // Exceptions raised by user programs, except those of the machine.
+        #[cfg(feature = "uspace")]
+        0..IRQ_VECTOR_START
+            if tf.is_user()
+                && !matches!(
+                    tf.vector as u8,
+                    NONMASKABLE_INTERRUPT_VECTOR | DOUBLE_FAULT_VECTOR | MACHINE_CHECK_VECTOR
+                ) =>
+        {
+            handle_user_exception(tf)
+        }
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
         GENERAL_PROTECTION_FAULT_VECTOR => {
             let instr = unsafe { *(tf.rip as *const u64) };
@@ -82,7 +135,8 @@ fn vec_to_str(vec: u64) -> &'static str {
 
 fn err_code_to_flags(err_code: u64) -> Result<MappingFlags, u64> {
     let code = PageFaultErrorCode::from_bits_truncate(err_code);
-    let reserved_bits = (PageFaultErrorCode::CAUSED_BY_WRITE
+    let reserved_bits = (PageFaultErrorCode::PROTECTION_VIOLATION
+        | PageFaultErrorCode::CAUSED_BY_WRITE
         | PageFaultErrorCode::USER_MODE
         | PageFaultErrorCode::INSTRUCTION_FETCH)
         .complement();
@@ -5,6 +5,8 @@ use handler_table::HandlerTable;
 use crate::platform::irq::{MAX_IRQ_COUNT, dispatch_irq};
 use crate::trap::{IRQ, register_trap_handler};
 
+#[cfg(feature = "smp")]
+pub use crate::platform::irq::{IPI_IRQ_NUM, send_ipi};
 pub use crate::platform::irq::{register_handler, set_enable};
 
 /// The type if an IRQ handler.
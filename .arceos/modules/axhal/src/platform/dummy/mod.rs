@@ -69,6 +69,10 @@ pub mod irq {
     /// The timer IRQ number.
     pub const TIMER_IRQ_NUM: usize = 0;
 
+    /// The inter-processor interrupt number.
+    #[cfg(feature = "smp")]
+    pub const IPI_IRQ_NUM: usize = 1;
+
     /// Enables or disables the given IRQ.
     pub fn set_enable(irq_num: usize, enabled: bool) {}
 
@@ -83,6 +87,10 @@ pub mod irq {
     /// up in the IRQ handler table and calls the corresponding handler. If
     /// necessary, it also acknowledges the interrupt controller after handling.
     pub fn dispatch_irq(irq_num: usize) {}
+
+    /// Sends an inter-processor interrupt to the CPU `cpu_id`.
+    #[cfg(feature = "smp")]
+    pub fn send_ipi(cpu_id: usize) {}
 }
 
 /// Initializes the platform devices for the primary CPU.
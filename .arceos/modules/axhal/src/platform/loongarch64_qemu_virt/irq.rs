@@ -4,21 +4,40 @@ use loongArch64::register::{
 };
 
 /// The maximum number of IRQs.
-pub const MAX_IRQ_COUNT: usize = 12;
+pub const MAX_IRQ_COUNT: usize = 13;
 
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = estat::Interrupt::Timer as usize;
 
+/// The inter-processor interrupt number.
+#[cfg(feature = "smp")]
+pub const IPI_IRQ_NUM: usize = estat::Interrupt::IPI as usize;
+
+/// The IPI action bit of the IPIs sent by `send_ipi`.
+#[cfg(feature = "smp")]
+const IPI_ACTION: u32 = 2;
+
+#[cfg(feature = "smp")]
+const IOCSR_IPI_STATUS: usize = 0x1000;
+#[cfg(feature = "smp")]
+const IOCSR_IPI_EN: usize = 0x1004;
+#[cfg(feature = "smp")]
+const IOCSR_IPI_CLEAR: usize = 0x100c;
+
 /// Enables or disables the given IRQ.
 pub fn set_enable(irq_num: usize, enabled: bool) {
-    if irq_num == TIMER_IRQ_NUM {
-        let old_value = ecfg::read().lie();
-        let new_value = match enabled {
-            true => old_value | LineBasedInterrupt::TIMER,
-            false => old_value & !LineBasedInterrupt::TIMER,
-        };
-        ecfg::set_lie(new_value);
-    }
+    let line = match irq_num {
+        TIMER_IRQ_NUM => LineBasedInterrupt::TIMER,
+        #[cfg(feature = "smp")]
+        IPI_IRQ_NUM => LineBasedInterrupt::IPI,
+        _ => return,
+    };
+    let old_value = ecfg::read().lie();
+    let new_value = match enabled {
+        true => old_value | line,
+        false => old_value & !line,
+    };
+    ecfg::set_lie(new_value);
 }
 
 /// Registers an IRQ handler for the given IRQ.
@@ -35,5 +54,36 @@ pub fn dispatch_irq(irq_num: usize) {
     if irq_num == TIMER_IRQ_NUM {
         ticlr::clear_timer_interrupt();
     }
+    #[cfg(feature = "smp")]
+    if irq_num == IPI_IRQ_NUM {
+        unsafe {
+            core::arch::asm!(
+                "iocsrrd.w {status}, {status_addr}",
+                "iocsrwr.w {status}, {clear_addr}",
+                status = out(reg) _,
+                status_addr = in(reg) IOCSR_IPI_STATUS,
+                clear_addr = in(reg) IOCSR_IPI_CLEAR,
+            );
+        }
+    }
     crate::irq::dispatch_irq_common(irq_num)
 }
+
+/// Sends an inter-processor interrupt to the CPU `cpu_id`.
+#[cfg(feature = "smp")]
+pub fn send_ipi(cpu_id: usize) {
+    loongArch64::ipi::send_ipi_single(cpu_id, IPI_ACTION);
+}
+
+/// Enables the inter-processor interrupts on the current CPU.
+#[cfg(feature = "smp")]
+pub(super) fn init_percpu() {
+    unsafe {
+        core::arch::asm!(
+            "iocsrwr.w {mask}, {en_addr}",
+            mask = in(reg) 1usize << IPI_ACTION,
+            en_addr = in(reg) IOCSR_IPI_EN,
+        );
+    }
+    set_enable(IPI_IRQ_NUM, true);
+}
@@ -31,6 +31,8 @@ unsafe extern "C" fn rust_entry(cpu_id: usize) {
     crate::cpu::init_primary(cpu_id);
     super::time::init_primary();
     super::time::init_percpu();
+    #[cfg(all(feature = "irq", feature = "smp"))]
+    super::irq::init_percpu();
 
     unsafe {
         rust_main(cpu_id, 0);
@@ -42,6 +44,8 @@ unsafe extern "C" fn rust_entry(cpu_id: usize) {
 pub(crate) extern "C" fn rust_entry_secondary(cpu_id: usize) {
     crate::cpu::init_secondary(cpu_id);
     super::time::init_percpu();
+    #[cfg(feature = "irq")]
+    super::irq::init_percpu();
 
     unsafe {
         rust_main_secondary(cpu_id);
@@ -0,0 +1,134 @@
+//! TLB maintenance across CPUs.
+//!
+//! Each CPU records the page table it runs on right before switching to it,
+//! and switching page tables flushes the local TLB. So only the CPUs whose
+//! current page table is the one modified may hold stale translations of it,
+//! and a TLB shootdown only interrupts those CPUs, with an IPI.
+
+use memory_addr::{PAGE_SIZE_4K, PhysAddr, VirtAddr};
+
+/// Ranges larger than this are flushed entirely instead of page by page.
+const FLUSH_ALL_THRESHOLD: usize = 32 * PAGE_SIZE_4K;
+
+/// Flushes the local TLB entries of `size` bytes from `start`.
+fn flush_local(start: VirtAddr, size: usize) {
+    if size > FLUSH_ALL_THRESHOLD {
+        crate::arch::flush_tlb(None);
+    } else {
+        for vaddr in (start.as_usize()..start.as_usize() + size).step_by(PAGE_SIZE_4K) {
+            crate::arch::flush_tlb(Some(vaddr.into()));
+        }
+    }
+}
+
+/// Records that the current CPU is about to switch to the page table `root`.
+///
+/// It must be called before the page table is loaded, with IRQs disabled.
+#[allow(unused_variables)]
+pub(crate) fn set_active_page_table(root: PhysAddr) {
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    shootdown::set_active_page_table(root);
+}
+
+/// Flushes the TLB entries of `size` bytes from `start` in the page table
+/// `root`, on the current CPU and all other CPUs running on it.
+///
+/// It must be called after the page table has been modified, and returns
+/// after all CPUs have flushed.
+pub fn flush_tlb_range(root: PhysAddr, start: VirtAddr, size: usize) {
+    flush_local(start, size);
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    shootdown::flush_others(root, start, size);
+    #[cfg(not(all(feature = "smp", feature = "irq")))]
+    let _ = root;
+}
+
+/// Handles the TLB shootdown IPI.
+#[cfg(all(feature = "smp", feature = "irq"))]
+pub fn handle_ipi() {
+    shootdown::handle_pending();
+}
+
+#[cfg(all(feature = "smp", feature = "irq"))]
+mod shootdown {
+    use core::hint::spin_loop;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering, fence};
+
+    use axconfig::SMP;
+    use memory_addr::{PhysAddr, VirtAddr};
+
+    use crate::cpu::this_cpu_id;
+
+    /// The page table each CPU runs on.
+    static ACTIVE_ROOTS: [AtomicUsize; SMP] = [const { AtomicUsize::new(0) }; SMP];
+    /// Whether each CPU has yet to handle the current request.
+    static PENDING: [AtomicBool; SMP] = [const { AtomicBool::new(false) }; SMP];
+    /// Held while a request is in flight, there is one at a time.
+    static LOCK: AtomicBool = AtomicBool::new(false);
+
+    static REQUEST_ROOT: AtomicUsize = AtomicUsize::new(0);
+    static REQUEST_START: AtomicUsize = AtomicUsize::new(0);
+    static REQUEST_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+    pub(super) fn set_active_page_table(root: PhysAddr) {
+        ACTIVE_ROOTS[this_cpu_id()].store(root.as_usize(), Ordering::SeqCst);
+        // Page walks on `root` must not start before a shooter can see it.
+        fence(Ordering::SeqCst);
+    }
+
+    /// Handles the request to the current CPU, if any.
+    pub(super) fn handle_pending() {
+        let cpu_id = this_cpu_id();
+        if !PENDING[cpu_id].load(Ordering::Acquire) {
+            return;
+        }
+        // A CPU that has switched page tables since has nothing to flush.
+        if ACTIVE_ROOTS[cpu_id].load(Ordering::Relaxed) == REQUEST_ROOT.load(Ordering::Relaxed) {
+            super::flush_local(
+                REQUEST_START.load(Ordering::Relaxed).into(),
+                REQUEST_SIZE.load(Ordering::Relaxed),
+            );
+        }
+        PENDING[cpu_id].store(false, Ordering::Release);
+    }
+
+    pub(super) fn flush_others(root: PhysAddr, start: VirtAddr, size: usize) {
+        let _guard = kernel_guard::NoPreempt::new();
+        let this_cpu = this_cpu_id();
+        // The page table updates must be visible before the CPUs are chosen,
+        // see `set_active_page_table`.
+        fence(Ordering::SeqCst);
+        let mut targets = [false; SMP];
+        for (cpu_id, target) in targets.iter_mut().enumerate() {
+            *target = cpu_id != this_cpu
+                && ACTIVE_ROOTS[cpu_id].load(Ordering::SeqCst) == root.as_usize();
+        }
+        if !targets.contains(&true) {
+            return;
+        }
+
+        // Requests to this CPU are handled while waiting, as IRQs may be
+        // disabled, otherwise two CPUs shooting each other would deadlock.
+        while LOCK
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            handle_pending();
+            spin_loop();
+        }
+        REQUEST_ROOT.store(root.as_usize(), Ordering::Relaxed);
+        REQUEST_START.store(start.as_usize(), Ordering::Relaxed);
+        REQUEST_SIZE.store(size, Ordering::Relaxed);
+        for cpu_id in (0..SMP).filter(|&cpu_id| targets[cpu_id]) {
+            PENDING[cpu_id].store(true, Ordering::Release);
+            crate::irq::send_ipi(cpu_id);
+        }
+        for cpu_id in (0..SMP).filter(|&cpu_id| targets[cpu_id]) {
+            while PENDING[cpu_id].load(Ordering::Acquire) {
+                handle_pending();
+                spin_loop();
+            }
+        }
+        LOCK.store(false, Ordering::Release);
+    }
+}
@@ -16,11 +16,58 @@ pub static IRQ: [fn(usize) -> bool];
 #[def_trap_handler]
 pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 
+/// A slice of handlers of page faults in kernel mode which are neither handled
+/// by the [`PAGE_FAULT`] handler nor fixed up by the exception table, e.g. when
+/// the kernel dereferences a user pointer whose memory is unmapped by another
+/// thread meanwhile.
+///
+/// The handler returns `true` if it has handled the fault, e.g. by killing the
+/// current task, otherwise the kernel panics.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static UNFIXED_USER_FAULT: [fn(VirtAddr, MappingFlags) -> bool];
+
 /// A slice of syscall handler functions.
 #[cfg(feature = "uspace")]
 #[def_trap_handler]
 pub static SYSCALL: [fn(&mut TrapFrame, usize) -> isize];
 
+/// Synchronous exceptions raised by user programs, other than page faults and
+/// syscalls.
+///
+/// The address carried by each variant is the faulting address if the
+/// hardware reports one, otherwise the address of the faulting instruction.
+#[cfg(feature = "uspace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserException {
+    /// Illegal or undefined instruction.
+    IllegalInstruction(VirtAddr),
+    /// Privileged instruction.
+    PrivilegedInstruction(VirtAddr),
+    /// General protection fault (x86_64).
+    GeneralProtection(VirtAddr),
+    /// Integer division by zero.
+    DivideByZero(VirtAddr),
+    /// Floating-point exception.
+    FloatingPoint(VirtAddr),
+    /// Misaligned memory access or instruction fetch.
+    Misaligned(VirtAddr),
+    /// Memory access fault which is not a page fault.
+    AccessFault(VirtAddr),
+    /// Breakpoint instruction.
+    Breakpoint(VirtAddr),
+    /// Single-step trap after the instruction before the address.
+    SingleStep(VirtAddr),
+}
+
+/// A slice of user exception handler functions.
+///
+/// The handler returns `true` if the exception is handled, e.g. a signal is
+/// sent to the current thread.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static USER_EXCEPTION: [fn(&mut TrapFrame, UserException) -> bool];
+
 /// A slice of callbacks to be invoked after a trap.
 #[linkme::distributed_slice]
 pub static POST_TRAP: [fn(&mut TrapFrame, bool)];
@@ -0,0 +1,63 @@
+//! Fault-tolerant access to user memory.
+//!
+//! The instructions which access user memory in [`user_copy`] and
+//! [`probe_user_write`] are recorded in the exception table, along with the
+//! fixup code to resume at. Page faults at these instructions are first passed
+//! to the registered [`PAGE_FAULT`](crate::trap::PAGE_FAULT) handler, so pages
+//! are faulted in as if accessed from user space. If the handler fails, the
+//! trap handler resumes at the fixup code instead of panicking, and the
+//! failure is returned to the caller.
+
+/// An entry of the exception table.
+#[repr(C)]
+struct ExceptionEntry {
+    /// The address of the instruction which may fault.
+    insn: usize,
+    /// The address to resume at when the instruction faults.
+    fixup: usize,
+}
+
+unsafe extern "C" {
+    fn _sex_table();
+    fn _eex_table();
+
+    fn __user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    fn __user_probe_write(addr: *mut u8) -> usize;
+}
+
+/// Returns the fixup address for a fault at `pc`, if the instruction at `pc`
+/// is in the exception table.
+pub(crate) fn fixup_exception(pc: usize) -> Option<usize> {
+    let start = _sex_table as usize as *const ExceptionEntry;
+    let len = (_eex_table as usize - _sex_table as usize) / size_of::<ExceptionEntry>();
+    let table = unsafe { core::slice::from_raw_parts(start, len) };
+    table
+        .iter()
+        .find(|entry| entry.insn == pc)
+        .map(|entry| entry.fixup)
+}
+
+/// Copies `len` bytes from `src` to `dst`, where either of them may be in user
+/// space.
+///
+/// Returns the number of bytes which are not copied because of a page fault
+/// which cannot be handled, or 0 if all bytes are copied.
+///
+/// # Safety
+///
+/// The kernel memory of `src` or `dst` must be valid for `len` bytes.
+pub unsafe fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    unsafe { __user_copy(dst, src, len) }
+}
+
+/// Faults in the byte at `addr` in user space for writing, without changing
+/// its value even if other threads write it at the same time.
+///
+/// Returns whether the byte can be written. `addr` must be aligned to 4 bytes.
+///
+/// # Safety
+///
+/// `addr` must be in user space.
+pub unsafe fn probe_user_write(addr: *mut u8) -> bool {
+    unsafe { __user_probe_write(addr) == 0 }
+}
@@ -1,21 +1,47 @@
 use core::fmt;
 
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use axerrno::{AxError, AxResult, ax_err};
 use axhal::mem::phys_to_virt;
-use axhal::paging::{MappingFlags, PageTable, PagingError};
+use axhal::paging::{MappingFlags, PageSize, PageTable, PagingError};
 use memory_addr::{
-    MemoryAddr, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, is_aligned_4k,
+    MemoryAddr, PAGE_SIZE_2M, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange,
+    is_aligned, is_aligned_4k,
 };
 use memory_set::{MemoryArea, MemorySet};
 
-use crate::backend::Backend;
+use crate::backend::{Backend, MmapFile, copy_huge_page, is_zero_frame, split_huge_page};
+use crate::frameinfo::add_frame_ref;
 use crate::mapping_err_to_ax_err;
+use crate::rangeset::RangeSet;
+
+/// The minimum gap between a stack growing down and the mapping below it.
+const STACK_GUARD_GAP: usize = 256 * PAGE_SIZE_4K;
+
+/// The number of free pages below which transparent huge pages are not used,
+/// a sixteenth of the memory.
+const THP_MIN_FREE_PAGES: usize = axconfig::plat::PHYS_MEMORY_SIZE / PAGE_SIZE_4K / 16;
 
 /// The virtual memory address space.
 pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    /// Ranges not inherited by the child on fork.
+    dontfork: RangeSet,
+    /// Stacks which grow down automatically on page faults.
+    growsdown: RangeSet,
+    /// Ranges not backed by transparent huge pages, set by `MADV_NOHUGEPAGE`.
+    nohugepage: RangeSet,
+    /// Ranges locked in memory by `mlock`.
+    locked: RangeSet,
+    /// Whether new mappings are locked, set by `mlockall(MCL_FUTURE)`.
+    lock_future: bool,
+    /// Whether the pages of new locked mappings are populated at once, unless
+    /// `mlockall(MCL_ONFAULT)` is used.
+    populate_future: bool,
 }
 
 impl AddrSpace {
@@ -56,6 +82,12 @@ impl AddrSpace {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            dontfork: RangeSet::new(),
+            growsdown: RangeSet::new(),
+            nohugepage: RangeSet::new(),
+            locked: RangeSet::new(),
+            lock_future: false,
+            populate_future: false,
         })
     }
 
@@ -134,6 +166,7 @@ impl AddrSpace {
         self.areas
             .map(area, &mut self.pt, false)
             .map_err(mapping_err_to_ax_err)?;
+        self.lock_new_mapping(start_vaddr, size);
         Ok(())
     }
 
@@ -158,31 +191,404 @@ impl AddrSpace {
         self.areas
             .map(area, &mut self.pt, false)
             .map_err(mapping_err_to_ax_err)?;
+        self.lock_new_mapping(start, size);
+        Ok(())
+    }
+
+    /// Add a new allocation mapping backed by 2M huge pages, for
+    /// `MAP_HUGETLB`.
+    ///
+    /// The mapping can only be unmapped, protected or moved in whole huge
+    /// pages.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned to 2M.
+    pub fn map_huge_alloc(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        populate: bool,
+    ) -> AxResult {
+        self.validate_region(start, size)?;
+        if !start.is_aligned(PAGE_SIZE_2M) || !is_aligned(size, PAGE_SIZE_2M) {
+            return ax_err!(InvalidInput, "address not aligned to huge pages");
+        }
+
+        let area = MemoryArea::new(start, size, flags, Backend::new_huge_alloc(populate));
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        self.lock_new_mapping(start, size);
+        Ok(())
+    }
+
+    /// Checks that the boundaries of the specified range do not split the
+    /// huge pages of the mappings added by [`AddrSpace::map_huge_alloc`].
+    fn check_huge_split(&self, start: VirtAddr, size: usize) -> AxResult {
+        for addr in [start, start + size] {
+            if !addr.is_aligned(PAGE_SIZE_2M) && self.is_hugetlb(addr) {
+                return ax_err!(InvalidInput, "address not aligned to huge pages");
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether a transparent huge page can be mapped for the fault at
+    /// `vaddr` in `area`.
+    ///
+    /// The 2M range around `vaddr` must be within a lazy allocation mapping
+    /// and not mapped yet, not even by the shared zero frame, as the page
+    /// table of the range is only emptied, not freed, by unmapping its pages.
+    /// Stacks and the ranges marked by `MADV_NOHUGEPAGE` are excluded, and 4K
+    /// pages are used when the memory is low.
+    fn thp_allowed(&self, area: &MemoryArea<Backend>, vaddr: VirtAddr) -> bool {
+        if !matches!(
+            area.backend(),
+            Backend::Alloc {
+                populate: false,
+                huge: false
+            }
+        ) {
+            return false;
+        }
+        let range = VirtAddrRange::from_start_size(vaddr.align_down(PAGE_SIZE_2M), PAGE_SIZE_2M);
+        range.start >= area.start()
+            && range.end <= area.end()
+            && self.growsdown.overlapping(range).is_empty()
+            && self.nohugepage.overlapping(range).is_empty()
+            && PageIter4K::new(range.start, range.end)
+                .unwrap()
+                .all(|addr| self.pt.query(addr).is_err())
+            && axalloc::global_allocator().available_pages() >= THP_MIN_FREE_PAGES
+    }
+
+    /// Add a new file mapping.
+    ///
+    /// See [`Backend`] for more details about the mapping backends.
+    ///
+    /// `start` is mapped to the file at `offset`, and the pages are read from
+    /// the file on demand. If `shared` is `true`, the modifications are written
+    /// back to the file.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
+    pub fn map_file(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        file: Arc<dyn MmapFile>,
+        offset: usize,
+        shared: bool,
+    ) -> AxResult {
+        self.validate_region(start, size)?;
+        if !is_aligned_4k(offset) {
+            return ax_err!(InvalidInput, "offset not aligned");
+        }
+
+        let area = MemoryArea::new(
+            start,
+            size,
+            flags,
+            Backend::new_file(start, file, offset, shared),
+        );
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        self.lock_new_mapping(start, size);
+        Ok(())
+    }
+
+    /// Writes the modified pages of shared file mappings within the specified
+    /// range back to the files.
+    ///
+    /// Returns an error if the range contains unmapped area.
+    pub fn sync(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        self.validate_region(start, size)?;
+        let end = start + size;
+
+        let mut addr = start;
+        while addr < end {
+            let Some(area) = self.areas.find(addr) else {
+                return ax_err!(NoMemory);
+            };
+            let area_end = area.end().min(end);
+            area.backend().sync(addr, area_end - addr, &mut self.pt)?;
+            addr = area_end;
+        }
+        Ok(())
+    }
+
+    /// Writes the modified pages of all shared file mappings back to the files.
+    pub fn sync_all(&mut self) -> AxResult {
+        for area in self.areas.iter() {
+            area.backend()
+                .sync(area.start(), area.size(), &mut self.pt)?;
+        }
+        Ok(())
+    }
+
+    /// Expands the mapping ending at `start + old_size` to `start + new_size`
+    /// in place.
+    ///
+    /// The new part has the same permissions and backend as the mapping it
+    /// extends, and its pages are mapped on demand.
+    ///
+    /// Returns an error if `start + old_size` is not the end of a mapping, or
+    /// the range to expand into is not free. Linear mappings cannot be
+    /// expanded.
+    pub fn expand(&mut self, start: VirtAddr, old_size: usize, new_size: usize) -> AxResult {
+        self.validate_region(start, new_size)?;
+        if old_size == 0 || old_size >= new_size || !is_aligned_4k(old_size) {
+            return ax_err!(InvalidInput);
+        }
+        let old_end = start + old_size;
+        let Some(area) = self.areas.find(old_end - PAGE_SIZE_4K) else {
+            return ax_err!(BadAddress);
+        };
+        if area.end() != old_end {
+            return ax_err!(AlreadyExists, "address already mapped");
+        }
+        let (flags, backend) = (area.flags(), area.backend().clone());
+        // Linear mappings would be extended over the physical memory beyond
+        // their end, which is not theirs.
+        if matches!(backend, Backend::Linear { .. }) {
+            return ax_err!(Unsupported, "mapping cannot be expanded");
+        }
+        if matches!(backend, Backend::Alloc { huge: true, .. })
+            && !(start + new_size).is_aligned(PAGE_SIZE_2M)
+        {
+            return ax_err!(InvalidInput, "size not aligned to huge pages");
+        }
+        if self
+            .areas
+            .overlaps(VirtAddrRange::new(old_end, start + new_size))
+        {
+            return ax_err!(AlreadyExists, "address already mapped");
+        }
+
+        let area = MemoryArea::new(old_end, new_size - old_size, flags, backend);
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        let new_range = VirtAddrRange::new(old_end, start + new_size);
+        if self.dontfork.contains(old_end - PAGE_SIZE_4K) {
+            self.dontfork.insert(new_range);
+        }
+        if self.nohugepage.contains(old_end - PAGE_SIZE_4K) {
+            self.nohugepage.insert(new_range);
+        }
+        if self.locked.contains(old_end - PAGE_SIZE_4K) {
+            self.locked.insert(new_range);
+            let _ = self.populate_area(new_range.start, new_range.size());
+        } else {
+            self.lock_new_mapping(new_range.start, new_range.size());
+        }
+        Ok(())
+    }
+
+    /// Checks whether the mappings in the range `[old_start, old_start + size)`
+    /// can be moved to `new_start` by [`AddrSpace::move_region`], once the
+    /// destination range is free.
+    ///
+    /// Nothing is changed, so that the callers can check the move before
+    /// unmapping the destination range.
+    pub fn check_move_region(
+        &self,
+        old_start: VirtAddr,
+        size: usize,
+        new_start: VirtAddr,
+    ) -> AxResult {
+        self.validate_region(old_start, size)?;
+        self.validate_region(new_start, size)?;
+        if VirtAddrRange::from_start_size(new_start, size)
+            .overlaps(VirtAddrRange::from_start_size(old_start, size))
+        {
+            return ax_err!(InvalidInput, "overlapping ranges");
+        }
+        // Huge page mappings can only be moved in whole huge pages.
+        let huge_aligned = old_start.is_aligned(PAGE_SIZE_2M)
+            && new_start.is_aligned(PAGE_SIZE_2M)
+            && is_aligned(size, PAGE_SIZE_2M);
+        if !huge_aligned
+            && self.areas.iter().any(|area| {
+                matches!(area.backend(), Backend::Alloc { huge: true, .. })
+                    && VirtAddrRange::new(area.start(), area.end())
+                        .overlaps(VirtAddrRange::from_start_size(old_start, size))
+            })
+        {
+            return ax_err!(InvalidInput, "address not aligned to huge pages");
+        }
+        let end = old_start + size;
+        let mut addr = old_start;
+        while addr < end {
+            let Some(area) = self.areas.find(addr) else {
+                return ax_err!(BadAddress);
+            };
+            if area.backend().relocate(old_start, new_start).is_none() {
+                return ax_err!(Unsupported, "mapping cannot be moved");
+            }
+            addr = area.end().min(end);
+        }
+        Ok(())
+    }
+
+    /// Moves the mappings in the range `[old_start, old_start + size)` to
+    /// `new_start`.
+    ///
+    /// The mapped pages are moved by updating the page table entries, their
+    /// contents are not copied. The destination range must be free and must
+    /// not overlap with the source range.
+    ///
+    /// Returns an error if the source range contains unmapped area.
+    pub fn move_region(
+        &mut self,
+        old_start: VirtAddr,
+        size: usize,
+        new_start: VirtAddr,
+    ) -> AxResult {
+        self.check_move_region(old_start, size, new_start)?;
+        if self
+            .areas
+            .overlaps(VirtAddrRange::from_start_size(new_start, size))
+        {
+            return ax_err!(AlreadyExists, "address already mapped");
+        }
+
+        let end = old_start + size;
+        let mut pieces = Vec::new();
+        let mut addr = old_start;
+        while addr < end {
+            let Some(area) = self.areas.find(addr) else {
+                return ax_err!(BadAddress);
+            };
+            let piece_end = area.end().min(end);
+            let Some(backend) = area.backend().relocate(old_start, new_start) else {
+                return ax_err!(Unsupported, "mapping cannot be moved");
+            };
+            pieces.push((addr, piece_end - addr, area.flags(), backend));
+            addr = piece_end;
+        }
+
+        for (start, size, flags, backend) in pieces {
+            let dst = new_start + (start - old_start);
+            let area = MemoryArea::new(dst, size, flags, backend);
+            self.areas
+                .map(area, &mut self.pt, false)
+                .map_err(mapping_err_to_ax_err)?;
+
+            let mut offset = 0;
+            while offset < size {
+                let (src, dst) = (start + offset, dst + offset);
+                let (frame, pte_flags, page_size) = match self.pt.query(src) {
+                    Ok(entry) => entry,
+                    Err(PagingError::NotMapped) => {
+                        offset += PAGE_SIZE_4K;
+                        continue;
+                    }
+                    Err(_) => return ax_err!(BadAddress),
+                };
+                let page_size = if page_size.is_huge()
+                    && !(src.is_aligned(page_size as usize)
+                        && dst.is_aligned(page_size as usize)
+                        && size - offset >= page_size as usize)
+                {
+                    // Transparent huge pages which cannot be moved as a whole
+                    // are split.
+                    if !split_huge_page(src, &mut self.pt) {
+                        return ax_err!(BadState);
+                    }
+                    PageSize::Size4K
+                } else {
+                    page_size
+                };
+                let frame = frame.align_down(page_size as usize);
+                self.pt
+                    .unmap(src)
+                    .map_err(|_| AxError::BadState)?
+                    .2
+                    .ignore();
+                self.pt
+                    .map(dst, frame, page_size, pte_flags)
+                    .map_err(|_| AxError::NoMemory)?
+                    .ignore();
+                offset += page_size as usize;
+            }
+            // No pages are left in the source range, so nothing is freed here,
+            // and the TLB entries of the source range are flushed on the way.
+            self.areas
+                .unmap(start, size, &mut self.pt)
+                .map_err(mapping_err_to_ax_err)?;
+        }
+
+        let old_range = VirtAddrRange::from_start_size(old_start, size);
+        for set in [
+            &mut self.dontfork,
+            &mut self.growsdown,
+            &mut self.nohugepage,
+            &mut self.locked,
+        ] {
+            for range in set.overlapping(old_range) {
+                set.insert(VirtAddrRange::from_start_size(
+                    new_start + (range.start - old_start),
+                    range.size(),
+                ));
+            }
+            set.remove(old_range);
+        }
         Ok(())
     }
 
     /// Populates the area with physical frames, returning false if the area
     /// contains unmapped area.
+    ///
+    /// The pages are faulted in as if they were read, so that shared file
+    /// pages are not marked dirty. Pages wholly beyond the end of the file
+    /// of a file mapping are left unmapped.
     pub fn populate_area(&mut self, mut start: VirtAddr, size: usize) -> AxResult {
         self.validate_region(start, size)?;
         let end = start + size;
 
         while let Some(area) = self.areas.find(start) {
             let backend = area.backend();
-            if let Backend::Alloc { populate } = backend {
-                if !*populate {
-                    for addr in PageIter4K::new(start, area.end().min(end)).unwrap() {
-                        match self.pt.query(addr) {
-                            Ok(_) => {}
-                            // If the page is not mapped, try map it.
-                            Err(PagingError::NotMapped) => {
-                                if !backend.handle_page_fault(addr, area.flags(), &mut self.pt) {
-                                    return Err(AxError::NoMemory);
+            if matches!(
+                backend,
+                Backend::Alloc {
+                    populate: false,
+                    ..
+                } | Backend::File { .. }
+            ) {
+                let access_flags = area.flags() & MappingFlags::READ;
+                for addr in PageIter4K::new(start, area.end().min(end)).unwrap() {
+                    match self.pt.query(addr) {
+                        Ok(_) => {}
+                        // If the page is not mapped, try map it.
+                        Err(PagingError::NotMapped) => {
+                            if let Backend::File {
+                                file, va_offset, ..
+                            } = backend
+                            {
+                                if Backend::beyond_file_end(addr, file, *va_offset) {
+                                    continue;
                                 }
                             }
-                            Err(_) => return Err(AxError::BadAddress),
-                        };
-                    }
+                            if self.thp_allowed(area, addr)
+                                && Backend::map_huge_page(addr, area.flags(), &mut self.pt)
+                            {
+                                continue;
+                            }
+                            if !backend.handle_page_fault(
+                                addr,
+                                area.flags(),
+                                access_flags,
+                                &mut self.pt,
+                            ) {
+                                return Err(AxError::NoMemory);
+                            }
+                        }
+                        Err(_) => return Err(AxError::BadAddress),
+                    };
                 }
             }
             start = area.end();
@@ -206,13 +612,447 @@ impl AddrSpace {
     /// aligned.
     pub fn unmap(&mut self, start: VirtAddr, size: usize) -> AxResult {
         self.validate_region(start, size)?;
+        self.check_huge_split(start, size)?;
 
         self.areas
             .unmap(start, size, &mut self.pt)
             .map_err(mapping_err_to_ax_err)?;
+        let range = VirtAddrRange::from_start_size(start, size);
+        self.dontfork.remove(range);
+        self.growsdown.remove(range);
+        self.nohugepage.remove(range);
+        self.locked.remove(range);
+        Ok(())
+    }
+
+    /// Drops the pages mapped in the specified range.
+    ///
+    /// The mappings are kept, and the pages are mapped again on the next
+    /// access: zero-filled for allocation mappings, or read from the file for
+    /// file mappings. Modified pages of shared file mappings are written back
+    /// before they are dropped.
+    ///
+    /// Returns an error if the range contains unmapped area, locked pages, or
+    /// mappings whose pages cannot be dropped.
+    pub fn discard(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        self.validate_region(start, size)?;
+        self.check_huge_split(start, size)?;
+        let end = start + size;
+        if !self
+            .locked
+            .overlapping(VirtAddrRange::new(start, end))
+            .is_empty()
+        {
+            return ax_err!(InvalidInput, "pages are locked");
+        }
+
+        let mut addr = start;
+        while addr < end {
+            let Some(area) = self.areas.find(addr) else {
+                return ax_err!(NoMemory);
+            };
+            let area_end = area.end().min(end);
+            if !area
+                .backend()
+                .discard(addr, area_end - addr, area.flags(), &mut self.pt)
+            {
+                return ax_err!(InvalidInput, "pages cannot be discarded");
+            }
+            addr = area_end;
+        }
+        Ok(())
+    }
+
+    /// Sets whether the mappings in the specified range are inherited by the
+    /// child address space in [`AddrSpace::clone_or_err`].
+    ///
+    /// Returns an error if the range contains unmapped area.
+    pub fn set_dontfork(&mut self, start: VirtAddr, size: usize, dontfork: bool) -> AxResult {
+        self.validate_region(start, size)?;
+        let range = VirtAddrRange::from_start_size(start, size);
+        if !self.check_region_access(range, MappingFlags::empty()) {
+            return ax_err!(NoMemory);
+        }
+        if dontfork {
+            self.dontfork.insert(range);
+        } else {
+            self.dontfork.remove(range);
+        }
+        Ok(())
+    }
+
+    /// Sets whether the mappings in the specified range may be backed by
+    /// transparent huge pages, for `MADV_HUGEPAGE` and `MADV_NOHUGEPAGE`.
+    ///
+    /// Returns an error if the range contains unmapped area.
+    pub fn set_hugepage(&mut self, start: VirtAddr, size: usize, enabled: bool) -> AxResult {
+        self.validate_region(start, size)?;
+        let range = VirtAddrRange::from_start_size(start, size);
+        if !self.check_region_access(range, MappingFlags::empty()) {
+            return ax_err!(NoMemory);
+        }
+        if enabled {
+            self.nohugepage.remove(range);
+        } else {
+            self.nohugepage.insert(range);
+        }
+        Ok(())
+    }
+
+    /// Marks the mappings in the specified range as a stack growing down.
+    ///
+    /// When an address below the stack is accessed, the stack may be expanded
+    /// to it by [`AddrSpace::grow_down`].
+    ///
+    /// Returns an error if the range contains unmapped area.
+    pub fn set_growsdown(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        self.validate_region(start, size)?;
+        let range = VirtAddrRange::from_start_size(start, size);
+        if !self.check_region_access(range, MappingFlags::empty()) {
+            return ax_err!(NoMemory);
+        }
+        self.growsdown.insert(range);
+        Ok(())
+    }
+
+    /// Returns the parts of the range `[start, start + size)` that are still
+    /// mapped by [`AddrSpace::map_linear`] to the physical memory at `paddr`
+    /// for `start`, after the rest is unmapped or replaced.
+    pub fn linear_ranges(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        paddr: PhysAddr,
+    ) -> Vec<VirtAddrRange> {
+        let offset = start.as_usize().wrapping_sub(paddr.as_usize());
+        let range = VirtAddrRange::from_start_size(start, size);
+        self.areas
+            .iter()
+            .filter(|area| {
+                matches!(area.backend(), Backend::Linear { pa_va_offset } if *pa_va_offset == offset)
+            })
+            .filter_map(|area| {
+                let start = area.start().max(range.start);
+                let end = area.end().min(range.end);
+                (start < end).then(|| VirtAddrRange::new(start, end))
+            })
+            .collect()
+    }
+
+    /// Returns whether `vaddr` is in a mapping added by
+    /// [`AddrSpace::map_huge_alloc`].
+    pub fn is_hugetlb(&self, vaddr: VirtAddr) -> bool {
+        self.areas
+            .find(vaddr)
+            .is_some_and(|area| matches!(area.backend(), Backend::Alloc { huge: true, .. }))
+    }
+
+    /// Returns the file and the offset of the page at `vaddr` if it is in a
+    /// file mapping and not mapped yet, so that the page can be read by
+    /// [`MmapFile::prefetch`] before the fault is handled.
+    pub fn unmapped_file_page(&self, vaddr: VirtAddr) -> Option<(Arc<dyn MmapFile>, u64)> {
+        self.areas
+            .find(vaddr)?
+            .backend()
+            .unmapped_file_page(vaddr, &self.pt)
+    }
+
+    /// Returns whether the specified range is wholly covered by private
+    /// anonymous mappings, which `MADV_FREE` is limited to. Mappings added by
+    /// [`AddrSpace::map_huge_alloc`] are excluded.
+    pub fn is_private_anonymous(&self, start: VirtAddr, size: usize) -> bool {
+        let end = start + size;
+        let mut addr = start;
+        while addr < end {
+            match self.areas.find(addr) {
+                Some(area) if matches!(area.backend(), Backend::Alloc { huge: false, .. }) => {
+                    addr = area.end();
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Returns whether `vaddr` is in a file mapping but wholly beyond the end
+    /// of the file, where accesses raise `SIGBUS` rather than `SIGSEGV`.
+    pub fn is_beyond_file_end(&self, vaddr: VirtAddr) -> bool {
+        self.areas
+            .find(vaddr)
+            .is_some_and(|area| match area.backend() {
+                Backend::File {
+                    file, va_offset, ..
+                } => Backend::beyond_file_end(vaddr, file, *va_offset),
+                _ => false,
+            })
+    }
+
+    /// Unmaps the pages of the shared file mappings which map any of `frames`,
+    /// e.g. the pages removed from a file by truncation, so that the next
+    /// accesses fault on the new end of the file.
+    pub fn unmap_file_frames(&mut self, frames: &BTreeSet<PhysAddr>) {
+        for area in self.areas.iter() {
+            if let Backend::File {
+                va_offset,
+                shared: true,
+                dirty,
+                ..
+            } = area.backend()
+            {
+                Backend::unmap_file_frames(
+                    area.start(),
+                    area.size(),
+                    &mut self.pt,
+                    frames,
+                    *va_offset,
+                    dirty,
+                );
+            }
+        }
+    }
+
+    /// Returns whether the page at `vaddr` is resident in memory, for
+    /// `mincore`. Pages mapping the shared zero frame are not, as nothing is
+    /// stored in them yet.
+    pub fn is_resident(&self, vaddr: VirtAddr) -> bool {
+        self.pt
+            .query(vaddr)
+            .is_ok_and(|(frame, ..)| !is_zero_frame(frame))
+    }
+
+    /// Returns the range of the stack growing down that contains `vaddr`.
+    pub fn find_growsdown(&self, vaddr: VirtAddr) -> Option<VirtAddrRange> {
+        self.growsdown.find(vaddr)
+    }
+
+    /// Expands the stack growing down right above `vaddr` to cover it.
+    ///
+    /// The stack is expanded only if its size does not exceed `stack_limit`,
+    /// and the total size of the mappings does not exceed `as_limit`. A guard
+    /// gap is kept from the mapping below. The new pages are mapped on demand.
+    ///
+    /// Returns `true` if the stack is expanded.
+    pub fn grow_down(&mut self, vaddr: VirtAddr, stack_limit: usize, as_limit: usize) -> bool {
+        if !self.va_range.contains(vaddr) || self.areas.find(vaddr).is_some() {
+            return false;
+        }
+        let start = vaddr.align_down_4k();
+        let Some(area) = self.areas.iter().find(|area| area.start() > vaddr) else {
+            return false;
+        };
+        let Some(stack) = self.growsdown.find(area.start()) else {
+            return false;
+        };
+        if stack.end - start > stack_limit || self.mapped_size() + (area.start() - start) > as_limit
+        {
+            return false;
+        }
+        // Keep a guard gap from the mapping below.
+        let prev_end = self
+            .areas
+            .iter()
+            .take_while(|area| area.end() <= start)
+            .last()
+            .map(|area| area.end());
+        if prev_end.is_some_and(|end| start - end < STACK_GUARD_GAP) {
+            return false;
+        }
+
+        let (old_start, old_size, flags) = (area.start(), area.size(), area.flags());
+        let backend = match area.backend() {
+            Backend::Alloc { .. } => Backend::new_alloc(false),
+            backend => backend.clone(),
+        };
+        let range = VirtAddrRange::new(start, old_start);
+        let locked = self.lock_future || self.locked.contains(old_start);
+
+        // The stack area is extended in place, rather than adding an area for
+        // every fault below it. Its old record is unmapped against an empty
+        // page table, so that the pages already mapped are kept.
+        let Ok(mut detached) = PageTable::try_new() else {
+            return false;
+        };
+        if self
+            .areas
+            .unmap(old_start, old_size, &mut detached)
+            .is_err()
+        {
+            return false;
+        }
+        let new_area = MemoryArea::new(start, old_start + old_size - start, flags, backend.clone());
+        if self.areas.map(new_area, &mut self.pt, false).is_err() {
+            let old_area = MemoryArea::new(old_start, old_size, flags, backend);
+            let _ = self.areas.map(old_area, &mut self.pt, false);
+            return false;
+        }
+        self.growsdown.insert(range);
+        // The pages are mapped on the fault anyway.
+        if locked {
+            self.locked.insert(range);
+        }
+        true
+    }
+
+    /// Returns the total size of the mappings in bytes, which is limited by
+    /// `RLIMIT_AS`.
+    pub fn mapped_size(&self) -> usize {
+        self.areas.iter().map(|area| area.size()).sum()
+    }
+
+    /// Returns whether the mapping at `vaddr` is a data mapping, i.e. private,
+    /// writable and not a stack, which is limited by `RLIMIT_DATA`.
+    pub fn is_data(&self, vaddr: VirtAddr) -> bool {
+        self.areas.find(vaddr).is_some_and(|area| {
+            area.flags().contains(MappingFlags::WRITE)
+                && matches!(
+                    area.backend(),
+                    Backend::Alloc { .. } | Backend::File { shared: false, .. }
+                )
+                && !self.growsdown.contains(vaddr)
+        })
+    }
+
+    /// Returns the size of the mappings in the specified range in bytes.
+    pub fn mapped_size_in(&self, start: VirtAddr, size: usize) -> usize {
+        let range = VirtAddrRange::from_start_size(start, size);
+        self.areas
+            .iter()
+            .map(|area| {
+                let start = area.start().max(range.start);
+                let end = area.end().min(range.end);
+                end.as_usize().saturating_sub(start.as_usize())
+            })
+            .sum()
+    }
+
+    /// Returns the total size of the data mappings in bytes (see
+    /// [`AddrSpace::is_data`]).
+    pub fn data_size(&self) -> usize {
+        self.data_size_in(self.base(), self.size())
+    }
+
+    /// Returns the size of the data mappings in the specified range in bytes.
+    pub fn data_size_in(&self, start: VirtAddr, size: usize) -> usize {
+        let range = VirtAddrRange::from_start_size(start, size);
+        self.areas
+            .iter()
+            .filter(|area| {
+                area.flags().contains(MappingFlags::WRITE)
+                    && matches!(
+                        area.backend(),
+                        Backend::Alloc { .. } | Backend::File { shared: false, .. }
+                    )
+            })
+            .map(|area| {
+                let start = area.start().max(range.start);
+                let end = area.end().min(range.end);
+                if start >= end {
+                    return 0;
+                }
+                self.growsdown
+                    .subtract(VirtAddrRange::new(start, end))
+                    .iter()
+                    .map(|range| range.size())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Returns the total size of the locked ranges in bytes, which is limited
+    /// by `RLIMIT_MEMLOCK`.
+    pub fn locked_size(&self) -> usize {
+        self.locked.size()
+    }
+
+    /// Returns the size of the part of the specified range that is not locked
+    /// yet.
+    pub fn unlocked_size(&self, start: VirtAddr, size: usize) -> usize {
+        self.locked
+            .subtract(VirtAddrRange::from_start_size(start, size))
+            .iter()
+            .map(|range| range.size())
+            .sum()
+    }
+
+    /// Returns whether new mappings are locked, see
+    /// [`AddrSpace::set_lock_future`].
+    pub fn lock_future(&self) -> bool {
+        self.lock_future
+    }
+
+    /// Locks or unlocks the mappings in the specified range, for `mlock` and
+    /// `munlock`.
+    ///
+    /// There is no swap, so locking only populates the pages if `populate` is
+    /// `true`, and keeps them from being discarded.
+    ///
+    /// Returns an error if the range contains unmapped area.
+    pub fn set_locked(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        locked: bool,
+        populate: bool,
+    ) -> AxResult {
+        self.validate_region(start, size)?;
+        let range = VirtAddrRange::from_start_size(start, size);
+        if !self.check_region_access(range, MappingFlags::empty()) {
+            return ax_err!(NoMemory);
+        }
+        if locked {
+            self.locked.insert(range);
+            if populate {
+                // Like Linux, pages which cannot be populated, e.g. beyond the
+                // end of a file, are left to the faults.
+                let _ = self.populate_area(start, size);
+            }
+        } else {
+            self.locked.remove(range);
+        }
         Ok(())
     }
 
+    /// Locks all current mappings, for `mlockall(MCL_CURRENT)`.
+    pub fn lock_all(&mut self, populate: bool) {
+        let ranges: Vec<_> = self
+            .areas
+            .iter()
+            .map(|area| VirtAddrRange::new(area.start(), area.end()))
+            .collect();
+        for range in ranges {
+            self.locked.insert(range);
+            if populate {
+                let _ = self.populate_area(range.start, range.size());
+            }
+        }
+    }
+
+    /// Sets whether new mappings are locked, for `mlockall(MCL_FUTURE)`, and
+    /// whether their pages are populated at once.
+    pub fn set_lock_future(&mut self, lock: bool, populate: bool) {
+        self.lock_future = lock;
+        self.populate_future = populate;
+    }
+
+    /// Unlocks all mappings and stops locking new ones, for `munlockall`.
+    pub fn unlock_all(&mut self) {
+        self.locked.clear();
+        self.lock_future = false;
+        self.populate_future = false;
+    }
+
+    /// Locks the new mapping in the specified range if
+    /// [`AddrSpace::set_lock_future`] is set.
+    fn lock_new_mapping(&mut self, start: VirtAddr, size: usize) {
+        if self.lock_future {
+            self.locked
+                .insert(VirtAddrRange::from_start_size(start, size));
+            if self.populate_future {
+                let _ = self.populate_area(start, size);
+            }
+        }
+    }
+
     /// To remove user area mappings from address space.
     pub fn unmap_user_areas(&mut self) -> AxResult {
         for area in self.areas.iter() {
@@ -226,6 +1066,10 @@ impl AddrSpace {
             );
         }
         self.areas.clear(&mut self.pt).unwrap();
+        self.dontfork.clear();
+        self.growsdown.clear();
+        self.nohugepage.clear();
+        self.unlock_all();
         Ok(())
     }
 
@@ -314,8 +1158,32 @@ impl AddrSpace {
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
     pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
-        // Populate the area first, which also checks the address range for us.
-        self.populate_area(start, size)?;
+        // The pages not mapped yet are left to be faulted in with the new
+        // permissions.
+        self.validate_region(start, size)?;
+        if !self.check_region_access(
+            VirtAddrRange::from_start_size(start, size),
+            MappingFlags::empty(),
+        ) {
+            return ax_err!(NoMemory);
+        }
+        self.check_huge_split(start, size)?;
+
+        if flags.contains(MappingFlags::WRITE) {
+            let range = VirtAddrRange::from_start_size(start, size);
+            for area in self.areas.iter() {
+                if let Backend::File {
+                    file, shared: true, ..
+                } = area.backend()
+                {
+                    if range.overlaps(VirtAddrRange::new(area.start(), area.end()))
+                        && !file.writable()
+                    {
+                        return ax_err!(PermissionDenied, "shared mapping is not writable");
+                    }
+                }
+            }
+        }
 
         self.areas
             .protect(start, size, |_| Some(flags), &mut self.pt)
@@ -327,6 +1195,10 @@ impl AddrSpace {
     /// Removes all mappings in the address space.
     pub fn clear(&mut self) {
         self.areas.clear(&mut self.pt).unwrap();
+        self.dontfork.clear();
+        self.growsdown.clear();
+        self.nohugepage.clear();
+        self.unlock_all();
     }
 
     /// Checks whether an access to the specified memory region is valid.
@@ -373,73 +1245,164 @@ impl AddrSpace {
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
-                return area
-                    .backend()
-                    .handle_page_fault(vaddr, orig_flags, &mut self.pt);
+                // Read faults map the shared zero frame rather than a huge
+                // page, so a range read before it is written keeps 4K pages.
+                if access_flags.contains(MappingFlags::WRITE)
+                    && self.thp_allowed(area, vaddr)
+                    && Backend::map_huge_page(vaddr, orig_flags, &mut self.pt)
+                {
+                    return true;
+                }
+                return area.backend().handle_page_fault(
+                    vaddr,
+                    orig_flags,
+                    access_flags,
+                    &mut self.pt,
+                );
             }
         }
         false
     }
 
-    /// Clone a [`AddrSpace`] by re-mapping all [`MemoryArea`]s in a new page table and copying data in user space.
+    /// Faults in the pages in the specified range for the access, like the
+    /// accesses from user space do, so that they can be accessed by
+    /// [`AddrSpace::read`] and [`AddrSpace::write`] from another address space.
+    ///
+    /// Copy-on-write pages are copied for the write access.
+    ///
+    /// Returns the size of the range from `start` which can be accessed.
+    pub fn fault_in(&mut self, start: VirtAddr, size: usize, access_flags: MappingFlags) -> usize {
+        let end = start + size;
+        let mut vaddr = start.align_down_4k();
+        while vaddr < end {
+            let present = self
+                .pt
+                .query(vaddr)
+                .is_ok_and(|(_, flags, _)| flags.contains(access_flags));
+            if !present && !self.handle_page_fault(vaddr, access_flags) {
+                break;
+            }
+            vaddr += PAGE_SIZE_4K;
+        }
+        (vaddr.max(start) - start).min(size)
+    }
+
+    /// Clone a [`AddrSpace`] by re-mapping all [`MemoryArea`]s in a new page table.
+    ///
+    /// Pages of allocation mappings are not copied. Instead, they are shared
+    /// copy-on-write: both page tables map the same frames without the write
+    /// permission, and the frame is copied on the first write from either side
+    /// (see [`AddrSpace::handle_page_fault`]). Pages of shared file mappings
+    /// are shared as they are. Huge pages of `MAP_HUGETLB` mappings are copied
+    /// at once, while transparent huge pages are split to be shared.
+    ///
+    /// Ranges marked by [`AddrSpace::set_dontfork`] are not mapped in the new
+    /// address space. Locks are not inherited, like Linux.
+    ///
+    /// The TLBs of all CPUs running on `self` are flushed, since its write
+    /// permissions are revoked.
     pub fn clone_or_err(&mut self) -> AxResult<Self> {
         let mut new_aspace = Self::new_empty(self.base(), self.size())?;
 
         for area in self.areas.iter() {
             let backend = area.backend();
-            // Remap the memory area in the new address space.
-            let new_area =
-                MemoryArea::new(area.start(), area.size(), area.flags(), backend.clone());
-            new_aspace
-                .areas
-                .map(new_area, &mut new_aspace.pt, false)
-                .map_err(mapping_err_to_ax_err)?;
-
-            if matches!(backend, Backend::Linear { .. }) {
-                continue;
-            }
-            // Copy data from old memory area to new memory area.
-            for vaddr in
-                PageIter4K::new(area.start(), area.end()).expect("Failed to create page iterator")
+            // Remap the memory area in the new address space, except the
+            // parts marked as not inherited. Frames of the allocation
+            // mappings are shared below, so they must not be populated here.
+            let new_backend = match backend {
+                Backend::Alloc { huge: false, .. } => Backend::new_alloc(false),
+                Backend::Alloc { huge: true, .. } => Backend::new_huge_alloc(false),
+                Backend::File { .. } => backend.fork_file(),
+                _ => backend.clone(),
+            };
+            let shared = matches!(backend, Backend::File { shared: true, .. });
+            let hugetlb = matches!(backend, Backend::Alloc { huge: true, .. });
+            for range in self
+                .dontfork
+                .subtract(VirtAddrRange::new(area.start(), area.end()))
             {
-                let addr = match self.pt.query(vaddr) {
-                    Ok((paddr, _, _)) => paddr,
-                    // If the page is not mapped, skip it.
-                    Err(PagingError::NotMapped) => continue,
-                    Err(_) => {
-                        error!("Bad Address at {}:{}:{}", module_path!(), file!(), line!());
-                        return Err(AxError::BadAddress);
-                    }
-                };
-                let new_addr = match new_aspace.pt.query(vaddr) {
-                    Ok((paddr, _, _)) => paddr,
-                    // If the page is not mapped, try map it.
-                    Err(PagingError::NotMapped) => {
-                        if !backend.handle_page_fault(vaddr, area.flags(), &mut new_aspace.pt) {
-                            return Err(AxError::NoMemory);
+                let new_area =
+                    MemoryArea::new(range.start, range.size(), area.flags(), new_backend.clone());
+                new_aspace
+                    .areas
+                    .map(new_area, &mut new_aspace.pt, false)
+                    .map_err(mapping_err_to_ax_err)?;
+                for stack in self.growsdown.overlapping(range) {
+                    new_aspace.growsdown.insert(stack);
+                }
+                for nohugepage in self.nohugepage.overlapping(range) {
+                    new_aspace.nohugepage.insert(nohugepage);
+                }
+
+                // Linear mappings, e.g. SysV shared memory, are remapped to the
+                // same physical memory above, so they are shared rather than
+                // copied.
+                if matches!(backend, Backend::Linear { .. }) {
+                    continue;
+                }
+                // Share the frames between the two page tables.
+                let mut vaddr = range.start;
+                while vaddr < range.end {
+                    let (frame, flags, page_size) = match self.pt.query(vaddr) {
+                        Ok(entry) => entry,
+                        // If the page is not mapped, skip it.
+                        Err(PagingError::NotMapped) => {
+                            vaddr += PAGE_SIZE_4K;
+                            continue;
                         }
-                        match new_aspace.pt.query(vaddr) {
-                            Ok((paddr, _, _)) => paddr,
-                            Err(_) => {
-                                error!("Bad Address at {}:{}:{}", module_path!(), file!(), line!());
-                                return Err(AxError::BadAddress);
-                            }
+                        Err(_) => {
+                            error!("Bad Address at {}:{}:{}", module_path!(), file!(), line!());
+                            return Err(AxError::BadAddress);
+                        }
+                    };
+                    if page_size.is_huge() {
+                        // Huge frames are never shared: pages of `MAP_HUGETLB`
+                        // mappings are copied at once, and transparent huge
+                        // pages are split to be shared as 4K pages.
+                        if hugetlb {
+                            let (new_frame, flags) =
+                                copy_huge_page(vaddr, &self.pt).ok_or(AxError::NoMemory)?;
+                            new_aspace
+                                .pt
+                                .map(vaddr, new_frame, page_size, flags)
+                                .map_err(|_| AxError::NoMemory)?
+                                .ignore();
+                            vaddr += page_size as usize;
+                            continue;
                         }
+                        if !split_huge_page(vaddr, &mut self.pt) {
+                            return ax_err!(BadState);
+                        }
+                        continue;
                     }
-                    Err(_) => {
-                        error!("Bad Address at {}:{}:{}", module_path!(), file!(), line!());
-                        return Err(AxError::BadAddress);
+                    if shared {
+                        new_aspace
+                            .pt
+                            .map(vaddr, frame, PageSize::Size4K, flags)
+                            .map_err(|_| AxError::NoMemory)?
+                            .ignore();
+                        add_frame_ref(frame);
+                    } else {
+                        let cow_flags = flags - MappingFlags::WRITE;
+                        new_aspace
+                            .pt
+                            .map(vaddr, frame, PageSize::Size4K, cow_flags)
+                            .map_err(|_| AxError::NoMemory)?
+                            .ignore();
+                        add_frame_ref(frame);
+                        if flags.contains(MappingFlags::WRITE) {
+                            self.pt
+                                .protect(vaddr, cow_flags)
+                                .map_err(|_| AxError::BadState)?
+                                .1
+                                .ignore();
+                        }
                     }
-                };
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        phys_to_virt(addr).as_ptr(),
-                        phys_to_virt(new_addr).as_mut_ptr(),
-                        PAGE_SIZE_4K,
-                    )
-                };
+                    vaddr += PAGE_SIZE_4K;
+                }
             }
         }
+        axhal::tlb::flush_tlb_range(self.pt.root_paddr(), self.base(), self.size());
         Ok(new_aspace)
     }
 }
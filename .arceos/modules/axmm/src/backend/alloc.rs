@@ -1,28 +1,177 @@
+use alloc::vec::Vec;
 use axalloc::global_allocator;
 use axhal::mem::{phys_to_virt, virt_to_phys};
-use axhal::paging::{MappingFlags, PageSize, PageTable};
-use memory_addr::{PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
+use axhal::paging::{MappingFlags, PageSize, PageTable, PagingError};
+use axhal::tlb::flush_tlb_range;
+use lazyinit::LazyInit;
+use memory_addr::{MemoryAddr, PAGE_SIZE_2M, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
 
 use super::Backend;
+use crate::frameinfo::{add_frame_ref, frame_ref_count, init_frame_ref, put_frame_ref};
 
-fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
-    let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
+/// The number of 4K frames in a huge page.
+const HUGE_PAGE_FRAMES: usize = PAGE_SIZE_2M / PAGE_SIZE_4K;
+/// The number of frames freed from caches at a time when the frames run out.
+const RECLAIM_BATCH: usize = 64;
+
+/// The frame filled with zeros, which is mapped read-only for read faults on
+/// lazy allocation mappings.
+///
+/// Its reference from the allocation is never dropped, so it is never freed,
+/// and a write to it is always handled by copying.
+static ZERO_FRAME: LazyInit<PhysAddr> = LazyInit::new();
+
+/// Allocates the shared zero frame.
+pub(crate) fn init_zero_frame() {
+    ZERO_FRAME.init_once(alloc_frame(true).expect("failed to allocate the zero frame"));
+}
+
+/// Returns whether `frame` is the shared zero frame, which holds no data of
+/// its own.
+pub(crate) fn is_zero_frame(frame: PhysAddr) -> bool {
+    frame == *ZERO_FRAME
+}
+
+/// Frees frames held by caches when the frames run out, see [`set_reclaimer`].
+static RECLAIMER: LazyInit<fn(usize) -> usize> = LazyInit::new();
+
+/// Sets the function called to free at most the given number of frames held
+/// by caches when the frames run out, which returns the number of frames
+/// freed.
+pub fn set_reclaimer(reclaimer: fn(usize) -> usize) {
+    RECLAIMER.init_once(reclaimer);
+}
+
+/// Allocates a reference-counted frame, which can be mapped by address spaces.
+/// Caches are shrunk if the frames run out.
+///
+/// The frame is freed after the reference is dropped by [`dealloc_frame`] and
+/// it is no longer mapped.
+pub fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
+    let vaddr = global_allocator()
+        .alloc_pages(1, PAGE_SIZE_4K)
+        .ok()
+        .or_else(|| {
+            let reclaim = RECLAIMER.get()?;
+            if reclaim(RECLAIM_BATCH) == 0 {
+                return None;
+            }
+            global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()
+        })?;
+    let vaddr = VirtAddr::from(vaddr);
     if zeroed {
         unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_4K) };
     }
     let paddr = virt_to_phys(vaddr);
+    init_frame_ref(paddr);
     Some(paddr)
 }
 
-fn dealloc_frame(frame: PhysAddr) {
-    let vaddr = phys_to_virt(frame);
-    global_allocator().dealloc_pages(vaddr.as_usize(), 1);
+/// Drops a reference to the frame, and frees it if it is no longer mapped.
+pub fn dealloc_frame(frame: PhysAddr) {
+    if put_frame_ref(frame) {
+        let vaddr = phys_to_virt(frame);
+        global_allocator().dealloc_pages(vaddr.as_usize(), 1);
+    }
+}
+
+/// Allocates a 2M huge frame, which is mapped by a single page table entry.
+///
+/// Only the reference count of the first 4K frame is used. Huge frames are
+/// never shared between page tables: they are split into 4K frames, or copied,
+/// before that.
+fn alloc_huge_frame(zeroed: bool) -> Option<PhysAddr> {
+    let vaddr = VirtAddr::from(
+        global_allocator()
+            .alloc_pages(HUGE_PAGE_FRAMES, PAGE_SIZE_2M)
+            .ok()?,
+    );
+    if zeroed {
+        unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_2M) };
+    }
+    let paddr = virt_to_phys(vaddr);
+    init_frame_ref(paddr);
+    Some(paddr)
+}
+
+/// Frees a huge frame allocated by [`alloc_huge_frame`].
+fn dealloc_huge_frame(frame: PhysAddr) {
+    if put_frame_ref(frame) {
+        let vaddr = phys_to_virt(frame);
+        global_allocator().dealloc_pages(vaddr.as_usize(), HUGE_PAGE_FRAMES);
+    }
+}
+
+/// Splits the huge page mapped at `vaddr` into 4K pages with the same
+/// permissions, which map the same frames.
+///
+/// Does nothing if `vaddr` is not mapped by a huge page.
+pub(crate) fn split_huge_page(vaddr: VirtAddr, pt: &mut PageTable) -> bool {
+    let Ok((frame, flags, page_size)) = pt.query(vaddr) else {
+        return true;
+    };
+    if !page_size.is_huge() {
+        return true;
+    }
+    let start = vaddr.align_down(page_size as usize);
+    let frame = frame.align_down(page_size as usize);
+    // Break before make: the huge page is unmapped and flushed on all CPUs
+    // before the 4K pages are installed, so that no CPU sees both at once.
+    match pt.unmap(start) {
+        Ok((_, _, tlb)) => tlb.ignore(),
+        Err(_) => return false,
+    }
+    flush_tlb_range(pt.root_paddr(), start, page_size as usize);
+    // Each 4K frame is reference-counted and freed on its own from now on.
+    for i in 0..page_size as usize / PAGE_SIZE_4K {
+        let offset = i * PAGE_SIZE_4K;
+        if i > 0 {
+            init_frame_ref(frame + offset);
+        }
+        match pt.map(start + offset, frame + offset, PageSize::Size4K, flags) {
+            Ok(tlb) => tlb.ignore(),
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+/// Copies the huge page mapped at `vaddr` to a new huge frame, for the child
+/// address space on fork.
+///
+/// Returns the new frame and the permissions of the page.
+pub(crate) fn copy_huge_page(vaddr: VirtAddr, pt: &PageTable) -> Option<(PhysAddr, MappingFlags)> {
+    let (frame, flags, page_size) = pt.query(vaddr).ok()?;
+    let frame = frame.align_down(page_size as usize);
+    let new_frame = alloc_huge_frame(false)?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            phys_to_virt(frame).as_ptr(),
+            phys_to_virt(new_frame).as_mut_ptr(),
+            PAGE_SIZE_2M,
+        )
+    };
+    Some((new_frame, flags))
 }
 
 impl Backend {
     /// Creates a new allocation mapping backend.
     pub const fn new_alloc(populate: bool) -> Self {
-        Self::Alloc { populate }
+        Self::Alloc {
+            populate,
+            huge: false,
+        }
+    }
+
+    /// Creates a new allocation mapping backend which maps 2M huge pages only,
+    /// for `MAP_HUGETLB`.
+    ///
+    /// The mapping must be aligned to 2M.
+    pub const fn new_huge_alloc(populate: bool) -> Self {
+        Self::Alloc {
+            populate,
+            huge: true,
+        }
     }
 
     pub(crate) fn map_alloc(
@@ -31,15 +180,30 @@ impl Backend {
         flags: MappingFlags,
         pt: &mut PageTable,
         populate: bool,
+        huge: bool,
     ) -> bool {
         debug!(
-            "map_alloc: [{:#x}, {:#x}) {:?} (populate={})",
+            "map_alloc: [{:#x}, {:#x}) {:?} (populate={}, huge={})",
             start,
             start + size,
             flags,
-            populate
+            populate,
+            huge
         );
-        if populate {
+        if populate && huge {
+            for addr in (start.as_usize()..start.as_usize() + size).step_by(PAGE_SIZE_2M) {
+                let Some(frame) = alloc_huge_frame(true) else {
+                    return false;
+                };
+                match pt.map(addr.into(), frame, PageSize::Size2M, flags) {
+                    Ok(tlb) => tlb.ignore(),
+                    Err(_) => {
+                        dealloc_huge_frame(frame);
+                        return false;
+                    }
+                }
+            }
+        } else if populate {
             // allocate all possible physical frames for populated mapping.
             for addr in PageIter4K::new(start, start + size).unwrap() {
                 if let Some(frame) = alloc_frame(true) {
@@ -61,41 +225,235 @@ impl Backend {
         size: usize,
         pt: &mut PageTable,
         _populate: bool,
+        huge: bool,
     ) -> bool {
         debug!("unmap_alloc: [{:#x}, {:#x})", start, start + size);
-        for addr in PageIter4K::new(start, start + size).unwrap() {
-            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
+        let end = start + size;
+        // The frames are freed only after no CPU can access them through
+        // stale TLB entries.
+        let mut frames = Vec::new();
+        let ok = 'unmap: {
+            let mut addr = start;
+            while addr < end {
+                let page_size = match pt.query(addr) {
+                    Ok((_, _, page_size)) => page_size,
+                    // Deallocation is needn't if the page is not mapped.
+                    Err(_) => {
+                        addr += PAGE_SIZE_4K;
+                        continue;
+                    }
+                };
+                if page_size.is_huge() && !Self::covers_huge_page(addr, end, page_size) {
+                    // Only transparent huge pages can be split.
+                    if huge || !split_huge_page(addr, pt) {
+                        break 'unmap false;
+                    }
+                    continue;
+                }
                 // Deallocate the physical frame if there is a mapping in the
                 // page table.
-                if page_size.is_huge() {
-                    return false;
-                }
-                tlb.flush();
-                dealloc_frame(frame);
+                let Ok((frame, _, tlb)) = pt.unmap(addr) else {
+                    break 'unmap false;
+                };
+                tlb.ignore();
+                frames.push((frame, page_size));
+                addr = addr.align_down(page_size as usize) + page_size as usize;
+            }
+            true
+        };
+        flush_tlb_range(pt.root_paddr(), start, size);
+        for (frame, page_size) in frames {
+            if page_size.is_huge() {
+                dealloc_huge_frame(frame);
             } else {
-                // Deallocation is needn't if the page is not mapped.
+                dealloc_frame(frame);
             }
         }
-        true
+        ok
+    }
+
+    /// Returns whether the huge page at `addr` is entirely within
+    /// `[addr, end)`.
+    fn covers_huge_page(addr: VirtAddr, end: VirtAddr, page_size: PageSize) -> bool {
+        addr.is_aligned(page_size as usize) && end - addr >= page_size as usize
+    }
+
+    pub(crate) fn protect_alloc(
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+        huge: bool,
+    ) -> bool {
+        debug!(
+            "protect_alloc: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        let end = start + size;
+        let ok = 'protect: {
+            let mut addr = start;
+            while addr < end {
+                let Ok((frame, _, page_size)) = pt.query(addr) else {
+                    addr += PAGE_SIZE_4K;
+                    continue;
+                };
+                if page_size.is_huge() && !Self::covers_huge_page(addr, end, page_size) {
+                    // Only transparent huge pages can be split.
+                    if huge || !split_huge_page(addr, pt) {
+                        break 'protect false;
+                    }
+                    continue;
+                }
+                // Frames shared copy-on-write must stay read-only, the write
+                // permission is restored when the page is copied on fault.
+                // Huge frames are never shared.
+                let flags = if frame_ref_count(frame) > 1 {
+                    new_flags - MappingFlags::WRITE
+                } else {
+                    new_flags
+                };
+                match pt.protect(addr, flags) {
+                    Ok((_, tlb)) => tlb.ignore(),
+                    Err(_) => break 'protect false,
+                }
+                addr = addr.align_down(page_size as usize) + page_size as usize;
+            }
+            true
+        };
+        flush_tlb_range(pt.root_paddr(), start, size);
+        ok
     }
 
     pub(crate) fn handle_page_fault_alloc(
         vaddr: VirtAddr,
         orig_flags: MappingFlags,
+        access_flags: MappingFlags,
         pt: &mut PageTable,
         populate: bool,
+        huge: bool,
     ) -> bool {
-        if populate {
-            false // Populated mappings should not trigger page faults.
-        } else if let Some(frame) = alloc_frame(true) {
-            // Allocate a physical frame lazily and map it to the fault address.
-            // `vaddr` does not need to be aligned. It will be automatically
-            // aligned during `pt.map` regardless of the page size.
-            pt.map(vaddr, frame, PageSize::Size4K, orig_flags)
-                .map(|tlb| tlb.flush())
-                .is_ok()
-        } else {
-            false
+        match pt.query(vaddr) {
+            Ok((frame, flags, page_size)) => {
+                if flags.contains(access_flags) {
+                    // The fault was caused by a stale TLB entry, e.g. the page
+                    // has been copied by another thread.
+                    axhal::arch::flush_tlb(Some(vaddr.align_down_4k()));
+                    true
+                } else if access_flags.contains(MappingFlags::WRITE) && !page_size.is_huge() {
+                    Self::handle_cow_fault(vaddr.align_down_4k(), frame, orig_flags, pt)
+                } else {
+                    false
+                }
+            }
+            Err(PagingError::NotMapped) => {
+                if populate {
+                    false // Populated mappings should not trigger page faults.
+                } else if huge {
+                    Self::map_huge_page(vaddr, orig_flags, pt)
+                } else if !access_flags.contains(MappingFlags::WRITE) {
+                    // Pages not written yet map the zero frame, they are
+                    // copied on the first write like other shared frames.
+                    let frame = *ZERO_FRAME;
+                    add_frame_ref(frame);
+                    match pt.map(
+                        vaddr,
+                        frame,
+                        PageSize::Size4K,
+                        orig_flags - MappingFlags::WRITE,
+                    ) {
+                        Ok(tlb) => {
+                            tlb.flush();
+                            true
+                        }
+                        Err(_) => {
+                            dealloc_frame(frame);
+                            false
+                        }
+                    }
+                } else if let Some(frame) = alloc_frame(true) {
+                    // Allocate a physical frame lazily and map it to the fault address.
+                    // `vaddr` does not need to be aligned. It will be automatically
+                    // aligned during `pt.map` regardless of the page size.
+                    pt.map(vaddr, frame, PageSize::Size4K, orig_flags)
+                        .map(|tlb| tlb.flush())
+                        .is_ok()
+                } else {
+                    false
+                }
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Maps a zeroed huge page covering `vaddr`, for a huge page mapping or a
+    /// transparent huge page.
+    ///
+    /// Returns `false` if no huge frame is available, or the range of the huge
+    /// page is already mapped by 4K pages in part.
+    pub(crate) fn map_huge_page(vaddr: VirtAddr, flags: MappingFlags, pt: &mut PageTable) -> bool {
+        let Some(frame) = alloc_huge_frame(true) else {
+            return false;
+        };
+        match pt.map(
+            vaddr.align_down(PAGE_SIZE_2M),
+            frame,
+            PageSize::Size2M,
+            flags,
+        ) {
+            Ok(tlb) => {
+                tlb.flush();
+                true
+            }
+            Err(_) => {
+                dealloc_huge_frame(frame);
+                false
+            }
+        }
+    }
+
+    /// Handles a write to a page shared copy-on-write.
+    ///
+    /// If the frame is still shared with other page tables, the content is
+    /// copied to a new frame, otherwise the write permission is just restored.
+    pub(super) fn handle_cow_fault(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        if frame_ref_count(frame) == 1 {
+            return pt
+                .protect(vaddr, orig_flags)
+                .map(|(_, tlb)| tlb.flush())
+                .is_ok();
+        }
+        let zero = is_zero_frame(frame);
+        let Some(new_frame) = alloc_frame(zero) else {
+            return false;
+        };
+        if !zero {
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    phys_to_virt(frame).as_ptr(),
+                    phys_to_virt(new_frame).as_mut_ptr(),
+                    PAGE_SIZE_4K,
+                )
+            };
+        }
+        match pt.remap(vaddr, new_frame, orig_flags) {
+            Ok((_, tlb)) => {
+                // Other threads must not write to the old frame any more.
+                tlb.ignore();
+                flush_tlb_range(pt.root_paddr(), vaddr, PAGE_SIZE_4K);
+                dealloc_frame(frame);
+                true
+            }
+            Err(_) => {
+                dealloc_frame(new_frame);
+                false
+            }
         }
     }
 }
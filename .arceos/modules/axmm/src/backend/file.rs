@@ -0,0 +1,413 @@
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axerrno::AxResult;
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageSize, PageTable, PagingError};
+use axhal::tlb::flush_tlb_range;
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
+
+use super::alloc::{alloc_frame, dealloc_frame};
+use super::{Backend, DirtyPages};
+use crate::frameinfo::add_frame_ref;
+
+/// A file that can be mapped into an address space by [`Backend::File`].
+pub trait MmapFile: Send + Sync {
+    /// Reads the file at `offset` into `buf`, returns the number of bytes read.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize>;
+
+    /// Writes `buf` into the file at `offset`, returns the number of bytes
+    /// written.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize>;
+
+    /// Returns the current size of the file in bytes.
+    fn size(&self) -> AxResult<u64>;
+
+    /// Returns the frame holding the page at `offset`, if the file keeps its
+    /// content in memory.
+    ///
+    /// Shared mappings map such frames directly instead of copies, so that
+    /// the modifications are visible to all mappings and the file at once.
+    fn shared_frame(&self, _offset: u64) -> AxResult<Option<PhysAddr>> {
+        Ok(None)
+    }
+
+    /// Marks the page at `offset` modified, after the frame returned by
+    /// [`shared_frame`](MmapFile::shared_frame) is written through a shared
+    /// mapping.
+    fn set_dirty(&self, _offset: u64) -> AxResult {
+        Ok(())
+    }
+
+    /// Returns whether shared mappings of the file can be made writable, e.g.
+    /// by `mprotect`.
+    fn writable(&self) -> bool {
+        true
+    }
+
+    /// Reads the page at `offset` into memory ahead of a fault on it, so that
+    /// the fault is handled without blocking on I/O.
+    fn prefetch(&self, _offset: u64) -> AxResult {
+        Ok(())
+    }
+}
+
+impl Backend {
+    /// Creates a new file mapping backend.
+    ///
+    /// `start` is mapped to the file at `offset`.
+    pub fn new_file(start: VirtAddr, file: Arc<dyn MmapFile>, offset: usize, shared: bool) -> Self {
+        Self::File {
+            file,
+            va_offset: start.as_usize().wrapping_sub(offset),
+            shared,
+            dirty: Arc::new(SpinNoIrq::new(BTreeSet::new())),
+        }
+    }
+
+    /// Returns the backend for the copy of the mapping in a forked address
+    /// space, where the pages dirty in this one are dirty as well.
+    pub(crate) fn fork_file(&self) -> Self {
+        match self {
+            Self::File {
+                file,
+                va_offset,
+                shared,
+                dirty,
+            } => Self::File {
+                file: file.clone(),
+                va_offset: *va_offset,
+                shared: *shared,
+                dirty: Arc::new(SpinNoIrq::new(dirty.lock().clone())),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns the file offset mapped at `vaddr`.
+    fn file_offset(vaddr: VirtAddr, va_offset: usize) -> u64 {
+        vaddr.as_usize().wrapping_sub(va_offset) as u64
+    }
+
+    /// Returns the file and the offset of the page at `vaddr` if it is in a
+    /// file mapping and not mapped yet, i.e. the fault on it reads the file.
+    pub(crate) fn unmapped_file_page(
+        &self,
+        vaddr: VirtAddr,
+        pt: &PageTable,
+    ) -> Option<(Arc<dyn MmapFile>, u64)> {
+        let Self::File {
+            file, va_offset, ..
+        } = self
+        else {
+            return None;
+        };
+        if pt.query(vaddr).is_ok() {
+            return None;
+        }
+        Some((
+            file.clone(),
+            Self::file_offset(vaddr.align_down_4k(), *va_offset),
+        ))
+    }
+
+    pub(crate) fn map_file(start: VirtAddr, size: usize, flags: MappingFlags) -> bool {
+        debug!("map_file: [{:#x}, {:#x}) {:?}", start, start + size, flags);
+        // create mapping entries on demand later in `handle_page_fault_file`.
+        true
+    }
+
+    pub(crate) fn unmap_file(
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+        file: &Arc<dyn MmapFile>,
+        va_offset: usize,
+        shared: bool,
+        dirty: &DirtyPages,
+    ) -> bool {
+        debug!("unmap_file: [{:#x}, {:#x})", start, start + size);
+        // The frames are freed after the TLB flush, see `unmap_alloc`.
+        let mut frames = Vec::new();
+        let mut ok = true;
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if let Ok((frame, _, _)) = pt.query(addr) {
+                if shared && dirty.lock().remove(&Self::file_offset(addr, va_offset)) {
+                    // Errors are ignored, there is no one to report them to.
+                    let _ = Self::write_back_page(addr, frame, file, va_offset);
+                }
+                match pt.unmap(addr) {
+                    Ok((_, _, tlb)) => tlb.ignore(),
+                    Err(_) => {
+                        ok = false;
+                        break;
+                    }
+                }
+                frames.push(frame);
+            }
+        }
+        flush_tlb_range(pt.root_paddr(), start, size);
+        for frame in frames {
+            dealloc_frame(frame);
+        }
+        ok
+    }
+
+    pub(crate) fn protect_file(
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+        va_offset: usize,
+        shared: bool,
+        dirty: &DirtyPages,
+    ) -> bool {
+        if !shared {
+            // Private pages are copy-on-write, the same as allocation mappings.
+            return Self::protect_alloc(start, size, new_flags, pt, false);
+        }
+        debug!(
+            "protect_file: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        let ok = {
+            let dirty = dirty.lock();
+            PageIter4K::new(start, start + size).unwrap().all(|addr| {
+                if pt.query(addr).is_err() {
+                    return true;
+                }
+                // Clean pages stay read-only, so that the first write marks
+                // them dirty. Dirty pages stay dirty even if they are made
+                // read-only.
+                let flags = if dirty.contains(&Self::file_offset(addr, va_offset)) {
+                    new_flags
+                } else {
+                    new_flags - MappingFlags::WRITE
+                };
+                pt.protect(addr, flags).map(|(_, tlb)| tlb.ignore()).is_ok()
+            })
+        };
+        // The dirty pages are not locked across the shootdown, which waits for
+        // the other CPUs.
+        flush_tlb_range(pt.root_paddr(), start, size);
+        ok
+    }
+
+    /// Returns whether the page at `vaddr` is wholly beyond the end of the
+    /// file, where accesses fail rather than mapping zeros.
+    pub(crate) fn beyond_file_end(
+        vaddr: VirtAddr,
+        file: &Arc<dyn MmapFile>,
+        va_offset: usize,
+    ) -> bool {
+        let offset = Self::file_offset(vaddr.align_down_4k(), va_offset);
+        file.size().is_ok_and(|size| offset >= size)
+    }
+
+    /// Unmaps the pages of a shared file mapping which map any of `frames`,
+    /// e.g. the pages removed from the file by truncation.
+    pub(crate) fn unmap_file_frames(
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+        frames: &BTreeSet<PhysAddr>,
+        va_offset: usize,
+        dirty: &DirtyPages,
+    ) {
+        // The frames are freed after the TLB flush, see `unmap_alloc`.
+        let mut unmapped = Vec::new();
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if let Ok((frame, _, _)) = pt.query(addr) {
+                if frames.contains(&frame) {
+                    if let Ok((_, _, tlb)) = pt.unmap(addr) {
+                        tlb.ignore();
+                        dirty.lock().remove(&Self::file_offset(addr, va_offset));
+                        unmapped.push(frame);
+                    }
+                }
+            }
+        }
+        flush_tlb_range(pt.root_paddr(), start, size);
+        for frame in unmapped {
+            dealloc_frame(frame);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn handle_page_fault_file(
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        access_flags: MappingFlags,
+        pt: &mut PageTable,
+        file: &Arc<dyn MmapFile>,
+        va_offset: usize,
+        shared: bool,
+        dirty: &DirtyPages,
+    ) -> bool {
+        let vaddr = vaddr.align_down_4k();
+        let offset = Self::file_offset(vaddr, va_offset);
+        match pt.query(vaddr) {
+            Ok((frame, flags, _)) => {
+                if flags.contains(access_flags) {
+                    // Stale TLB entry, see `handle_page_fault_alloc`.
+                    axhal::arch::flush_tlb(Some(vaddr));
+                    true
+                } else if !access_flags.contains(MappingFlags::WRITE) {
+                    false
+                } else if shared {
+                    // First write to a clean shared page, mark it dirty.
+                    if pt
+                        .protect(vaddr, orig_flags)
+                        .map(|(_, tlb)| tlb.flush())
+                        .is_err()
+                    {
+                        return false;
+                    }
+                    Self::mark_dirty(offset, frame, file, dirty);
+                    true
+                } else {
+                    Self::handle_cow_fault(vaddr, frame, orig_flags, pt)
+                }
+            }
+            Err(PagingError::NotMapped) => {
+                if Self::beyond_file_end(vaddr, file, va_offset) {
+                    return false;
+                }
+                if shared {
+                    match file.shared_frame(offset) {
+                        Ok(Some(frame)) => {
+                            add_frame_ref(frame);
+                            // Mapped read-only until it is written, the same
+                            // as the pages read from the file below.
+                            let write = access_flags.contains(MappingFlags::WRITE);
+                            let flags = if write {
+                                orig_flags
+                            } else {
+                                orig_flags - MappingFlags::WRITE
+                            };
+                            return match pt.map(vaddr, frame, PageSize::Size4K, flags) {
+                                Ok(tlb) => {
+                                    tlb.flush();
+                                    if write {
+                                        Self::mark_dirty(offset, frame, file, dirty);
+                                    }
+                                    true
+                                }
+                                Err(_) => {
+                                    dealloc_frame(frame);
+                                    false
+                                }
+                            };
+                        }
+                        Ok(None) => {}
+                        Err(_) => return false,
+                    }
+                }
+                let Some(frame) = alloc_frame(true) else {
+                    return false;
+                };
+                let buf = unsafe {
+                    core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K)
+                };
+                // The part beyond the end of file is left zero-filled.
+                if file.read_at(offset, buf).is_err() {
+                    dealloc_frame(frame);
+                    return false;
+                }
+                // Shared pages are mapped read-only until they are written, to
+                // know which pages need to be written back.
+                let write = access_flags.contains(MappingFlags::WRITE);
+                let flags = if shared && !write {
+                    orig_flags - MappingFlags::WRITE
+                } else {
+                    orig_flags
+                };
+                match pt.map(vaddr, frame, PageSize::Size4K, flags) {
+                    Ok(tlb) => {
+                        tlb.flush();
+                        if shared && write {
+                            Self::mark_dirty(offset, frame, file, dirty);
+                        }
+                        true
+                    }
+                    Err(_) => {
+                        dealloc_frame(frame);
+                        false
+                    }
+                }
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Writes dirty pages of a shared file mapping back to the file, and marks
+    /// them clean.
+    pub(crate) fn sync_file(
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+        file: &Arc<dyn MmapFile>,
+        va_offset: usize,
+        dirty: &DirtyPages,
+    ) -> AxResult {
+        let mut result = Ok(());
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if let Ok((frame, flags, _)) = pt.query(addr) {
+                let offset = Self::file_offset(addr, va_offset);
+                if dirty.lock().contains(&offset) {
+                    result = Self::write_back_page(addr, frame, file, va_offset);
+                    if result.is_err() {
+                        break;
+                    }
+                    dirty.lock().remove(&offset);
+                    // Writes on other CPUs must fault again to mark the page
+                    // dirty, so their TLBs are flushed below.
+                    if let Ok((_, tlb)) = pt.protect(addr, flags - MappingFlags::WRITE) {
+                        tlb.ignore();
+                    }
+                }
+            }
+        }
+        flush_tlb_range(pt.root_paddr(), start, size);
+        result
+    }
+
+    /// Records that the page at the file `offset`, mapped to `frame`, is
+    /// written through a shared mapping. The page of the file itself is marked
+    /// dirty in the file at once.
+    fn mark_dirty(offset: u64, frame: PhysAddr, file: &Arc<dyn MmapFile>, dirty: &DirtyPages) {
+        dirty.lock().insert(offset);
+        if let Ok(Some(file_frame)) = file.shared_frame(offset) {
+            if file_frame == frame {
+                // Also written back when the page is unmapped or synchronized.
+                let _ = file.set_dirty(offset);
+            }
+        }
+    }
+
+    fn write_back_page(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        file: &Arc<dyn MmapFile>,
+        va_offset: usize,
+    ) -> AxResult {
+        let offset = Self::file_offset(vaddr, va_offset);
+        let file_size = file.size()?;
+        if offset >= file_size {
+            // Do not extend the file by writing back the pages beyond its end.
+            return Ok(());
+        }
+        if file.shared_frame(offset)? == Some(frame) {
+            // The page of the file itself is modified.
+            return file.set_dirty(offset);
+        }
+        let len = PAGE_SIZE_4K.min((file_size - offset) as usize);
+        let buf = unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), len) };
+        file.write_at(offset, buf)?;
+        Ok(())
+    }
+}
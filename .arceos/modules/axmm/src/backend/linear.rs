@@ -1,4 +1,5 @@
 use axhal::paging::{MappingFlags, PageTable};
+use axhal::tlb::flush_tlb_range;
 use memory_addr::{PhysAddr, VirtAddr};
 
 use super::Backend;
@@ -37,8 +38,11 @@ impl Backend {
         _pa_va_offset: usize,
     ) -> bool {
         debug!("unmap_linear: [{:#x}, {:#x})", start, start + size);
-        pt.unmap_region(start, size, true)
-            .map(|tlb| tlb.ignore()) // flush each page on unmap, do not flush the entire TLB.
-            .is_ok()
+        let ok = pt
+            .unmap_region(start, size, false)
+            .map(|tlb| tlb.ignore())
+            .is_ok();
+        flush_tlb_range(pt.root_paddr(), start, size);
+        ok
     }
 }
@@ -1,20 +1,35 @@
 //! Memory mapping backends.
 
+use ::alloc::collections::BTreeSet;
+use ::alloc::sync::Arc;
+
 use axhal::paging::{MappingFlags, PageTable};
+use kspin::SpinNoIrq;
 use memory_addr::VirtAddr;
 use memory_set::MappingBackend;
 
 mod alloc;
+mod file;
 mod linear;
 
+pub use self::alloc::{alloc_frame, dealloc_frame, set_reclaimer};
+pub(crate) use self::alloc::{copy_huge_page, init_zero_frame, is_zero_frame, split_huge_page};
+pub use self::file::MmapFile;
+
+/// The file offsets of the pages written through a shared file mapping and
+/// not written back yet.
+pub(crate) type DirtyPages = Arc<SpinNoIrq<BTreeSet<u64>>>;
+
 /// A unified enum type for different memory mapping backends.
 ///
-/// Currently, two backends are implemented:
+/// Currently, three backends are implemented:
 ///
 /// - **Linear**: used for linear mappings. The target physical frames are
 ///   contiguous and their addresses should be known when creating the mapping.
 /// - **Allocation**: used in general, or for lazy mappings. The target physical
 ///   frames are obtained from the global allocator.
+/// - **File**: used for file mappings. The target physical frames are obtained
+///   from the global allocator and filled with the file content on demand.
 #[derive(Clone)]
 pub enum Backend {
     /// Linear mapping backend.
@@ -32,9 +47,44 @@ pub enum Backend {
     /// mapping is created, and no page faults are triggered during the memory
     /// access. Otherwise, the physical frames are allocated on demand (by
     /// handling page faults).
+    ///
+    /// If `huge` is `true`, the mapping is backed by 2M huge pages only.
+    /// Otherwise, 4K pages are used, except the transparent huge pages mapped
+    /// by [`AddrSpace::handle_page_fault`](crate::AddrSpace::handle_page_fault)
+    /// where possible.
+    ///
+    /// The frames may be shared copy-on-write with other address spaces, see
+    /// [`AddrSpace::clone_or_err`](crate::AddrSpace::clone_or_err).
     Alloc {
         /// Whether to populate the physical frames when creating the mapping.
         populate: bool,
+        /// Whether the mapping is backed by huge pages only.
+        huge: bool,
+    },
+    /// File mapping backend.
+    ///
+    /// The physical frames are allocated and read from the file on the first
+    /// access. The virtual address `vaddr` is mapped to the file offset
+    /// `vaddr - va_offset`.
+    ///
+    /// If `shared` is `true`, the modifications are written back to the file
+    /// when the pages are unmapped or synchronized (see [`AddrSpace::sync`]).
+    /// Otherwise, the pages are private copy-on-write copies of the file.
+    ///
+    /// The pages written are tracked by `dirty` rather than by the page table
+    /// entries, which lose the write permission to `mprotect`.
+    ///
+    /// [`AddrSpace::sync`]: crate::AddrSpace::sync
+    File {
+        /// The mapped file.
+        file: Arc<dyn MmapFile>,
+        /// `vaddr - file offset`.
+        va_offset: usize,
+        /// Whether the modifications are visible to the file.
+        shared: bool,
+        /// The dirty pages, shared by the parts of the mapping split by
+        /// `munmap` or `mprotect`.
+        dirty: DirtyPages,
     },
 }
 
@@ -45,14 +95,23 @@ impl MappingBackend for Backend {
     fn map(&self, start: VirtAddr, size: usize, flags: MappingFlags, pt: &mut PageTable) -> bool {
         match *self {
             Self::Linear { pa_va_offset } => Self::map_linear(start, size, flags, pt, pa_va_offset),
-            Self::Alloc { populate } => Self::map_alloc(start, size, flags, pt, populate),
+            Self::Alloc { populate, huge } => {
+                Self::map_alloc(start, size, flags, pt, populate, huge)
+            }
+            Self::File { .. } => Self::map_file(start, size, flags),
         }
     }
 
     fn unmap(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
-        match *self {
-            Self::Linear { pa_va_offset } => Self::unmap_linear(start, size, pt, pa_va_offset),
-            Self::Alloc { populate } => Self::unmap_alloc(start, size, pt, populate),
+        match self {
+            Self::Linear { pa_va_offset } => Self::unmap_linear(start, size, pt, *pa_va_offset),
+            Self::Alloc { populate, huge } => Self::unmap_alloc(start, size, pt, *populate, *huge),
+            Self::File {
+                file,
+                va_offset,
+                shared,
+                dirty,
+            } => Self::unmap_file(start, size, pt, file, *va_offset, *shared, dirty),
         }
     }
 
@@ -63,10 +122,27 @@ impl MappingBackend for Backend {
         new_flags: Self::Flags,
         page_table: &mut Self::PageTable,
     ) -> bool {
-        page_table
-            .protect_region(start, size, new_flags, true)
-            .map(|tlb| tlb.ignore())
-            .is_ok()
+        match self {
+            Self::Linear { .. } => {
+                let ok = page_table
+                    .protect_region(start, size, new_flags, false)
+                    .map(|tlb| tlb.ignore())
+                    .is_ok();
+                axhal::tlb::flush_tlb_range(page_table.root_paddr(), start, size);
+                ok
+            }
+            Self::Alloc { huge, .. } => {
+                Self::protect_alloc(start, size, new_flags, page_table, *huge)
+            }
+            Self::File {
+                va_offset,
+                shared,
+                dirty,
+                ..
+            } => Self::protect_file(
+                start, size, new_flags, page_table, *va_offset, *shared, dirty,
+            ),
+        }
     }
 }
 
@@ -75,13 +151,109 @@ impl Backend {
         &self,
         vaddr: VirtAddr,
         orig_flags: MappingFlags,
+        access_flags: MappingFlags,
         page_table: &mut PageTable,
     ) -> bool {
-        match *self {
+        match self {
             Self::Linear { .. } => false, // Linear mappings should not trigger page faults.
-            Self::Alloc { populate } => {
-                Self::handle_page_fault_alloc(vaddr, orig_flags, page_table, populate)
+            Self::Alloc { populate, huge } => Self::handle_page_fault_alloc(
+                vaddr,
+                orig_flags,
+                access_flags,
+                page_table,
+                *populate,
+                *huge,
+            ),
+            Self::File {
+                file,
+                va_offset,
+                shared,
+                dirty,
+            } => Self::handle_page_fault_file(
+                vaddr,
+                orig_flags,
+                access_flags,
+                page_table,
+                file,
+                *va_offset,
+                *shared,
+                dirty,
+            ),
+        }
+    }
+
+    /// Drops the pages mapped in the given range, so that they are mapped again
+    /// on the next access.
+    ///
+    /// Returns `false` if the pages cannot be dropped.
+    pub(crate) fn discard(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        page_table: &mut PageTable,
+    ) -> bool {
+        match self {
+            Self::Linear { .. } => false,
+            Self::Alloc { populate, huge } => {
+                // Populated mappings do not handle page faults, map zeroed
+                // frames again instead.
+                Self::unmap_alloc(start, size, page_table, *populate, *huge)
+                    && (!*populate || Self::map_alloc(start, size, flags, page_table, true, *huge))
             }
+            Self::File {
+                file,
+                va_offset,
+                shared,
+                dirty,
+            } => Self::unmap_file(start, size, page_table, file, *va_offset, *shared, dirty),
+        }
+    }
+
+    /// Returns the backend for the mapping moved from `old_start` to
+    /// `new_start`, or `None` if the mapping cannot be moved.
+    ///
+    /// The pages are not populated by the new backend, they are expected to
+    /// be moved by the caller.
+    pub(crate) fn relocate(&self, old_start: VirtAddr, new_start: VirtAddr) -> Option<Self> {
+        match self {
+            Self::Linear { .. } => None,
+            Self::Alloc { huge, .. } => Some(Self::Alloc {
+                populate: false,
+                huge: *huge,
+            }),
+            Self::File {
+                file,
+                va_offset,
+                shared,
+                dirty,
+            } => Some(Self::File {
+                file: file.clone(),
+                va_offset: va_offset
+                    .wrapping_add(new_start.as_usize())
+                    .wrapping_sub(old_start.as_usize()),
+                shared: *shared,
+                dirty: dirty.clone(),
+            }),
+        }
+    }
+
+    /// Writes the modified pages in the given range back to the file, if this
+    /// is a shared file mapping.
+    pub(crate) fn sync(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        page_table: &mut PageTable,
+    ) -> axerrno::AxResult {
+        match self {
+            Self::File {
+                file,
+                va_offset,
+                shared: true,
+                dirty,
+            } => Self::sync_file(start, size, page_table, file, *va_offset, dirty),
+            _ => Ok(()),
         }
     }
 }
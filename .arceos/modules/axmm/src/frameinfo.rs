@@ -0,0 +1,47 @@
+//! Reference counting of physical frames.
+//!
+//! Frames allocated by [`Backend::Alloc`](crate::Backend::Alloc) may be mapped
+//! by more than one page table after a copy-on-write clone of the address
+//! space. Each frame records how many page table entries point to it, and it
+//! is returned to the global allocator only when the last one is removed.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axconfig::plat::{PHYS_MEMORY_BASE, PHYS_MEMORY_SIZE};
+use memory_addr::{PAGE_SIZE_4K, PhysAddr};
+
+const MAX_FRAME_NUM: usize = PHYS_MEMORY_SIZE / PAGE_SIZE_4K;
+
+static FRAME_REFS: [AtomicU32; MAX_FRAME_NUM] = [const { AtomicU32::new(0) }; MAX_FRAME_NUM];
+
+fn frame_ref(paddr: PhysAddr) -> &'static AtomicU32 {
+    let index = (paddr.as_usize() - PHYS_MEMORY_BASE) / PAGE_SIZE_4K;
+    &FRAME_REFS[index]
+}
+
+/// Initializes the reference count of a newly allocated frame to 1.
+pub(crate) fn init_frame_ref(paddr: PhysAddr) {
+    frame_ref(paddr).store(1, Ordering::Release);
+}
+
+/// Adds a reference to the frame, when it is mapped by another page table
+/// entry.
+pub(crate) fn add_frame_ref(paddr: PhysAddr) {
+    frame_ref(paddr).fetch_add(1, Ordering::AcqRel);
+}
+
+/// Removes a reference from the frame.
+///
+/// Returns `true` if it was the last reference, i.e. the frame can be freed.
+pub(crate) fn put_frame_ref(paddr: PhysAddr) -> bool {
+    let old = frame_ref(paddr).fetch_sub(1, Ordering::AcqRel);
+    debug_assert!(old > 0, "frame {:#x} is not referenced", paddr);
+    old == 1
+}
+
+/// Returns the number of references to the frame, i.e. the page table entries
+/// mapping it and the owner, if any, which allocated it by
+/// [`alloc_frame`](crate::alloc_frame).
+pub fn frame_ref_count(paddr: PhysAddr) -> u32 {
+    frame_ref(paddr).load(Ordering::Acquire)
+}
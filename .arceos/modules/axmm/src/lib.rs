@@ -8,9 +8,12 @@ extern crate alloc;
 
 mod aspace;
 mod backend;
+mod frameinfo;
+mod rangeset;
 
 pub use self::aspace::AddrSpace;
-pub use self::backend::Backend;
+pub use self::backend::{Backend, MmapFile, alloc_frame, dealloc_frame, set_reclaimer};
+pub use self::frameinfo::frame_ref_count;
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
@@ -63,6 +66,7 @@ pub fn init_memory_management() {
     debug!("kernel address space init OK: {:#x?}", kernel_aspace);
     KERNEL_ASPACE.init_once(SpinNoIrq::new(kernel_aspace));
     axhal::paging::set_kernel_page_table_root(kernel_page_table_root());
+    backend::init_zero_frame();
 }
 
 /// Initializes kernel paging for secondary CPUs.
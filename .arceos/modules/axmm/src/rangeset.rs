@@ -0,0 +1,117 @@
+//! A set of disjoint virtual address ranges.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use memory_addr::{VirtAddr, VirtAddrRange};
+
+/// A set of disjoint virtual address ranges.
+///
+/// It records the attributes of parts of an address space that are not kept
+/// in the memory areas, e.g. the ranges not inherited on fork, the stacks
+/// growing down, or the locked ranges.
+#[derive(Default)]
+pub(crate) struct RangeSet {
+    /// Maps the start address of each range to its end address.
+    ranges: BTreeMap<VirtAddr, VirtAddr>,
+}
+
+impl RangeSet {
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        Self {
+            ranges: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the parts of the set that overlap with `range`.
+    pub fn overlapping(&self, range: VirtAddrRange) -> Vec<VirtAddrRange> {
+        self.ranges
+            .range(..range.end)
+            .filter(|&(_, &end)| end > range.start)
+            .map(|(&start, &end)| VirtAddrRange::new(start.max(range.start), end.min(range.end)))
+            .collect()
+    }
+
+    /// Returns the parts of `range` that are not in the set.
+    pub fn subtract(&self, range: VirtAddrRange) -> Vec<VirtAddrRange> {
+        let mut result = Vec::new();
+        let mut start = range.start;
+        for r in self.overlapping(range) {
+            if r.start > start {
+                result.push(VirtAddrRange::new(start, r.start));
+            }
+            start = r.end;
+        }
+        if start < range.end {
+            result.push(VirtAddrRange::new(start, range.end));
+        }
+        result
+    }
+
+    /// Returns the total size of the ranges in bytes.
+    pub fn size(&self) -> usize {
+        self.ranges.iter().map(|(&start, &end)| end - start).sum()
+    }
+
+    /// Returns the range in the set that contains `addr`.
+    pub fn find(&self, addr: VirtAddr) -> Option<VirtAddrRange> {
+        self.ranges
+            .range(..=addr)
+            .next_back()
+            .filter(|&(_, &end)| end > addr)
+            .map(|(&start, &end)| VirtAddrRange::new(start, end))
+    }
+
+    /// Returns whether `addr` is in the set.
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.find(addr).is_some()
+    }
+
+    /// Adds `range` to the set.
+    pub fn insert(&mut self, range: VirtAddrRange) {
+        if range.is_empty() {
+            return;
+        }
+        self.remove(range);
+        let (mut start, mut end) = (range.start, range.end);
+        // Merge with the adjacent ranges.
+        if let Some((&prev_start, &prev_end)) = self.ranges.range(..start).next_back() {
+            if prev_end == start {
+                self.ranges.remove(&prev_start);
+                start = prev_start;
+            }
+        }
+        if let Some(next_end) = self.ranges.remove(&end) {
+            end = next_end;
+        }
+        self.ranges.insert(start, end);
+    }
+
+    /// Removes `range` from the set.
+    pub fn remove(&mut self, range: VirtAddrRange) {
+        if range.is_empty() {
+            return;
+        }
+        let overlapping: Vec<_> = self
+            .ranges
+            .range(..range.end)
+            .filter(|&(_, &end)| end > range.start)
+            .map(|(&start, &end)| (start, end))
+            .collect();
+        for (start, end) in overlapping {
+            self.ranges.remove(&start);
+            if start < range.start {
+                self.ranges.insert(start, range.start);
+            }
+            if end > range.end {
+                self.ranges.insert(range.end, end);
+            }
+        }
+    }
+
+    /// Removes all ranges from the set.
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+}
@@ -260,6 +260,9 @@ fn init_interrupt() {
         axtask::on_timer_tick();
     });
 
+    #[cfg(all(feature = "smp", feature = "paging"))]
+    axhal::irq::register_handler(axhal::irq::IPI_IRQ_NUM, axhal::tlb::handle_ipi);
+
     // Enable IRQs before starting app
     axhal::arch::enable_irqs();
 }
@@ -1,8 +1,10 @@
+use alloc::vec;
 use core::ffi::{c_char, c_void};
 
 use axerrno::{LinuxError, LinuxResult};
 use axfs::fops::DirEntry;
 use macro_rules_attribute::apply;
+use starry_core::mm::copy_to_user;
 
 use crate::{
     ptr::{PtrWrapper, UserConstPtr, UserPtr},
@@ -24,8 +26,8 @@ pub fn sys_ioctl(_fd: i32, _op: usize, _argp: UserPtr<c_void>) -> LinuxResult<is
 }
 
 pub fn sys_chdir(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
-    axfs::api::set_current_dir(path).map(|_| 0).map_err(|err| {
+    let path = path.read_str()?;
+    axfs::api::set_current_dir(&path).map(|_| 0).map_err(|err| {
         warn!("Failed to change directory: {err:?}");
         err.into()
     })
@@ -147,8 +149,8 @@ pub fn sys_linkat(
     new_path: UserConstPtr<c_char>,
     flags: i32,
 ) -> LinuxResult<isize> {
-    let old_path = old_path.get_as_null_terminated()?;
-    let new_path = new_path.get_as_null_terminated()?;
+    let old_path = old_path.read_c_string()?;
+    let new_path = new_path.read_c_string()?;
 
     if flags != 0 {
         warn!("Unsupported flags: {flags}");
@@ -178,5 +180,16 @@ pub fn sys_linkat(
 }
 
 pub fn sys_getcwd(buf: UserPtr<c_char>, size: usize) -> LinuxResult<isize> {
-    Ok(arceos_posix_api::sys_getcwd(buf.get_as_null_terminated()?.as_ptr() as _, size) as _)
+    // The path is built in a kernel buffer and copied out with its terminator.
+    let mut cwd = vec![0 as c_char; size];
+    let ret = arceos_posix_api::sys_getcwd(cwd.as_mut_ptr(), size);
+    if ret != cwd.as_mut_ptr() {
+        // The negative error code.
+        return Ok(ret as _);
+    }
+    let len = cwd.iter().position(|&c| c == 0).unwrap() + 1;
+    // SAFETY: `c_char` is a byte.
+    let bytes = unsafe { core::slice::from_raw_parts(cwd.as_ptr() as *const u8, len) };
+    copy_to_user(buf.address(), bytes)?;
+    Ok(buf.address().as_usize() as _)
 }
@@ -3,9 +3,12 @@ use core::ffi::c_int;
 use arceos_posix_api as api;
 use arceos_posix_api::{FD_TABLE, add_file_like, close_file_like, ctypes, get_file_like};
 use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{F_ADD_SEALS, F_GET_SEALS};
 use starry_core::resource::ResourceLimitType;
 use starry_core::task::current_process_data;
 
+use super::MemFile;
+
 pub fn sys_dup(old_fd: c_int) -> LinuxResult<isize> {
     let limit = current_process_data()
         .resource_limits
@@ -58,5 +61,12 @@ pub fn sys_close(fd: c_int) -> LinuxResult<isize> {
 }
 
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> LinuxResult<isize> {
-    Ok(api::sys_fcntl(fd, cmd, arg) as _)
+    match cmd as u32 {
+        F_ADD_SEALS => {
+            MemFile::from_fd(fd)?.add_seals(arg as _)?;
+            Ok(0)
+        }
+        F_GET_SEALS => Ok(MemFile::from_fd(fd)?.seals() as _),
+        _ => Ok(api::sys_fcntl(fd, cmd, arg) as _),
+    }
 }
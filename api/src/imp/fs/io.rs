@@ -1,18 +1,89 @@
+use alloc::ffi::CString;
 use core::ffi::{c_char, c_void};
 
+use super::MemFile;
+use super::sysctl::{sysctl_setter, write_sysctl};
+use crate::imp::utils::path::resolve_path_with_parent;
 use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
 use arceos_posix_api::ctypes::off_t;
-use arceos_posix_api::{self as api, ctypes::mode_t};
-use axerrno::LinuxResult;
+use arceos_posix_api::{self as api, AT_FDCWD, ctypes::mode_t};
+use axerrno::{LinuxError, LinuxResult};
 use axfs::fops::File;
+use axio::SeekFrom;
+use linux_raw_sys::general::O_TRUNC;
+use starry_core::page_cache::{InodeId, PAGE_CACHE};
+
+/// The maximum number of I/O vectors of `readv` and `writev`.
+const IOV_MAX: i32 = 1024;
+
+/// Returns the page cache inode of the file.
+pub fn cached_inode(file: &File) -> LinuxResult<InodeId> {
+    Ok(PAGE_CACHE.inode(file.get_node())?)
+}
+
+/// Reads a regular file at its offset through the page cache.
+fn file_read(file: &api::File, buf: &mut [u8]) -> LinuxResult<usize> {
+    let mut inner = file.inner().lock();
+    if !inner.is_readable() {
+        return Err(LinuxError::EBADF);
+    }
+    let inode = cached_inode(&inner)?;
+    let pos = inner.seek(SeekFrom::Current(0))?;
+    let len = PAGE_CACHE.read_at(inode, pos, buf)?;
+    inner.seek(SeekFrom::Start(pos + len as u64))?;
+    Ok(len)
+}
+
+/// Writes a regular file at its offset through the page cache.
+fn file_write(file: &api::File, buf: &[u8]) -> LinuxResult<usize> {
+    let mut inner = file.inner().lock();
+    if !inner.is_writable() {
+        return Err(LinuxError::EBADF);
+    }
+    let inode = cached_inode(&inner)?;
+    if let Some(setter) = sysctl_setter(file.path()) {
+        let len = write_sysctl(setter, inode, buf)?;
+        inner.seek(SeekFrom::Start(len as u64))?;
+        return Ok(len);
+    }
+    let pos = if inner.is_append() {
+        PAGE_CACHE.size(inode)?
+    } else {
+        inner.seek(SeekFrom::Current(0))?
+    };
+    let len = PAGE_CACHE.write_at(inode, pos, buf)?;
+    inner.seek(SeekFrom::Start(pos + len as u64))?;
+    Ok(len)
+}
+
+/// Returns the `iocnt` I/O vectors at `iov`, which must be no more than
+/// `IOV_MAX`.
+pub fn iovecs(
+    iov: UserConstPtr<api::ctypes::iovec>,
+    iocnt: i32,
+) -> LinuxResult<&'static [api::ctypes::iovec]> {
+    if !(0..=IOV_MAX).contains(&iocnt) {
+        return Err(LinuxError::EINVAL);
+    }
+    let iov = iov.get_as_array(iocnt as _)?;
+    Ok(unsafe { core::slice::from_raw_parts(iov, iocnt as _) })
+}
 
 pub fn sys_read(fd: i32, buf: UserPtr<c_void>, count: usize) -> LinuxResult<isize> {
     let buf = buf.get_as_bytes(count)?;
+    if let Ok(file) = api::File::from_fd(fd) {
+        let buf = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, count) };
+        return Ok(file_read(&file, buf)? as _);
+    }
     Ok(api::sys_read(fd, buf, count))
 }
 
 pub fn sys_write(fd: i32, buf: UserConstPtr<c_void>, count: usize) -> LinuxResult<isize> {
     let buf = buf.get_as_bytes(count)?;
+    if let Ok(file) = api::File::from_fd(fd) {
+        let buf = unsafe { core::slice::from_raw_parts(buf as *const u8, count) };
+        return Ok(file_write(&file, buf)? as _);
+    }
     Ok(api::sys_write(fd, buf, count))
 }
 
@@ -21,11 +92,37 @@ pub fn sys_writev(
     iov: UserConstPtr<api::ctypes::iovec>,
     iocnt: i32,
 ) -> LinuxResult<isize> {
+    if let Ok(file) = api::File::from_fd(fd) {
+        let mut written = 0;
+        for iov in iovecs(iov.address().as_usize().into(), iocnt)? {
+            let buf = UserConstPtr::<u8>::from(iov.iov_base as usize).get_as_bytes(iov.iov_len)?;
+            let buf = unsafe { core::slice::from_raw_parts(buf, iov.iov_len) };
+            let len = file_write(&file, buf)?;
+            written += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        return Ok(written as _);
+    }
     let iov = iov.get_as_bytes(iocnt as _)?;
     unsafe { Ok(api::sys_writev(fd, iov, iocnt)) }
 }
 
 pub fn sys_readv(fd: i32, iov: UserPtr<api::ctypes::iovec>, iocnt: i32) -> LinuxResult<isize> {
+    if let Ok(file) = api::File::from_fd(fd) {
+        let mut read = 0;
+        for iov in iovecs(iov.address().as_usize().into(), iocnt)? {
+            let buf = UserPtr::<u8>::from(iov.iov_base as usize).get_as_bytes(iov.iov_len)?;
+            let buf = unsafe { core::slice::from_raw_parts_mut(buf, iov.iov_len) };
+            let len = file_read(&file, buf)?;
+            read += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        return Ok(read as _);
+    }
     let iov = iov.get_as_bytes(iocnt as _)?;
     unsafe { Ok(api::sys_readv(fd, iov, iocnt)) }
 }
@@ -36,16 +133,32 @@ pub fn sys_openat(
     flags: i32,
     modes: mode_t,
 ) -> LinuxResult<isize> {
-    let path = path.get_as_null_terminated()?;
-    Ok(api::sys_openat(dirfd, path.as_ptr(), flags, modes) as _)
+    let path = path.read_str()?;
+    if path.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    // Open the file by its absolute path, which identifies it in the page cache.
+    let path = resolve_path_with_parent(dirfd, &path)?;
+    let c_path = CString::new(path.as_str()).map_err(|_| LinuxError::EINVAL)?;
+    let fd = api::sys_openat(AT_FDCWD, c_path.as_ptr(), flags, modes);
+    if fd >= 0 && flags as u32 & O_TRUNC != 0 {
+        // The file is truncated bypassing the cache.
+        if let Ok(file) = api::File::from_fd(fd) {
+            let inode = cached_inode(&file.inner().lock())?;
+            PAGE_CACHE.truncate(inode, 0)?;
+        }
+    }
+    Ok(fd as _)
 }
 
 pub fn sys_open(path: UserConstPtr<c_char>, flags: i32, modes: mode_t) -> LinuxResult<isize> {
-    use arceos_posix_api::AT_FDCWD;
     sys_openat(AT_FDCWD as _, path, flags, modes)
 }
 
 pub fn sys_lseek(fd: i32, offset: isize, whence: i32) -> LinuxResult<isize> {
+    if let Ok(file) = MemFile::from_fd(fd) {
+        return Ok(file.seek(offset, whence)? as _);
+    }
     Ok(api::sys_lseek(fd, offset as off_t, whence) as _)
 }
 
@@ -56,31 +169,78 @@ pub fn sys_sendfile(
     count: usize,
 ) -> LinuxResult<isize> {
     let offset = offset.nullable(UserPtr::get)?;
-    Ok(api::sys_sendfile(
+    // The files are read and written bypassing the cache.
+    if let Ok(file) = api::File::from_fd(in_fd) {
+        PAGE_CACHE.sync(cached_inode(&file.inner().lock())?)?;
+    }
+    let ret = api::sys_sendfile(
         out_fd,
         in_fd,
         offset.unwrap_or(core::ptr::null_mut()),
         count,
-    ) as _)
+    );
+    if let Ok(file) = api::File::from_fd(out_fd) {
+        PAGE_CACHE.invalidate(cached_inode(&file.inner().lock())?)?;
+    }
+    Ok(ret as _)
 }
 
+/// Changes the size of the file, whose write permission is checked by the
+/// caller.
 pub fn sys_truncate_impl(file: &File, length: isize) -> LinuxResult<isize> {
-    // set size to length
-    file.truncate(length as u64)
-        .map_err(|_| axerrno::LinuxError::EIO)?;
+    if length < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let inode = cached_inode(file)?;
+    PAGE_CACHE.truncate(inode, length as u64)?;
     Ok(0)
 }
 
 pub fn sys_pwrite_impl(fd: i32, buf: &[u8], offset: isize) -> LinuxResult<isize> {
+    if let Ok(file) = MemFile::from_fd(fd) {
+        return Ok(file.write_at(offset as _, buf)? as _);
+    }
     let file = api::File::from_fd(fd)?;
-    let file = file.inner();
-    let write_len = file.lock().write_at(offset as _, buf)?;
+    let inner = file.inner().lock();
+    if !inner.is_writable() {
+        return Err(LinuxError::EBADF);
+    }
+    let inode = cached_inode(&inner)?;
+    if let Some(setter) = sysctl_setter(file.path()) {
+        return Ok(write_sysctl(setter, inode, buf)? as _);
+    }
+    let write_len = PAGE_CACHE.write_at(inode, offset as _, buf)?;
     Ok(write_len as _)
 }
 
 pub fn sys_pread_impl(fd: i32, buf: &mut [u8], offset: isize) -> LinuxResult<isize> {
+    if let Ok(file) = MemFile::from_fd(fd) {
+        return Ok(file.read_at(offset as _, buf) as _);
+    }
     let file = api::File::from_fd(fd)?;
-    let file = file.inner();
-    let read_len = file.lock().read_at(offset as _, buf)?;
+    let inner = file.inner().lock();
+    if !inner.is_readable() {
+        return Err(LinuxError::EBADF);
+    }
+    let inode = cached_inode(&inner)?;
+    let read_len = PAGE_CACHE.read_at(inode, offset as _, buf)?;
     Ok(read_len as _)
 }
+
+pub fn sys_fsync(fd: i32) -> LinuxResult<isize> {
+    match api::File::from_fd(fd) {
+        Ok(file) => {
+            let inode = cached_inode(&file.inner().lock())?;
+            PAGE_CACHE.sync(inode)?;
+            Ok(0)
+        }
+        // Other files are not cached.
+        Err(LinuxError::EINVAL) => Ok(0),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn sys_sync() -> LinuxResult<isize> {
+    PAGE_CACHE.sync_all()?;
+    Ok(0)
+}
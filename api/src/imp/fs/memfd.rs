@@ -0,0 +1,393 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::ffi::{c_char, c_int, c_uint};
+
+use arceos_posix_api::{FileLike, add_file_like_cloexec, ctypes, get_file_like};
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+use axhal::mem::phys_to_virt;
+use axio::PollState;
+use axmm::MmapFile;
+use linux_raw_sys::general::{
+    F_SEAL_EXEC, F_SEAL_FUTURE_WRITE, F_SEAL_GROW, F_SEAL_SEAL, F_SEAL_SHRINK, F_SEAL_WRITE,
+    MFD_ALLOW_SEALING, MFD_CLOEXEC, MFD_EXEC, MFD_NOEXEC_SEAL, S_IFREG, SEEK_CUR, SEEK_END,
+    SEEK_SET,
+};
+use memory_addr::{PAGE_SIZE_4K, PhysAddr};
+use spin::Mutex;
+use starry_core::mm::unmap_shared_frames;
+use syscall_trace::syscall_trace;
+
+use crate::ptr::UserConstPtr;
+
+/// The maximum length of the name given to `memfd_create`, excluding the
+/// `memfd:` prefix.
+const MFD_NAME_MAX_LEN: usize = 249;
+
+/// All the seals that can be added by `F_ADD_SEALS`.
+const ALL_SEALS: c_uint =
+    F_SEAL_SEAL | F_SEAL_SHRINK | F_SEAL_GROW | F_SEAL_WRITE | F_SEAL_FUTURE_WRITE | F_SEAL_EXEC;
+
+/// A page of a memory file.
+struct Page(PhysAddr);
+
+impl Page {
+    fn new() -> LinuxResult<Self> {
+        axmm::alloc_frame(true).map(Page).ok_or(LinuxError::ENOMEM)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(phys_to_virt(self.0).as_ptr(), PAGE_SIZE_4K) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn as_mut_slice(&self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(self.0).as_mut_ptr(), PAGE_SIZE_4K) }
+    }
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        // The frame lives on while it is still mapped.
+        axmm::dealloc_frame(self.0);
+    }
+}
+
+struct MemFileInner {
+    /// The pages by their indices, holes are read as zeros
+    pages: BTreeMap<usize, Page>,
+    /// The size in bytes
+    size: usize,
+    /// The `F_SEAL_*` seals
+    seals: c_uint,
+}
+
+/// An anonymous file in memory, created by `memfd_create`.
+///
+/// Shared mappings map the pages of the file directly, so they are shared
+/// with all other mappings and file descriptors of it.
+pub struct MemFile {
+    name: String,
+    inner: Mutex<MemFileInner>,
+    /// The file offset for `read` and `write`
+    pos: Mutex<usize>,
+    /// Cloned by the shared mappings that may be written, which prevent
+    /// `F_SEAL_WRITE` from being added.
+    writers: Arc<()>,
+}
+
+impl MemFile {
+    fn new(name: String, seals: c_uint) -> Self {
+        Self {
+            name,
+            inner: Mutex::new(MemFileInner {
+                pages: BTreeMap::new(),
+                size: 0,
+                seals,
+            }),
+            pos: Mutex::new(0),
+            writers: Arc::new(()),
+        }
+    }
+
+    /// Creates the file of `size` bytes backing a shared anonymous mapping.
+    pub fn new_anonymous(size: usize) -> Arc<Self> {
+        let file = Self::new("/dev/zero (deleted)".into(), F_SEAL_SEAL);
+        file.inner.lock().size = size;
+        Arc::new(file)
+    }
+
+    /// Returns the memory file of the file descriptor `fd`.
+    pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        get_file_like(fd)?
+            .into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+
+    /// Returns the name given to `memfd_create`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// Returns the current seals, for `F_GET_SEALS`.
+    pub fn seals(&self) -> c_uint {
+        self.inner.lock().seals
+    }
+
+    /// Adds the seals, for `F_ADD_SEALS`.
+    pub fn add_seals(&self, seals: c_uint) -> LinuxResult {
+        if seals & !ALL_SEALS != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let mut inner = self.inner.lock();
+        if inner.seals & F_SEAL_SEAL != 0 {
+            return Err(LinuxError::EPERM);
+        }
+        // Writable shared mappings could still change the content.
+        if seals & F_SEAL_WRITE != 0
+            && inner.seals & F_SEAL_WRITE == 0
+            && Arc::strong_count(&self.writers) > 1
+        {
+            return Err(LinuxError::EBUSY);
+        }
+        inner.seals |= seals;
+        Ok(())
+    }
+
+    /// Returns whether `write` and new writable shared mappings are forbidden.
+    fn write_sealed(seals: c_uint) -> bool {
+        seals & (F_SEAL_WRITE | F_SEAL_FUTURE_WRITE) != 0
+    }
+
+    /// Reads the file at `offset`, returns the number of bytes read.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let inner = self.inner.lock();
+        if offset >= inner.size {
+            return 0;
+        }
+        let len = buf.len().min(inner.size - offset);
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let page_offset = pos % PAGE_SIZE_4K;
+            let count = (PAGE_SIZE_4K - page_offset).min(len - done);
+            let dst = &mut buf[done..done + count];
+            match inner.pages.get(&(pos / PAGE_SIZE_4K)) {
+                Some(page) => {
+                    dst.copy_from_slice(&page.as_slice()[page_offset..page_offset + count])
+                }
+                None => dst.fill(0),
+            }
+            done += count;
+        }
+        len
+    }
+
+    /// Writes `buf` into the file at `offset`, extending the file if needed,
+    /// returns the number of bytes written.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> LinuxResult<usize> {
+        let mut inner = self.inner.lock();
+        if Self::write_sealed(inner.seals) {
+            return Err(LinuxError::EPERM);
+        }
+        let end = offset.checked_add(buf.len()).ok_or(LinuxError::EFBIG)?;
+        if end > inner.size && inner.seals & F_SEAL_GROW != 0 {
+            return Err(LinuxError::EPERM);
+        }
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let page_offset = pos % PAGE_SIZE_4K;
+            let count = (PAGE_SIZE_4K - page_offset).min(buf.len() - done);
+            let index = pos / PAGE_SIZE_4K;
+            if !inner.pages.contains_key(&index) {
+                inner.pages.insert(index, Page::new()?);
+            }
+            inner.pages[&index].as_mut_slice()[page_offset..page_offset + count]
+                .copy_from_slice(&buf[done..done + count]);
+            done += count;
+        }
+        inner.size = inner.size.max(end);
+        Ok(buf.len())
+    }
+
+    /// Changes the size of the file, for `ftruncate`.
+    pub fn truncate(&self, size: usize) -> LinuxResult {
+        let mut inner = self.inner.lock();
+        if size < inner.size && inner.seals & F_SEAL_SHRINK != 0 {
+            return Err(LinuxError::EPERM);
+        }
+        if size > inner.size && inner.seals & F_SEAL_GROW != 0 {
+            return Err(LinuxError::EPERM);
+        }
+        let mut removed = BTreeMap::new();
+        if size < inner.size {
+            // Remove the pages beyond the end, and clear the rest of the last
+            // page, so that the file reads zeros if it grows again.
+            removed = inner.pages.split_off(&size.div_ceil(PAGE_SIZE_4K));
+            let page_offset = size % PAGE_SIZE_4K;
+            if page_offset != 0 {
+                if let Some(page) = inner.pages.get(&(size / PAGE_SIZE_4K)) {
+                    page.as_mut_slice()[page_offset..].fill(0);
+                }
+            }
+        }
+        inner.size = size;
+        // The address spaces are locked before the file when pages are mapped.
+        drop(inner);
+        if !removed.is_empty() {
+            // The removed pages are unmapped before they are freed, so that
+            // the mappings do not keep using them.
+            let frames = removed.values().map(|page| page.0).collect();
+            unmap_shared_frames(&frames);
+        }
+        Ok(())
+    }
+
+    /// Changes the file offset, for `lseek`.
+    pub fn seek(&self, offset: isize, whence: c_int) -> LinuxResult<usize> {
+        let mut pos = self.pos.lock();
+        let base = match whence as c_uint {
+            SEEK_SET => 0,
+            SEEK_CUR => *pos as isize,
+            SEEK_END => self.size() as isize,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let new_pos = base.checked_add(offset).ok_or(LinuxError::EOVERFLOW)?;
+        if new_pos < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        *pos = new_pos as usize;
+        Ok(*pos)
+    }
+
+    /// Returns the file for `mmap`.
+    ///
+    /// Shared mappings are not allowed to be writable once the file is sealed
+    /// for writing, otherwise they count as writers until they are unmapped.
+    pub fn mmap_file(
+        self: &Arc<Self>,
+        shared: bool,
+        writable: bool,
+    ) -> LinuxResult<Arc<dyn MmapFile>> {
+        let writer = if shared && Self::write_sealed(self.seals()) {
+            if writable {
+                return Err(LinuxError::EPERM);
+            }
+            None
+        } else if shared {
+            Some(self.writers.clone())
+        } else {
+            None
+        };
+        Ok(Arc::new(MemFileMapping {
+            file: self.clone(),
+            writer,
+        }))
+    }
+}
+
+impl FileLike for MemFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let mut pos = self.pos.lock();
+        let len = self.read_at(*pos, buf);
+        *pos += len;
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let mut pos = self.pos.lock();
+        let len = self.write_at(*pos, buf)?;
+        *pos += len;
+        Ok(len)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let inner = self.inner.lock();
+        let mode = if inner.seals & F_SEAL_EXEC != 0 {
+            0o666
+        } else {
+            0o777
+        };
+        Ok(ctypes::stat {
+            // TODO: true inode
+            st_ino: self as *const Self as usize as _,
+            st_nlink: 1,
+            st_mode: S_IFREG | mode,
+            st_uid: 1000,
+            st_gid: 1000,
+            st_size: inner.size as _,
+            st_blocks: (inner.pages.len() * PAGE_SIZE_4K / 512) as _,
+            st_blksize: PAGE_SIZE_4K as _,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// A memory file mapped by `mmap`.
+struct MemFileMapping {
+    file: Arc<MemFile>,
+    /// Held by shared mappings that may be written.
+    writer: Option<Arc<()>>,
+}
+
+impl MmapFile for MemFileMapping {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        Ok(self.file.read_at(offset as usize, buf))
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> AxResult<usize> {
+        // Shared mappings write to the pages of the file directly, there is
+        // nothing to write back.
+        Ok(buf.len())
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        Ok(self.file.size() as u64)
+    }
+
+    fn shared_frame(&self, offset: u64) -> AxResult<Option<PhysAddr>> {
+        let offset = offset as usize;
+        let mut inner = self.file.inner.lock();
+        if offset >= inner.size {
+            return Err(AxError::InvalidInput);
+        }
+        let index = offset / PAGE_SIZE_4K;
+        if !inner.pages.contains_key(&index) {
+            inner
+                .pages
+                .insert(index, Page::new().map_err(|_| AxError::NoMemory)?);
+        }
+        Ok(Some(inner.pages[&index].0))
+    }
+
+    fn writable(&self) -> bool {
+        self.writer.is_some()
+    }
+}
+
+#[syscall_trace]
+pub fn sys_memfd_create(name: UserConstPtr<c_char>, flags: c_uint) -> LinuxResult<isize> {
+    // TODO: support `MFD_HUGETLB`
+    if flags & !(MFD_CLOEXEC | MFD_ALLOW_SEALING | MFD_NOEXEC_SEAL | MFD_EXEC) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if flags & MFD_NOEXEC_SEAL != 0 && flags & MFD_EXEC != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let name = name.read_str()?;
+    if name.len() > MFD_NAME_MAX_LEN {
+        return Err(LinuxError::EINVAL);
+    }
+    // Without `MFD_ALLOW_SEALING`, the file is sealed against new seals.
+    let seals = if flags & MFD_NOEXEC_SEAL != 0 {
+        F_SEAL_EXEC
+    } else if flags & MFD_ALLOW_SEALING != 0 {
+        0
+    } else {
+        F_SEAL_SEAL
+    };
+    let file = MemFile::new(alloc::format!("memfd:{name}"), seals);
+    Ok(add_file_like_cloexec(Arc::new(file), flags & MFD_CLOEXEC != 0)? as _)
+}
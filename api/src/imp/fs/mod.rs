@@ -2,16 +2,19 @@ mod ctl;
 mod fd_ops;
 pub mod fs;
 mod io;
+mod memfd;
 mod mount;
 pub mod path;
 mod pipe;
 pub mod poll;
 mod stat;
 pub mod status;
+mod sysctl;
 
 pub use self::ctl::*;
 pub use self::fd_ops::*;
 pub use self::io::*;
+pub use self::memfd::*;
 pub use self::mount::*;
 pub use self::pipe::*;
 pub use self::stat::*;
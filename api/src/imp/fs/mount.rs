@@ -14,9 +14,9 @@ pub fn sys_mount(
     _data: UserConstPtr<c_void>,
 ) -> LinuxResult<isize> {
     info!("sys_mount");
-    let source = source.get_as_null_terminated()?;
-    let target = target.get_as_null_terminated()?;
-    let fs_type = fs_type.get_as_str()?;
+    let source = source.read_c_string()?;
+    let target = target.read_c_string()?;
+    let fs_type = fs_type.read_str()?;
     let device_path = handle_file_path(AT_FDCWD, Some(source.as_ptr() as _), false)?;
     let mount_path = handle_file_path(AT_FDCWD, Some(target.as_ptr() as _), true)?;
     info!(
@@ -48,7 +48,7 @@ pub fn sys_mount(
 
 pub fn sys_umount2(target: UserConstPtr<c_char>, flags: i32) -> LinuxResult<isize> {
     info!("sys_umount2");
-    let target = target.get_as_null_terminated()?;
+    let target = target.read_c_string()?;
     let mount_path = handle_file_path(AT_FDCWD, Some(target.as_ptr() as _), true)?;
     if flags != 0 {
         debug!("flags unimplemented");
@@ -1,9 +1,9 @@
 use crate::imp::utils::path::resolve_path_with_parent;
-use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
+use axfs::fops::{File, OpenOptions};
 use bitflags::bitflags;
 use linux_raw_sys::general::{RENAME_EXCHANGE, RENAME_NOREPLACE, RENAME_WHITEOUT};
+use starry_core::page_cache::PAGE_CACHE;
 
 bitflags! {
     #[derive(Debug)]
@@ -14,6 +14,13 @@ bitflags! {
     }
 }
 
+/// Opens the regular file at the absolute `path` to read, to get its node.
+fn open_file(path: &str) -> LinuxResult<File> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    Ok(File::open(path, &options)?)
+}
+
 pub fn sys_rename_impl(
     old_dir_fd: i32,
     old_path: &str,
@@ -28,6 +35,12 @@ pub fn sys_rename_impl(
         return Err(LinuxError::EEXIST);
     }
     // TODO:`EXCHANGE` and `WHITEOUT`
+    if let Ok(file) = open_file(&old_path) {
+        PAGE_CACHE.rename(file.get_node())?;
+    }
+    if let Ok(file) = open_file(&new_path) {
+        PAGE_CACHE.unlink(file.get_node())?;
+    }
     axfs::api::rename(&old_path, &new_path)?;
     Ok(0)
 }
@@ -64,17 +77,10 @@ pub fn sys_unlink_impl(dir_fd: i32, path: &str, flags: UnlinkFlags) -> LinuxResu
         if flags.contains(UnlinkFlags::NO_REMOVE_FILE) {
             return Err(LinuxError::ENOTDIR);
         }
-        // check if the file is opened
-        // TODO: remove the file after close
-        let mut option = OpenOptions::new();
-        option.read(true);
-        if let Ok(file) = axfs::fops::File::open(&path, &option) {
-            let node = file.get_node();
-            if Arc::strong_count(node) > 1 && path.as_str().starts_with("/tmp/tmpfile") {
-                // FIXME: currently, the way we check if the file is opened is not accurate
-                info!("[sys_unlink_impl] file is opened, cannot unlink");
-                return Err(LinuxError::EBUSY);
-            }
+        // The content of the file is kept in the page cache if it is still
+        // open or mapped.
+        if let Ok(file) = open_file(&path) {
+            PAGE_CACHE.unlink(file.get_node())?;
         }
         axfs::api::remove_file(&path)?;
     } else {
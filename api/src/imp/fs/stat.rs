@@ -148,7 +148,7 @@ impl FsType {
 // TODO: [dummy] return dummy values
 #[apply(syscall_instrument)]
 pub fn sys_statfs(path: UserConstPtr<c_char>, buf: UserPtr<StatFs>) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.read_c_string()?;
     let _ = arceos_posix_api::handle_file_path(-1, Some(path.as_ptr() as _), false)?;
 
     // dummy data
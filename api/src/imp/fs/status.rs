@@ -1,6 +1,7 @@
+use crate::imp::fs::MemFile;
 use crate::imp::fs::fs::open_file_like;
 use crate::imp::utils::path::resolve_path_with_parent;
-use arceos_posix_api::ctypes;
+use arceos_posix_api::{FileLike, ctypes};
 use axerrno::LinuxResult;
 
 /// File status
@@ -80,6 +81,12 @@ impl From<ctypes::timespec> for TimeSpec {
 /// [Availability] Most
 /// TODO: add support for symlink
 pub fn sys_stat_impl(dir_fd: i32, path: &str, _follow_symlinks: bool) -> LinuxResult<FileStatus> {
+    // memory files have no path to resolve
+    if path.is_empty() {
+        if let Ok(file) = MemFile::from_fd(dir_fd) {
+            return Ok(file.stat()?.into());
+        }
+    }
     let path = resolve_path_with_parent(dir_fd, path)?;
     let file = open_file_like(path.as_str(), None)?;
     let file_status: FileStatus = file.stat()?.into();
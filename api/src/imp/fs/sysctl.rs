@@ -0,0 +1,43 @@
+//! Kernel parameters under `/proc/sys`, which are applied when written.
+
+use alloc::format;
+use alloc::string::String;
+
+use axerrno::{LinuxError, LinuxResult};
+use starry_core::page_cache::{InodeId, PAGE_CACHE};
+use undefined_process::Pid;
+use undefined_process::pid::set_pid_max;
+
+/// Applies the value written to a parameter, and returns the value to be
+/// stored in its file. Fails with `EINVAL` if the value is invalid.
+type SysctlSetter = fn(&str) -> LinuxResult<String>;
+
+/// The writable parameters and their setters.
+const SYSCTLS: &[(&str, SysctlSetter)] = &[("/proc/sys/kernel/pid_max", set_pid_max_sysctl)];
+
+fn set_pid_max_sysctl(value: &str) -> LinuxResult<String> {
+    let pid_max: Pid = value.trim().parse().map_err(|_| LinuxError::EINVAL)?;
+    if !set_pid_max(pid_max) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(format!("{}\n", pid_max))
+}
+
+/// Returns the setter of the parameter at `path`, if it is one.
+pub fn sysctl_setter(path: &str) -> Option<SysctlSetter> {
+    SYSCTLS
+        .iter()
+        .find(|(sysctl, _)| *sysctl == path)
+        .map(|(_, setter)| *setter)
+}
+
+/// Writes `buf` to the parameter file `inode` with `setter`. The whole
+/// value is applied at once, replacing the content of the file, and
+/// nothing is written if it is rejected.
+pub fn write_sysctl(setter: SysctlSetter, inode: InodeId, buf: &[u8]) -> LinuxResult<usize> {
+    let value = core::str::from_utf8(buf).map_err(|_| LinuxError::EINVAL)?;
+    let value = setter(value)?;
+    PAGE_CACHE.truncate(inode, 0)?;
+    PAGE_CACHE.write_at(inode, 0, value.as_bytes())?;
+    Ok(buf.len())
+}
@@ -1,14 +1,57 @@
 use axerrno::LinuxResult;
+use axhal::paging::MappingFlags;
+use memory_addr::{VirtAddr, align_up_4k};
+use starry_core::resource::ResourceLimitType;
 use starry_core::task::current_process_data;
 use syscall_trace::syscall_trace;
 
 #[syscall_trace]
 pub fn sys_brk(addr: usize) -> LinuxResult<isize> {
-    let mut return_val: isize = current_process_data().get_heap_top() as isize;
-    let heap_bottom = current_process_data().get_heap_bottom();
-    if addr != 0 && addr >= heap_bottom && addr <= heap_bottom + axconfig::plat::USER_HEAP_SIZE {
-        current_process_data().set_heap_top(addr);
-        return_val = addr as isize;
+    let process_data = current_process_data();
+    let heap_bottom = process_data.get_heap_bottom();
+    let heap_top = process_data.get_heap_top();
+    // On failure, the current program break is returned.
+    if addr < heap_bottom {
+        return Ok(heap_top as isize);
     }
-    Ok(return_val)
+    let data_limit = process_data
+        .resource_limits
+        .lock()
+        .get_soft(&ResourceLimitType::DATA);
+    if (addr - heap_bottom) as u64 > data_limit {
+        return Ok(heap_top as isize);
+    }
+
+    let mut aspace = process_data.addr_space.lock();
+    // Checked before aligning, which would overflow near the top.
+    if addr > aspace.end().as_usize() {
+        return Ok(heap_top as isize);
+    }
+    // The heap area covers `[heap_bottom, align_up_4k(heap_top))`.
+    let old_end = align_up_4k(heap_top);
+    let new_end = align_up_4k(addr);
+    if new_end > old_end {
+        if process_data
+            .check_vm_limits(&aspace, new_end - old_end, true)
+            .is_err()
+        {
+            return Ok(heap_top as isize);
+        }
+        // Pages are allocated on demand. Fails if the range is already mapped.
+        if aspace
+            .map_alloc(
+                VirtAddr::from(old_end),
+                new_end - old_end,
+                MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+                false,
+            )
+            .is_err()
+        {
+            return Ok(heap_top as isize);
+        }
+    } else if new_end < old_end {
+        aspace.unmap(VirtAddr::from(new_end), old_end - new_end)?;
+    }
+    process_data.set_heap_top(addr);
+    Ok(addr as isize)
 }
@@ -1,14 +1,24 @@
-use crate::ptr::UserInPtr;
+use crate::imp::fs::{MemFile, cached_inode};
+use crate::ptr::{UserInPtr, UserOutPtr};
 use crate::{
     ptr::{PtrWrapper, UserPtr},
     syscall_instrument,
 };
-use alloc::vec;
-use axerrno::{LinuxError, LinuxResult};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{AxResult, LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
+use axmm::{AddrSpace, MmapFile};
+use linux_raw_sys::general::{
+    MADV_DODUMP, MADV_DOFORK, MADV_DONTDUMP, MADV_DONTFORK, MADV_DONTNEED, MADV_FREE,
+    MADV_HUGEPAGE, MADV_NOHUGEPAGE, MADV_NORMAL, MADV_RANDOM, MADV_SEQUENTIAL, MADV_WILLNEED,
+};
 use macro_rules_attribute::apply;
-use memory_addr::{VirtAddr, VirtAddrRange};
-use starry_core::task::current_process_data;
+use memory_addr::{MemoryAddr, PAGE_SIZE_2M, PAGE_SIZE_4K, PhysAddr, VirtAddr, VirtAddrRange};
+use starry_core::page_cache::{InodeId, PAGE_CACHE};
+use starry_core::process::ProcessData;
+use starry_core::resource::ResourceLimitType;
+use starry_core::task::{current_process, current_process_data};
 use syscall_trace::syscall_trace;
 
 bitflags::bitflags! {
@@ -24,7 +34,7 @@ bitflags::bitflags! {
         /// Page can be executed.
         const PROT_EXEC = 1 << 2;
         /// Extend change to start of growsdown vma (mprotect only).
-        const PROT_GROWDOWN = 0x01000000;
+        const PROT_GROWSDOWN = 0x01000000;
         /// Extend change to start of growsup vma (mprotect only).
         const PROT_GROWSUP = 0x02000000;
     }
@@ -60,10 +70,91 @@ bitflags::bitflags! {
         const MAP_FIXED = 1 << 4;
         /// Don't use a file.
         const MAP_ANONYMOUS = 1 << 5;
+        /// Stack-like segment, which grows down on page faults.
+        const MAP_GROWSDOWN = 1 << 8;
+        /// Lock the pages in memory, like `mlock`.
+        const MAP_LOCKED = 1 << 13;
         /// Don't check for reservations.
         const MAP_NORESERVE = 1 << 14;
         /// Allocation is for a stack.
         const MAP_STACK = 0x20000;
+        /// Create a huge page mapping.
+        const MAP_HUGETLB = 0x40000;
+    }
+}
+
+/// The shift of the huge page size encoded in the flags of `mmap`, as its log2.
+const MAP_HUGE_SHIFT: i32 = 26;
+/// The mask of the huge page size encoded in the flags of `mmap`.
+const MAP_HUGE_MASK: i32 = 0x3f;
+/// The log2 of the only huge page size supported, 2M.
+const MAP_HUGE_2MB: i32 = 21;
+
+bitflags::bitflags! {
+    /// flags for sys_mremap
+    ///
+    /// See <https://github.com/bminor/glibc/blob/master/sysdeps/unix/sysv/linux/bits/mman-linux.h>
+    #[derive(Debug)]
+    struct MremapFlags: i32 {
+        /// The mapping may be moved to a new address.
+        const MREMAP_MAYMOVE = 1 << 0;
+        /// The mapping must be moved to the given new address.
+        const MREMAP_FIXED = 1 << 1;
+    }
+}
+
+bitflags::bitflags! {
+    /// flags for sys_msync
+    ///
+    /// See <https://github.com/bminor/glibc/blob/master/bits/mman.h>
+    #[derive(Debug)]
+    struct MsyncFlags: i32 {
+        /// Sync memory asynchronously.
+        const MS_ASYNC = 1 << 0;
+        /// Invalidate the caches.
+        const MS_INVALIDATE = 1 << 1;
+        /// Synchronous memory sync.
+        const MS_SYNC = 1 << 2;
+    }
+}
+
+/// A regular file mapped through the page cache.
+struct MmapFileWrapper {
+    /// Keeps the file open, and so its pages cached, while it is mapped.
+    _file: Arc<arceos_posix_api::File>,
+    inode: InodeId,
+    /// Whether the file is opened for writing.
+    writable: bool,
+}
+
+impl MmapFile for MmapFileWrapper {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        PAGE_CACHE.read_at(self.inode, offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        PAGE_CACHE.write_at(self.inode, offset, buf)
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        PAGE_CACHE.size(self.inode)
+    }
+
+    fn shared_frame(&self, offset: u64) -> AxResult<Option<PhysAddr>> {
+        PAGE_CACHE.frame(self.inode, offset).map(Some)
+    }
+
+    fn set_dirty(&self, offset: u64) -> AxResult {
+        PAGE_CACHE.set_dirty(self.inode, offset);
+        Ok(())
+    }
+
+    fn writable(&self) -> bool {
+        self.writable
+    }
+
+    fn prefetch(&self, offset: u64) -> AxResult {
+        PAGE_CACHE.frame(self.inode, offset).map(|_| ())
     }
 }
 
@@ -85,15 +176,30 @@ pub fn sys_mmap(
     // TODO: check illegal flags for mmap
     // An example is the flags contained none of MAP_PRIVATE, MAP_SHARED, or MAP_SHARED_VALIDATE.
     let map_flags = MmapFlags::from_bits_truncate(flags);
+    let huge = map_flags.contains(MmapFlags::MAP_HUGETLB);
+    let page_size = if huge {
+        // Only anonymous mappings of 2M huge pages are supported.
+        let huge_size = (flags >> MAP_HUGE_SHIFT) & MAP_HUGE_MASK;
+        if (huge_size != 0 && huge_size != MAP_HUGE_2MB)
+            || !map_flags.contains(MmapFlags::MAP_ANONYMOUS)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        if map_flags.contains(MmapFlags::MAP_FIXED) && !(addr as usize).is_aligned(PAGE_SIZE_2M) {
+            return Err(LinuxError::EINVAL);
+        }
+        PAGE_SIZE_2M
+    } else {
+        PAGE_SIZE_4K
+    };
     let mut aligned_length = length;
 
     if addr.is_null() {
-        aligned_length = memory_addr::align_up_4k(aligned_length);
+        aligned_length = aligned_length.align_up(page_size);
     } else {
-        let start = addr as usize;
-        let mut end = start + aligned_length;
-        addr = memory_addr::align_down_4k(start) as *mut usize;
-        end = memory_addr::align_up_4k(end);
+        let start = (addr as usize).align_down(page_size);
+        let end = (addr as usize + aligned_length).align_up(page_size);
+        addr = start as *mut usize;
         aligned_length = end - start;
     }
 
@@ -102,61 +208,146 @@ pub fn sys_mmap(
         addr, length, permission_flags, map_flags, fd, offset
     );
 
-    let start_addr = if map_flags.contains(MmapFlags::MAP_FIXED) {
-        if addr.is_null() {
+    let shared = map_flags.contains(MmapFlags::MAP_SHARED);
+    let writable = permission_flags.contains(MmapProt::PROT_WRITE);
+    let anonymous = map_flags.contains(MmapFlags::MAP_ANONYMOUS) || fd == -1;
+    let file = if anonymous {
+        // Shared anonymous memory is an anonymous file, so that it stays
+        // shared with the children after `fork`.
+        (shared && !huge)
+            .then(|| MemFile::new_anonymous(aligned_length).mmap_file(true, writable))
+            .transpose()?
+    } else {
+        if offset < 0 || !memory_addr::is_aligned_4k(offset as usize) {
             return Err(LinuxError::EINVAL);
         }
-        let dst_addr = VirtAddr::from(addr as usize);
-        aspace.unmap(dst_addr, aligned_length)?;
-        dst_addr
-    } else {
-        aspace
-            .find_free_area(
-                VirtAddr::from(addr as usize),
-                aligned_length,
-                VirtAddrRange::new(aspace.base(), aspace.end()),
-            )
-            .or(aspace.find_free_area(
-                aspace.base(),
-                aligned_length,
-                VirtAddrRange::new(aspace.base(), aspace.end()),
-            ))
-            .ok_or(LinuxError::ENOMEM)?
+        let file = arceos_posix_api::get_file_like(fd)?.into_any();
+        let file: Arc<dyn MmapFile> = match file.downcast::<MemFile>() {
+            Ok(file) => file.mmap_file(shared, writable)?,
+            Err(file) => {
+                let file = file
+                    .downcast::<arceos_posix_api::File>()
+                    .map_err(|_| LinuxError::ENODEV)?;
+                let (inode, file_writable) = {
+                    let inner = file.inner().lock();
+                    // The file must be readable, and writable as well to be
+                    // mapped shared and writable.
+                    if !inner.is_readable() || (shared && writable && !inner.is_writable()) {
+                        return Err(LinuxError::EACCES);
+                    }
+                    (cached_inode(&inner)?, inner.is_writable())
+                };
+                Arc::new(MmapFileWrapper {
+                    _file: file,
+                    inode,
+                    writable: file_writable,
+                })
+            }
+        };
+        Some(file)
     };
 
-    let populate = if fd == -1 {
-        false
+    let fixed = map_flags.contains(MmapFlags::MAP_FIXED);
+    let start_addr = if fixed {
+        if addr.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        VirtAddr::from(addr as usize)
+    } else if !addr.is_null()
+        && is_free_area(
+            &aspace,
+            VirtAddr::from(addr as usize).align_up(page_size),
+            aligned_length,
+        )
+    {
+        // A free range at the hint is used as is, even if it is not aligned
+        // for transparent huge pages.
+        VirtAddr::from(addr as usize).align_up(page_size)
     } else {
-        !map_flags.contains(MmapFlags::MAP_ANONYMOUS)
+        // Without a hint, keep away from the heap so that `brk` can grow.
+        let hint = if addr.is_null() {
+            VirtAddr::from(current.get_mmap_base())
+        } else {
+            VirtAddr::from(addr as usize)
+        };
+        // Large private anonymous mappings are aligned to 2M as well, so that
+        // they can be backed by transparent huge pages.
+        let align = if file.is_none() && aligned_length >= PAGE_SIZE_2M {
+            PAGE_SIZE_2M
+        } else {
+            page_size
+        };
+        find_mmap_area(&aspace, hint, aligned_length, align).ok_or(LinuxError::ENOMEM)?
     };
+    // The limits are checked before the mappings replaced by `MAP_FIXED` are
+    // removed, counting them as freed.
+    let data = writable && !shared && !map_flags.contains(MmapFlags::MAP_GROWSDOWN);
+    if fixed {
+        current.check_vm_limits_replacing(&aspace, start_addr, aligned_length, data)?;
+    } else {
+        current.check_vm_limits(&aspace, aligned_length, data)?;
+    }
+    if map_flags.contains(MmapFlags::MAP_LOCKED) {
+        let new_locked = aspace.unlocked_size(start_addr, aligned_length);
+        check_memlock(&current, &aspace, new_locked, LinuxError::EAGAIN)?;
+    }
+    if fixed {
+        aspace.unmap(start_addr, aligned_length)?;
+        current.release_shared_memory(&aspace, current_process().get_pid());
+    }
 
-    aspace.map_alloc(
-        start_addr,
-        aligned_length,
-        permission_flags.into(),
-        populate,
-    )?;
-
-    if populate {
-        let file = arceos_posix_api::get_file_like(fd)?;
-        let file_size = file.stat()?.st_size as usize;
-        let file = file
-            .into_any()
-            .downcast::<arceos_posix_api::File>()
-            .map_err(|_| LinuxError::EBADF)?;
-        let file = file.inner().lock();
-        if offset < 0 || offset as usize >= file_size {
-            return Err(LinuxError::EINVAL);
+    if let Some(file) = file {
+        // The pages are read from the file on demand.
+        aspace.map_file(
+            start_addr,
+            aligned_length,
+            permission_flags.into(),
+            file,
+            if anonymous { 0 } else { offset as usize },
+            shared,
+        )?;
+    } else if huge {
+        aspace.map_huge_alloc(start_addr, aligned_length, permission_flags.into(), false)?;
+    } else {
+        aspace.map_alloc(start_addr, aligned_length, permission_flags.into(), false)?;
+        if map_flags.contains(MmapFlags::MAP_GROWSDOWN) {
+            aspace.set_growsdown(start_addr, aligned_length)?;
+        }
+        // Stacks are not backed by transparent huge pages, like Linux.
+        if map_flags.intersects(MmapFlags::MAP_STACK | MmapFlags::MAP_GROWSDOWN) {
+            aspace.set_hugepage(start_addr, aligned_length, false)?;
         }
-        let offset = offset as usize;
-        let length = core::cmp::min(length, file_size - offset);
-        let mut buf = vec![0u8; length];
-        file.read_at(offset as u64, &mut buf)?;
-        aspace.write(start_addr, &buf)?;
+    }
+    if map_flags.contains(MmapFlags::MAP_LOCKED) {
+        aspace.set_locked(start_addr, aligned_length, true, true)?;
     }
     Ok(start_addr.as_usize() as _)
 }
 
+/// Returns whether the range of `size` bytes at `start` is free and within
+/// the address space.
+fn is_free_area(aspace: &AddrSpace, start: VirtAddr, size: usize) -> bool {
+    let limit = VirtAddrRange::new(aspace.base(), aspace.end());
+    aspace.contains_range(start, size) && aspace.find_free_area(start, size, limit) == Some(start)
+}
+
+/// Finds a free range of `size` bytes aligned to `align` for a new mapping,
+/// trying from `hint` first.
+fn find_mmap_area(
+    aspace: &AddrSpace,
+    hint: VirtAddr,
+    size: usize,
+    align: usize,
+) -> Option<VirtAddr> {
+    // Reserve the room to align the start up.
+    let search_size = size + align - PAGE_SIZE_4K;
+    let limit = VirtAddrRange::new(aspace.base(), aspace.end());
+    aspace
+        .find_free_area(hint, search_size, limit)
+        .or(aspace.find_free_area(aspace.base(), search_size, limit))
+        .map(|start| start.align_up(align))
+}
+
 #[apply(syscall_instrument)]
 pub fn sys_munmap(addr: UserPtr<usize>, length: usize) -> LinuxResult<isize> {
     // Safety: addr is used for mapping, and we won't directly access it.
@@ -167,7 +358,7 @@ pub fn sys_munmap(addr: UserPtr<usize>, length: usize) -> LinuxResult<isize> {
     let length = memory_addr::align_up_4k(length);
     let start_addr = VirtAddr::from(addr as usize);
     aspace.unmap(start_addr, length)?;
-    axhal::arch::flush_tlb(None);
+    current.release_shared_memory(&aspace, current_process().get_pid());
     Ok(0)
 }
 
@@ -176,11 +367,43 @@ pub fn sys_mprotect(addr: UserInPtr<usize>, length: usize, prot: i32) -> LinuxRe
     // Safety: addr is used for mapping, and we won't directly access it.
     let addr = unsafe { addr.get_unchecked() };
 
-    // TODO: implement PROT_GROWSUP & PROT_GROWSDOWN
     let Some(permission_flags) = MmapProt::from_bits(prot) else {
         return Err(LinuxError::EINVAL);
     };
-    if permission_flags.contains(MmapProt::PROT_GROWDOWN | MmapProt::PROT_GROWSUP) {
+    // There are no mappings growing up.
+    if permission_flags.contains(MmapProt::PROT_GROWSUP) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let current = current_process_data();
+    let mut aspace = current.addr_space.lock();
+    let mut length = memory_addr::align_up_4k(length);
+    let mut start_addr = VirtAddr::from(addr as usize);
+    if permission_flags.contains(MmapProt::PROT_GROWSDOWN) {
+        // Extend the range down to the start of the stack.
+        let stack = aspace
+            .find_growsdown(start_addr)
+            .ok_or(LinuxError::EINVAL)?;
+        length += start_addr - stack.start;
+        start_addr = stack.start;
+    }
+    aspace.protect(start_addr, length, permission_flags.into())?;
+
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_msync(addr: UserInPtr<usize>, length: usize, flags: i32) -> LinuxResult<isize> {
+    // Safety: addr is used for mapping, and we won't directly access it.
+    let addr = unsafe { addr.get_unchecked() };
+
+    let Some(flags) = MsyncFlags::from_bits(flags) else {
+        return Err(LinuxError::EINVAL);
+    };
+    if flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC) {
+        return Err(LinuxError::EINVAL);
+    }
+    if !memory_addr::is_aligned_4k(addr as usize) {
         return Err(LinuxError::EINVAL);
     }
 
@@ -188,7 +411,370 @@ pub fn sys_mprotect(addr: UserInPtr<usize>, length: usize, prot: i32) -> LinuxRe
     let mut aspace = current.addr_space.lock();
     let length = memory_addr::align_up_4k(length);
     let start_addr = VirtAddr::from(addr as usize);
-    aspace.protect(start_addr, length, permission_flags.into())?;
+    if !aspace.contains_range(start_addr, length) {
+        return Err(LinuxError::ENOMEM);
+    }
+    // The pages are written back synchronously even for MS_ASYNC, and there
+    // is no cache to invalidate.
+    aspace.sync(start_addr, length)?;
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_mremap(
+    old_addr: UserInPtr<usize>,
+    old_size: usize,
+    new_size: usize,
+    flags: i32,
+    new_addr: UserInPtr<usize>,
+) -> LinuxResult<isize> {
+    // Safety: the addresses are used for mapping, and we won't directly access them.
+    let old_addr = unsafe { old_addr.get_unchecked() } as usize;
+    let new_addr = unsafe { new_addr.get_unchecked() } as usize;
+
+    let Some(flags) = MremapFlags::from_bits(flags) else {
+        return Err(LinuxError::EINVAL);
+    };
+    if flags.contains(MremapFlags::MREMAP_FIXED) && !flags.contains(MremapFlags::MREMAP_MAYMOVE) {
+        return Err(LinuxError::EINVAL);
+    }
+    // TODO: duplicate shared mappings when `old_size` is 0
+    if !memory_addr::is_aligned_4k(old_addr) || old_size == 0 || new_size == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let old_start = VirtAddr::from(old_addr);
+
+    let current = current_process_data();
+    let mut aspace = current.addr_space.lock();
+    // Huge page mappings are resized in whole huge pages.
+    let page_size = if aspace.is_hugetlb(old_start) {
+        if !old_start.is_aligned(PAGE_SIZE_2M) {
+            return Err(LinuxError::EINVAL);
+        }
+        PAGE_SIZE_2M
+    } else {
+        PAGE_SIZE_4K
+    };
+    let old_size = old_size.align_up(page_size);
+    let new_size = new_size.align_up(page_size);
+    if !aspace.contains_range(old_start, old_size)
+        || !aspace.check_region_access(
+            VirtAddrRange::from_start_size(old_start, old_size),
+            MappingFlags::empty(),
+        )
+    {
+        return Err(LinuxError::EFAULT);
+    }
+
+    let data = aspace.is_data(old_start);
+
+    if flags.contains(MremapFlags::MREMAP_FIXED) {
+        let new_start = VirtAddr::from(new_addr);
+        if !new_addr.is_aligned(page_size) || !aspace.contains_range(new_start, new_size) {
+            return Err(LinuxError::EINVAL);
+        }
+        if VirtAddrRange::from_start_size(new_start, new_size)
+            .overlaps(VirtAddrRange::from_start_size(old_start, old_size))
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        // Nothing is unmapped before the move is known to succeed.
+        current.check_vm_limits_moving(
+            &aspace,
+            (old_start, old_size),
+            (new_start, new_size),
+            data,
+        )?;
+        aspace.check_move_region(old_start, old_size.min(new_size), new_start)?;
+        aspace.unmap(new_start, new_size)?;
+        current.release_shared_memory(&aspace, current_process().get_pid());
+        move_mapping(&mut aspace, old_start, old_size, new_start, new_size)?;
+        return Ok(new_start.as_usize() as _);
+    }
+
+    if new_size > old_size {
+        current.check_vm_limits(&aspace, new_size - old_size, data)?;
+    }
+
+    if new_size <= old_size {
+        // Shrink in place.
+        if new_size < old_size {
+            aspace.unmap(old_start + new_size, old_size - new_size)?;
+            current.release_shared_memory(&aspace, current_process().get_pid());
+        }
+        return Ok(old_start.as_usize() as _);
+    }
+    if aspace.expand(old_start, old_size, new_size).is_ok() {
+        return Ok(old_start.as_usize() as _);
+    }
+    if !flags.contains(MremapFlags::MREMAP_MAYMOVE) {
+        return Err(LinuxError::ENOMEM);
+    }
+    let new_start = find_mmap_area(
+        &aspace,
+        VirtAddr::from(current.get_mmap_base()),
+        new_size,
+        page_size,
+    )
+    .ok_or(LinuxError::ENOMEM)?;
+    aspace.check_move_region(old_start, old_size, new_start)?;
+    move_mapping(&mut aspace, old_start, old_size, new_start, new_size)?;
+    Ok(new_start.as_usize() as _)
+}
+
+/// Moves the mapping at `old_start` to the free range at `new_start`, and
+/// resizes it from `old_size` to `new_size`.
+///
+/// The old tail of a shrunk mapping is only unmapped once the rest is moved.
+fn move_mapping(
+    aspace: &mut AddrSpace,
+    old_start: VirtAddr,
+    old_size: usize,
+    new_start: VirtAddr,
+    new_size: usize,
+) -> LinuxResult {
+    let moved_size = old_size.min(new_size);
+    aspace.move_region(old_start, moved_size, new_start)?;
+    if new_size < old_size {
+        aspace.unmap(old_start + new_size, old_size - new_size)?;
+    }
+    if new_size > moved_size {
+        aspace.expand(new_start, moved_size, new_size)?;
+    }
+    Ok(())
+}
+
+#[syscall_trace]
+pub fn sys_madvise(addr: UserInPtr<usize>, length: usize, advice: i32) -> LinuxResult<isize> {
+    // Safety: addr is used for mapping, and we won't directly access it.
+    let addr = unsafe { addr.get_unchecked() };
+
+    if !memory_addr::is_aligned_4k(addr as usize) {
+        return Err(LinuxError::EINVAL);
+    }
+    let current = current_process_data();
+    let mut aspace = current.addr_space.lock();
+    let length = memory_addr::align_up_4k(length);
+    let start_addr = VirtAddr::from(addr as usize);
+    if !aspace.contains_range(start_addr, length) {
+        return Err(LinuxError::ENOMEM);
+    }
+    if !aspace.check_region_access(
+        VirtAddrRange::from_start_size(start_addr, length),
+        MappingFlags::empty(),
+    ) {
+        return Err(LinuxError::ENOMEM);
+    }
+
+    match advice as u32 {
+        MADV_DONTNEED => aspace.discard(start_addr, length)?,
+        // The pages are freed at once, like MADV_DONTNEED, but only private
+        // anonymous pages can be freed.
+        MADV_FREE => {
+            if !aspace.is_private_anonymous(start_addr, length) {
+                return Err(LinuxError::EINVAL);
+            }
+            aspace.discard(start_addr, length)?
+        }
+        MADV_WILLNEED => aspace.populate_area(start_addr, length)?,
+        MADV_DONTFORK => aspace.set_dontfork(start_addr, length, true)?,
+        MADV_DOFORK => aspace.set_dontfork(start_addr, length, false)?,
+        MADV_HUGEPAGE => aspace.set_hugepage(start_addr, length, true)?,
+        MADV_NOHUGEPAGE => aspace.set_hugepage(start_addr, length, false)?,
+        // Hints which do not affect the behavior.
+        MADV_NORMAL | MADV_RANDOM | MADV_SEQUENTIAL | MADV_DONTDUMP | MADV_DODUMP => {}
+        _ => return Err(LinuxError::EINVAL),
+    }
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_mincore(
+    addr: UserInPtr<usize>,
+    length: usize,
+    vec: UserOutPtr<u8>,
+) -> LinuxResult<isize> {
+    // Safety: addr is used for mapping, and we won't directly access it.
+    let addr = unsafe { addr.get_unchecked() };
+
+    if !memory_addr::is_aligned_4k(addr as usize) {
+        return Err(LinuxError::EINVAL);
+    }
+    let length = memory_addr::align_up_4k(length);
+    let start_addr = VirtAddr::from(addr as usize);
+    let residency: Vec<u8> = {
+        let current = current_process_data();
+        let aspace = current.addr_space.lock();
+        if !aspace.contains_range(start_addr, length)
+            || !aspace.check_region_access(
+                VirtAddrRange::from_start_size(start_addr, length),
+                MappingFlags::empty(),
+            )
+        {
+            return Err(LinuxError::ENOMEM);
+        }
+        (0..length / PAGE_SIZE_4K)
+            .map(|i| {
+                let page = start_addr + i * PAGE_SIZE_4K;
+                aspace.is_resident(page) as u8
+            })
+            .collect()
+    };
+
+    // The address space must not be locked here, since checking `vec` may
+    // populate its pages.
+    let vec = vec.get_as_array(residency.len())?;
+    unsafe { core::ptr::copy_nonoverlapping(residency.as_ptr(), vec, residency.len()) };
+    Ok(0)
+}
+
+bitflags::bitflags! {
+    /// flags for sys_mlock2
+    #[derive(Debug)]
+    struct MlockFlags: u32 {
+        /// Lock the pages when they are faulted in, instead of populating them.
+        const MLOCK_ONFAULT = 1 << 0;
+    }
+}
 
+bitflags::bitflags! {
+    /// flags for sys_mlockall
+    #[derive(Debug)]
+    struct MlockallFlags: i32 {
+        /// Lock all current mappings.
+        const MCL_CURRENT = 1 << 0;
+        /// Lock all future mappings.
+        const MCL_FUTURE = 1 << 1;
+        /// Lock the pages when they are faulted in, instead of populating them.
+        const MCL_ONFAULT = 1 << 2;
+    }
+}
+
+/// Checks whether `size` more bytes can be locked in `aspace` within
+/// `RLIMIT_MEMLOCK`, and returns `err` if not.
+///
+/// Like Linux, nothing can be locked by unprivileged processes if the limit is
+/// 0, and `EPERM` is returned.
+fn check_memlock(
+    current: &ProcessData,
+    aspace: &AddrSpace,
+    size: usize,
+    err: LinuxError,
+) -> LinuxResult {
+    let limit = current
+        .resource_limits
+        .lock()
+        .get_soft(&ResourceLimitType::MEMLOCK);
+    if limit == 0 {
+        return Err(LinuxError::EPERM);
+    }
+    if (aspace.locked_size() as u64).saturating_add(size as u64) > limit {
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Returns the page-aligned range covering `[addr, addr + length)`, for `mlock`
+/// and `munlock`.
+fn lock_range(aspace: &AddrSpace, addr: usize, length: usize) -> LinuxResult<(VirtAddr, usize)> {
+    let start = memory_addr::align_down_4k(addr);
+    let end = addr
+        .checked_add(length)
+        .filter(|&end| end <= usize::MAX - PAGE_SIZE_4K)
+        .map(memory_addr::align_up_4k)
+        .ok_or(LinuxError::EINVAL)?;
+    let start_addr = VirtAddr::from(start);
+    if !aspace.contains_range(start_addr, end - start)
+        || !aspace.check_region_access(
+            VirtAddrRange::new(start_addr, end.into()),
+            MappingFlags::empty(),
+        )
+    {
+        return Err(LinuxError::ENOMEM);
+    }
+    Ok((start_addr, end - start))
+}
+
+fn mlock_impl(addr: usize, length: usize, flags: MlockFlags) -> LinuxResult<isize> {
+    let current = current_process_data();
+    let mut aspace = current.addr_space.lock();
+    let (start, length) = lock_range(&aspace, addr, length)?;
+    check_memlock(
+        &current,
+        &aspace,
+        aspace.unlocked_size(start, length),
+        LinuxError::ENOMEM,
+    )?;
+    aspace.set_locked(
+        start,
+        length,
+        true,
+        !flags.contains(MlockFlags::MLOCK_ONFAULT),
+    )?;
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_mlock(addr: UserInPtr<usize>, length: usize) -> LinuxResult<isize> {
+    // Safety: addr is used for mapping, and we won't directly access it.
+    let addr = unsafe { addr.get_unchecked() };
+    mlock_impl(addr as usize, length, MlockFlags::empty())
+}
+
+#[syscall_trace]
+pub fn sys_mlock2(addr: UserInPtr<usize>, length: usize, flags: u32) -> LinuxResult<isize> {
+    // Safety: addr is used for mapping, and we won't directly access it.
+    let addr = unsafe { addr.get_unchecked() };
+    let Some(flags) = MlockFlags::from_bits(flags) else {
+        return Err(LinuxError::EINVAL);
+    };
+    mlock_impl(addr as usize, length, flags)
+}
+
+#[syscall_trace]
+pub fn sys_munlock(addr: UserInPtr<usize>, length: usize) -> LinuxResult<isize> {
+    // Safety: addr is used for mapping, and we won't directly access it.
+    let addr = unsafe { addr.get_unchecked() };
+
+    let current = current_process_data();
+    let mut aspace = current.addr_space.lock();
+    let (start, length) = lock_range(&aspace, addr as usize, length)?;
+    aspace.set_locked(start, length, false, false)?;
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_mlockall(flags: i32) -> LinuxResult<isize> {
+    let flags = match MlockallFlags::from_bits(flags) {
+        Some(flags) if flags.intersects(MlockallFlags::MCL_CURRENT | MlockallFlags::MCL_FUTURE) => {
+            flags
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let populate = !flags.contains(MlockallFlags::MCL_ONFAULT);
+
+    let current = current_process_data();
+    let mut aspace = current.addr_space.lock();
+    let limit = current
+        .resource_limits
+        .lock()
+        .get_soft(&ResourceLimitType::MEMLOCK);
+    if limit == 0 {
+        return Err(LinuxError::EPERM);
+    }
+    if flags.contains(MlockallFlags::MCL_CURRENT) {
+        // Like Linux, all mappings are counted, including those locked already.
+        if aspace.mapped_size() as u64 > limit {
+            return Err(LinuxError::ENOMEM);
+        }
+        aspace.lock_all(populate);
+    }
+    aspace.set_lock_future(flags.contains(MlockallFlags::MCL_FUTURE), populate);
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_munlockall() -> LinuxResult<isize> {
+    current_process_data().addr_space.lock().unlock_all();
     Ok(0)
 }
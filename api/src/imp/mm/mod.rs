@@ -1,5 +1,7 @@
 mod brk;
 mod mmap;
+mod process_vm;
 
 pub use self::brk::*;
 pub use self::mmap::*;
+pub use self::process_vm::*;
@@ -0,0 +1,164 @@
+use crate::imp::fs::iovecs;
+use crate::ptr::UserConstPtr;
+use alloc::vec;
+use alloc::vec::Vec;
+use arceos_posix_api::ctypes::iovec;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
+use starry_core::mm::{copy_from_user, copy_to_user};
+use starry_core::process::get_process_data;
+use starry_core::task::{current_process, current_process_data};
+use syscall_trace::syscall_trace;
+use undefined_process::Pid;
+use undefined_process::process::get_process;
+
+/// Checks whether the current process may access the memory of the process
+/// `pid`, like `ptrace` attaching to it.
+///
+/// Access is granted to the ancestors of the target, and to processes with the
+/// same credentials when the target is dumpable.
+fn check_access(pid: Pid) -> LinuxResult {
+    let target = get_process(pid).ok_or(LinuxError::ESRCH)?;
+    let target_data = get_process_data(pid).ok_or(LinuxError::ESRCH)?;
+    let current = current_process();
+    let mut ancestor = Some(target);
+    while let Some(process) = ancestor {
+        if process.get_pid() == current.get_pid() {
+            return Ok(());
+        }
+        ancestor = process.get_parent();
+    }
+    let current = current_process_data().credentials();
+    let target = target_data.credentials();
+    let same_credentials = target.uid == current.uid
+        && target.euid == current.uid
+        && target.gid == current.gid
+        && target.egid == current.gid;
+    if same_credentials && target_data.is_dumpable() {
+        Ok(())
+    } else {
+        Err(LinuxError::EPERM)
+    }
+}
+
+/// Copies between the local I/O vectors of the current process and the remote
+/// ones of the process `pid`, in the order of the vectors.
+///
+/// The caller must be allowed to access the memory of the process, see
+/// `check_access`.
+///
+/// The remote memory is accessed through the address space of the target
+/// page by page, with a buffer in between, so the address space is never
+/// locked while the local memory is accessed. The copy stops at the first
+/// page which cannot be accessed, either local or remote.
+fn process_vm_rw(
+    pid: Pid,
+    local_iov: UserConstPtr<iovec>,
+    liovcnt: usize,
+    remote_iov: UserConstPtr<iovec>,
+    riovcnt: usize,
+    flags: usize,
+    write: bool,
+) -> LinuxResult<isize> {
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let liovcnt = i32::try_from(liovcnt).map_err(|_| LinuxError::EINVAL)?;
+    let riovcnt = i32::try_from(riovcnt).map_err(|_| LinuxError::EINVAL)?;
+    let local_iov = iovecs(local_iov, liovcnt)?;
+    let remote_iov = iovecs(remote_iov, riovcnt)?;
+    for iov in remote_iov {
+        if (iov.iov_base as usize).checked_add(iov.iov_len).is_none() {
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    check_access(pid)?;
+    let target = get_process_data(pid).ok_or(LinuxError::ESRCH)?;
+
+    let local = local_iov
+        .iter()
+        .filter(|iov| iov.iov_len > 0)
+        .map(|iov| (VirtAddr::from(iov.iov_base as usize), iov.iov_len))
+        .collect::<Vec<_>>();
+    let access_flags = if write {
+        MappingFlags::WRITE
+    } else {
+        MappingFlags::READ
+    };
+
+    let mut buf = vec![0u8; PAGE_SIZE_4K];
+    let mut local = local.into_iter();
+    let Some(mut local_buf) = local.next() else {
+        return Ok(0);
+    };
+    let mut copied = 0;
+    'remote: for iov in remote_iov {
+        let mut offset = 0;
+        while offset < iov.iov_len {
+            if local_buf.1 == 0 {
+                match local.next() {
+                    Some(next) => local_buf = next,
+                    None => break 'remote,
+                }
+            }
+            let remote_addr = VirtAddr::from(iov.iov_base as usize + offset);
+            let len = (iov.iov_len - offset)
+                .min(local_buf.1)
+                .min(PAGE_SIZE_4K - remote_addr.align_offset_4k());
+            let chunk = &mut buf[..len];
+            if write && copy_from_user(chunk, local_buf.0).is_err() {
+                break 'remote;
+            }
+            {
+                let mut aspace = target.addr_space.lock();
+                if aspace.fault_in(remote_addr, len, access_flags) < len {
+                    break 'remote;
+                }
+                let result = if write {
+                    aspace.write(remote_addr, chunk)
+                } else {
+                    aspace.read(remote_addr, chunk)
+                };
+                if result.is_err() {
+                    break 'remote;
+                }
+            }
+            if !write && copy_to_user(local_buf.0, chunk).is_err() {
+                break 'remote;
+            }
+            local_buf = (local_buf.0 + len, local_buf.1 - len);
+            offset += len;
+            copied += len;
+        }
+    }
+    // Like Linux, a partial copy is not an error.
+    if copied == 0 && remote_iov.iter().any(|iov| iov.iov_len > 0) {
+        return Err(LinuxError::EFAULT);
+    }
+    Ok(copied as _)
+}
+
+#[syscall_trace]
+pub fn sys_process_vm_readv(
+    pid: Pid,
+    local_iov: UserConstPtr<iovec>,
+    liovcnt: usize,
+    remote_iov: UserConstPtr<iovec>,
+    riovcnt: usize,
+    flags: usize,
+) -> LinuxResult<isize> {
+    process_vm_rw(pid, local_iov, liovcnt, remote_iov, riovcnt, flags, false)
+}
+
+#[syscall_trace]
+pub fn sys_process_vm_writev(
+    pid: Pid,
+    local_iov: UserConstPtr<iovec>,
+    liovcnt: usize,
+    remote_iov: UserConstPtr<iovec>,
+    riovcnt: usize,
+    flags: usize,
+) -> LinuxResult<isize> {
+    process_vm_rw(pid, local_iov, liovcnt, remote_iov, riovcnt, flags, true)
+}
@@ -117,7 +117,9 @@ pub fn sys_clone_impl(
         let page_table = current_process_data().addr_space.lock().page_table_root();
         new_task.ctx_mut().set_page_table_root(page_table);
 
-        let thread = current_process().create_thread();
+        let thread = current_process()
+            .create_thread()
+            .ok_or(LinuxError::EAGAIN)?;
         let thread_data = create_thread_data(current_process_data().clone(), thread.get_tid());
         // signals
         // for thread, there should be no exit_signal,
@@ -133,7 +135,7 @@ pub fn sys_clone_impl(
             // we clone the `Arc` itself rather than the data
             current_process_data().addr_space.clone()
         } else {
-            // clone the address space
+            // clone the address space, pages are shared copy-on-write
             let addr_space = &current_process_data().addr_space;
             let mut addr_space = addr_space.lock();
             let mut new_addr_space = addr_space.clone_or_err()?;
@@ -156,7 +158,7 @@ pub fn sys_clone_impl(
             Arc::default()
         };
         // fork new process
-        let new_process = parent.fork();
+        let new_process = parent.fork().ok_or(LinuxError::EAGAIN)?;
         let new_thread = new_process.get_main_thread().unwrap();
         let process_data = ProcessData::new(
             current_process_data().command_line.lock().clone(),
@@ -164,6 +166,16 @@ pub fn sys_clone_impl(
             signal_actions,
             exit_signal,
         );
+        // the heap is part of the (cloned or shared) address space
+        process_data.set_heap_bottom(current_process_data().get_heap_bottom());
+        process_data.set_heap_top(current_process_data().get_heap_top());
+        process_data.set_mmap_base(current_process_data().get_mmap_base());
+        process_data.set_credentials(current_process_data().credentials());
+        process_data.set_dumpable(current_process_data().is_dumpable());
+        // a copied address space keeps the shared memory segments attached
+        if !clone_flags.contains(CloneFlags::VM) {
+            process_data.inherit_shared_memory(&current_process_data(), new_process.get_pid());
+        }
         let thread_data = create_thread_data(Arc::new(process_data), new_thread.get_tid());
 
         (new_thread, thread_data)
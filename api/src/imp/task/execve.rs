@@ -1,13 +1,17 @@
 use alloc::string::String;
 use alloc::vec::Vec;
-use axerrno::{AxError, LinuxResult};
-use axhal::arch::{TrapFrame};
+use arceos_posix_api::close_cloexec_files;
+use axerrno::LinuxResult;
+use axhal::arch::TrapFrame;
+use axsignal::Signo;
 use axtask::current;
 use core::default::Default;
 use starry_core::mm;
-use starry_core::mm::map_trampoline;
+use starry_core::resource::ResourceLimitType;
 use starry_core::task::{current_process, current_process_data};
 
+use crate::imp::task::exit_by_signal;
+
 pub fn sys_execve_impl(
     tf: &mut TrapFrame,
     path: String,
@@ -33,33 +37,47 @@ pub fn sys_execve_impl(
 
     let process_data = current_process_data();
 
-    // clear address space
+    let stack_limit = process_data
+        .resource_limits
+        .lock()
+        .get_soft(&ResourceLimitType::STACK);
+    let app = mm::read_user_app(&path, &args, &envs, stack_limit).inspect_err(|err| {
+        error!("Failed to read app {}: {:?}", path, err);
+    })?;
+
+    // load executable binary, which replaces the old address space
     let addr_space = &process_data.addr_space;
     let mut addr_space = addr_space.lock();
-    addr_space.unmap_user_areas()?;
-    // for signals
-    map_trampoline(&mut addr_space)?;
-    axhal::arch::flush_tlb(None);
-
-    // load executable binary
-    let (entry_point, user_stack_base) =
-        mm::load_user_app(&mut addr_space, &args, &envs).map_err(|_| {
-            error!("Failed to load app {}", path);
-            AxError::NotFound
-        })?;
+    let (entry_point, user_stack_base, heap_bottom, mmap_base) =
+        match mm::load_user_app(&mut addr_space, &app, process_data.credentials()) {
+            Ok(result) => result,
+            Err(err) => {
+                // Like Linux, the process is killed, as the old program is gone.
+                error!("Failed to load app {}: {:?}", path, err);
+                drop(addr_space);
+                exit_by_signal(Signo::SIGSEGV, false);
+            }
+        };
 
     // set name and path
     current().set_name(&path);
     *process_data.command_line.lock() = args;
+    process_data.set_heap_bottom(heap_bottom.as_usize());
+    process_data.set_heap_top(heap_bottom.as_usize());
+    process_data.set_mmap_base(mmap_base.as_usize());
+    process_data.set_did_exec();
+    process_data.set_dumpable(true);
 
     // reset some process attributes
-    // TODO: reset signal dispositions, mmap, shm, etc.
+    // TODO: reset signal dispositions, mmap, etc.
     *process_data.signal.actions.lock() = Default::default();
-    process_data.shared_memory.lock().clear();
+    process_data.detach_shared_memory(&mut addr_space, current_process().get_pid());
+    drop(addr_space);
+    close_cloexec_files();
 
     // new user context
     tf.set_ip(entry_point.as_usize());
     tf.set_sp(user_stack_base.as_usize());
-    
+
     Ok(0)
 }
@@ -1,36 +1,48 @@
-use crate::imp::task::signal::{send_signal_process, send_signal_thread};
+use super::wait::child_info_status;
+use crate::imp::task::signal::{make_child_info, send_signal_process, send_signal_thread};
 use crate::ptr::{PtrWrapper, UserPtr};
 use arceos_posix_api::close_all_file_like;
 use axsignal::{SignalInfo, Signo};
 use core::sync::atomic::Ordering;
 use linux_raw_sys::general::SI_KERNEL;
-use starry_core::process::get_process_data;
+use starry_core::process::{CpuTimes, get_process_data};
 use starry_core::task::{
-    current_process, current_process_data, current_thread, current_thread_data,
+    current_process, current_process_data, current_thread, current_thread_data, time_stat_output,
 };
 use undefined_process::Pid;
 
 pub fn sys_exit_impl(exit_code: i32, exit_group: bool) -> ! {
+    do_exit((exit_code & 0xff) << 8, exit_group)
+}
+
+/// Exits the current process killed by `signo`, reporting a core dump to the
+/// parent if `core_dump` is `true`.
+pub fn exit_by_signal(signo: Signo, core_dump: bool) -> ! {
+    do_exit(signo as i32 | if core_dump { 0x80 } else { 0 }, true)
+}
+
+/// Exits the current thread, or the whole process if `exit_group` is `true`,
+/// with the wait status `status` reported by `wait4`.
+fn do_exit(status: i32, exit_group: bool) -> ! {
     {
         if exit_group {
             info!(
-                "[exit] process {} exiting with code {}",
+                "[exit] process {} exiting with status {:#x}",
                 current_process().get_pid(),
-                exit_code
+                status
             );
         } else {
             info!(
-                "[exit] thread {} exiting with code {}",
+                "[exit] thread {} exiting with status {:#x}",
                 current_thread().get_tid(),
-                exit_code
+                status
             );
         }
         let addr_clear_child_tid = current_thread_data()
             .addr_clear_child_tid
             .load(Ordering::Relaxed);
         let addr_clear_child_tid = UserPtr::<Pid>::from(addr_clear_child_tid);
-        if let Ok(ptr) = addr_clear_child_tid.get() {
-            unsafe { ptr.write(0) };
+        if addr_clear_child_tid.write(0).is_ok() {
             // TODO: wake up threads, which are blocked by futex, and waiting for the address pointed by clear_child_tid
             let table = &current_process_data().futex_table;
             let addr = addr_clear_child_tid.address().as_usize();
@@ -40,16 +52,40 @@ pub fn sys_exit_impl(exit_code: i32, exit_group: bool) -> ! {
             });
             axtask::yield_now();
         }
-        current_thread().exit(exit_code);
+        let process_data = current_process_data();
+        let status = process_data.exit_status(status, exit_group);
+        let (_, utime_us, _, stime_us) = time_stat_output();
+        process_data.add_thread_times(CpuTimes { utime_us, stime_us });
+        if let Some((reaper, zombies)) = current_thread().exit(status) {
+            // zombie children adopted by the reaper notified only their old parent
+            if let Some(reaper_data) = get_process_data(reaper.get_pid()) {
+                for zombie in zombies {
+                    let (code, status) = child_info_status(zombie.get_exit_code());
+                    let sig = make_child_info(Signo::SIGCHLD, code, zombie.get_pid(), status);
+                    let _ = send_signal_process(reaper.get_pid(), sig);
+                }
+                reaper_data.child_exit_wq.notify_all(false)
+            }
+        }
         let process = current_process();
         if process.is_zombie() {
             // threads have exited
+            // write back shared file mappings, so that the parent can see them
+            let mut addr_space = process_data.addr_space.lock();
+            if let Err(err) = addr_space.sync_all() {
+                warn!("[exit] failed to write back shared mappings: {:?}", err);
+            }
+            process_data.detach_shared_memory(&mut addr_space, process.get_pid());
+            drop(addr_space);
             // send signals
             if let Some(parent) = process.get_parent() {
                 if let Some(parent_data) = get_process_data(parent.get_pid()) {
-                    let signal = parent_data.exit_signal.unwrap_or(Signo::SIGCHLD);
-                    let _ =
-                        send_signal_process(parent.get_pid(), SignalInfo::new(signal, SI_KERNEL));
+                    // "clone" children may exit without a signal
+                    if let Some(signal) = process_data.exit_signal {
+                        let (code, status) = child_info_status(status);
+                        let sig = make_child_info(signal, code, process.get_pid(), status);
+                        let _ = send_signal_process(parent.get_pid(), sig);
+                    }
                     parent_data.child_exit_wq.notify_all(false)
                 }
             }
@@ -65,5 +101,5 @@ pub fn sys_exit_impl(exit_code: i32, exit_group: bool) -> ! {
             }
         }
     }
-    axtask::exit(exit_code)
+    axtask::exit(status)
 }
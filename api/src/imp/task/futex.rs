@@ -30,7 +30,7 @@ pub fn sys_futex(
     let command = futex_op & (FUTEX_CMD_MASK as u32);
     match command {
         FUTEX_WAIT => {
-            if *uaddr.get_as_ref()? != value {
+            if uaddr.read()? != value {
                 return Err(LinuxError::EAGAIN);
             }
             let wq = futex_table
@@ -40,7 +40,7 @@ pub fn sys_futex(
                 .clone();
 
             if !timeout.is_null() {
-                wq.wait_timeout(timespec_to_timevalue(*timeout.get_as_ref()?), false);
+                wq.wait_timeout(timespec_to_timevalue(timeout.read()?), false);
             } else {
                 wq.wait();
             }
@@ -62,7 +62,7 @@ pub fn sys_futex(
             Ok(count)
         }
         FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
-            if command == FUTEX_CMP_REQUEUE && *uaddr.get_as_ref()? != value3 {
+            if command == FUTEX_CMP_REQUEUE && uaddr.read()? != value3 {
                 return Err(LinuxError::EAGAIN);
             }
             let value2 = timeout.address().as_usize() as u32;
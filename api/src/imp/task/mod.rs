@@ -2,7 +2,9 @@ mod clone;
 mod execve;
 mod exit;
 mod futex;
+mod pidfd;
 pub mod schedule;
+mod session;
 pub mod signal;
 mod thread;
 mod wait;
@@ -12,6 +14,8 @@ pub use self::clone::*;
 pub use self::execve::*;
 pub use self::exit::*;
 pub use self::futex::*;
+pub use self::pidfd::*;
 pub use self::schedule::*;
+pub use self::session::*;
 pub use self::thread::*;
 pub use self::wait::*;
@@ -0,0 +1,93 @@
+use alloc::sync::Arc;
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use arceos_posix_api::{FileLike, add_file_like, ctypes, get_file_like};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use linux_raw_sys::general::{O_NONBLOCK, S_IFREG};
+use syscall_trace::syscall_trace;
+use undefined_process::Pid;
+use undefined_process::process::{Process, get_process};
+
+/// A process file descriptor, opened by `pidfd_open`, which refers to a
+/// process and can be waited for with `waitid(P_PIDFD)`.
+///
+/// It refers to the process itself rather than its ID, so it keeps referring
+/// to the same process after the ID is reused. It becomes readable when the
+/// process exits.
+pub struct PidFd {
+    process: Arc<Process>,
+    nonblocking: AtomicBool,
+}
+
+impl PidFd {
+    /// Returns the process file of the file descriptor `fd`.
+    pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        get_file_like(fd)?
+            .into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::EBADF)
+    }
+
+    /// Returns the process referred to by the file.
+    pub fn process(&self) -> &Arc<Process> {
+        &self.process
+    }
+
+    /// Returns `true` if the file is opened with `PIDFD_NONBLOCK`, in which
+    /// case `waitid` fails with `EAGAIN` instead of blocking.
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Relaxed)
+    }
+}
+
+impl FileLike for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: self as *const Self as usize as _,
+            st_nlink: 1,
+            st_mode: S_IFREG | 0o600,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.process.is_zombie(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[syscall_trace]
+pub fn sys_pidfd_open(pid: i32, flags: u32) -> LinuxResult<isize> {
+    // `PIDFD_NONBLOCK` is `O_NONBLOCK`
+    if flags & !O_NONBLOCK != 0 || pid <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    // Only processes, i.e. thread group leaders, can be referred to.
+    let process = get_process(pid as Pid).ok_or(LinuxError::ESRCH)?;
+    let file = PidFd {
+        process,
+        nonblocking: AtomicBool::new(flags & O_NONBLOCK != 0),
+    };
+    Ok(add_file_like(Arc::new(file))? as _)
+}
@@ -12,3 +12,15 @@ pub fn sys_nanosleep(
 ) -> LinuxResult<isize> {
     unsafe { Ok(api::sys_nanosleep(req.get()?, rem.get()?) as _) }
 }
+
+/// Returns the CPU the thread runs on, in `cpu`, and its NUMA node, which is
+/// always 0, in `node`. Either may be null.
+pub fn sys_getcpu(cpu: UserPtr<u32>, node: UserPtr<u32>) -> LinuxResult<isize> {
+    if !cpu.is_null() {
+        cpu.write(axhal::cpu::this_cpu_id() as _)?;
+    }
+    if !node.is_null() {
+        node.write(0)?;
+    }
+    Ok(0)
+}
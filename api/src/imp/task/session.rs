@@ -0,0 +1,74 @@
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use starry_core::process::get_process_data;
+use starry_core::task::current_process;
+use syscall_trace::syscall_trace;
+use undefined_process::Pid;
+use undefined_process::process::{Process, get_process};
+
+/// Returns the process `pid`, or the current process if `pid` is 0.
+fn process_or_current(pid: i32) -> LinuxResult<Arc<Process>> {
+    match pid {
+        0 => Ok(current_process()),
+        1.. => get_process(pid as Pid).ok_or(LinuxError::ESRCH),
+        _ => Err(LinuxError::ESRCH),
+    }
+}
+
+#[syscall_trace]
+pub fn sys_setpgid(pid: i32, pgid: i32) -> LinuxResult<isize> {
+    if pgid < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let current = current_process();
+    let process = process_or_current(pid)?;
+    let pid = process.get_pid();
+    if pid != current.get_pid() {
+        // Only the process itself and its children can be moved.
+        if process
+            .get_parent()
+            .is_none_or(|parent| parent.get_pid() != current.get_pid())
+        {
+            return Err(LinuxError::ESRCH);
+        }
+        if process.get_session().get_sid() != current.get_session().get_sid() {
+            return Err(LinuxError::EPERM);
+        }
+        if get_process_data(pid).is_some_and(|data| data.did_exec()) {
+            return Err(LinuxError::EACCES);
+        }
+    }
+    if process.is_session_leader() {
+        return Err(LinuxError::EPERM);
+    }
+    if pgid == 0 || pgid as Pid == pid {
+        process.create_group();
+    } else if !process.move_to_group(pgid as Pid) {
+        // The group does not exist in the session of the process.
+        return Err(LinuxError::EPERM);
+    }
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_getpgid(pid: i32) -> LinuxResult<isize> {
+    Ok(process_or_current(pid)?.get_group().get_pgid() as _)
+}
+
+#[syscall_trace]
+pub fn sys_getpgrp() -> LinuxResult<isize> {
+    Ok(current_process().get_group().get_pgid() as _)
+}
+
+#[syscall_trace]
+pub fn sys_setsid() -> LinuxResult<isize> {
+    let session = current_process()
+        .create_session()
+        .ok_or(LinuxError::EPERM)?;
+    Ok(session.get_sid() as _)
+}
+
+#[syscall_trace]
+pub fn sys_getsid(pid: i32) -> LinuxResult<isize> {
+    Ok(process_or_current(pid)?.get_session().get_sid() as _)
+}
@@ -3,25 +3,33 @@ use core::{mem, time::Duration};
 use arceos_posix_api::ctypes::timespec;
 use axerrno::{LinuxError, LinuxResult};
 use linux_raw_sys::general::{
-    MINSIGSTKSZ, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, kernel_sigaction, siginfo,
+    BUS_ADRALN, CLD_CONTINUED, CLD_STOPPED, FPE_FLTINV, FPE_INTDIV, ILL_ILLOPC, ILL_PRVOPC,
+    MINSIGSTKSZ, SA_NOCLDSTOP, SEGV_ACCERR, SI_KERNEL, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK,
+    SIG_UNBLOCK, TRAP_BRKPT, TRAP_TRACE, kernel_sigaction, siginfo,
 };
 
 use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
 
-use crate::imp::task::sys_exit_impl;
+use crate::imp::task::exit_by_signal;
 use axhal::{
     arch::TrapFrame,
-    trap::{POST_TRAP, register_trap_handler},
+    mem::VirtAddr,
+    trap::{POST_TRAP, USER_EXCEPTION, UserException, register_trap_handler},
+};
+use axsignal::{
+    SignalAction, SignalDisposition, SignalInfo, SignalOSAction, SignalSet, SignalStack, Signo,
 };
-use axsignal::{SignalInfo, SignalOSAction, SignalSet, SignalStack, Signo};
 use starry_core::process::{get_process_data, get_thread_data};
-use starry_core::task::{current_process, current_process_data, current_thread_data};
+use starry_core::task::{
+    current_process, current_process_data, current_thread, current_thread_data,
+};
 use undefined_process::Pid;
-use undefined_process::process::get_all_processes;
+use undefined_process::process::{Process, get_all_processes, get_process};
 use undefined_process::process_group::get_process_group;
 use undefined_process::thread::get_thread;
 
 fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) -> bool {
+    drop_discarded_signals();
     let signal = &current_thread_data().signal;
     let Some((sig, os_action)) = signal.check_signals(tf, restore_blocked) else {
         return false;
@@ -30,18 +38,22 @@ fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) -> bool
     let signo = sig.signo();
     match os_action {
         SignalOSAction::Terminate => {
-            sys_exit_impl(128 + signo as i32, true);
+            exit_by_signal(signo, false);
         }
         SignalOSAction::CoreDump => {
             // TODO: implement core dump
-            sys_exit_impl(128 + signo as i32, true);
+            exit_by_signal(signo, true);
         }
         SignalOSAction::Stop => {
-            // TODO: implement stop
-            sys_exit_impl(1, true);
+            // The threads are parked on the way back to user mode.
+            let process = current_process();
+            if current_process_data().stop(signo) {
+                notify_parent_job_status(&process, CLD_STOPPED, signo);
+            }
         }
         SignalOSAction::Continue => {
-            // TODO: implement continue
+            // The process is continued when `SIGCONT` is sent, see
+            // `complete_signal`.
         }
         SignalOSAction::Handler => {
             // do nothing
@@ -56,7 +68,189 @@ fn post_trap_callback(tf: &mut TrapFrame, from_user: bool) {
         return;
     }
 
-    check_signals(tf, None);
+    // A stopped thread is parked until the process is continued, then checks
+    // the signals again, e.g. to run the `SIGCONT` handler.
+    loop {
+        check_signals(tf, None);
+        let process_data = current_process_data();
+        if !process_data.is_stopped() {
+            break;
+        }
+        process_data
+            .stop_wq
+            .wait_until(|| !process_data.is_stopped());
+    }
+}
+
+/// Returns the signal `signo` reporting a state change of the child `pid` to
+/// its parent, e.g. `SIGCHLD`.
+///
+/// `code` is one of the `CLD_*` codes, and `status` the exit code or the
+/// signal causing the change.
+pub fn make_child_info(signo: Signo, code: u32, pid: Pid, status: i32) -> SignalInfo {
+    let mut sig = SignalInfo::new(signo, code);
+    unsafe {
+        let sigchld = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigchld;
+        sigchld._pid = pid as _;
+        sigchld._status = status;
+    }
+    sig
+}
+
+/// Notifies the parent of `process` of a job control state change with
+/// `SIGCHLD`, unless it has set `SA_NOCLDSTOP`, and wakes it up in `wait4`.
+///
+/// `code` is `CLD_STOPPED` or `CLD_CONTINUED`, and `signo` the signal causing
+/// the change.
+fn notify_parent_job_status(process: &Process, code: u32, signo: Signo) {
+    let Some(parent) = process.get_parent() else {
+        return;
+    };
+    let Some(parent_data) = get_process_data(parent.get_pid()) else {
+        return;
+    };
+    let mut action: kernel_sigaction = unsafe { mem::zeroed() };
+    parent_data.signal.actions.lock()[Signo::SIGCHLD].to_ctype(&mut action);
+    if action.sa_flags as u32 & SA_NOCLDSTOP == 0 {
+        let sig = make_child_info(Signo::SIGCHLD, code, process.get_pid(), signo as _);
+        let _ = send_signal_process(parent.get_pid(), sig);
+    }
+    parent_data.child_exit_wq.notify_all(false);
+}
+
+/// Returns the set of the signals `signos`.
+fn signal_set(signos: &[Signo]) -> SignalSet {
+    let mut set = SignalSet::default();
+    for &signo in signos {
+        set.add(signo);
+    }
+    set
+}
+
+/// Discards the pending signals of the process `pid` canceled by `signo`,
+/// before it is queued.
+///
+/// Like Linux, `SIGCONT` discards the pending stop signals, and the stop
+/// signals discard a pending `SIGCONT`. The threads remove them from their
+/// pending sets themselves, see `drop_discarded_signals`.
+fn prepare_signal(pid: Pid, signo: Signo) {
+    let discarded = match signo {
+        Signo::SIGCONT => signal_set(&[
+            Signo::SIGSTOP,
+            Signo::SIGTSTP,
+            Signo::SIGTTIN,
+            Signo::SIGTTOU,
+        ]),
+        Signo::SIGSTOP | Signo::SIGTSTP | Signo::SIGTTIN | Signo::SIGTTOU => {
+            signal_set(&[Signo::SIGCONT])
+        }
+        _ => return,
+    };
+    if let Some(process_data) = get_process_data(pid) {
+        process_data.discard_signals(signo, discarded);
+    }
+}
+
+/// Handles the job control of the process `pid` after `signo` is queued for
+/// it or for one of its threads, as a stopped process does not check its
+/// signals.
+///
+/// `SIGCONT` continues the process, even if it is blocked or ignored, and
+/// `SIGKILL` wakes it up to be killed.
+fn complete_signal(pid: Pid, signo: Signo) {
+    let Some(process_data) = get_process_data(pid) else {
+        return;
+    };
+    match signo {
+        Signo::SIGCONT => {
+            if process_data.resume(true) {
+                if let Some(process) = get_process(pid) {
+                    notify_parent_job_status(&process, CLD_CONTINUED, signo);
+                }
+            }
+        }
+        Signo::SIGKILL => {
+            process_data.resume(false);
+        }
+        _ => {}
+    }
+}
+
+/// Removes the pending signals discarded by a signal sent later from the
+/// pending set of the current thread and of its process, see
+/// `prepare_signal`.
+fn drop_discarded_signals() {
+    let signal = &current_thread_data().signal;
+    let mut discarded = current_process_data().discarded_signals();
+    discarded &= signal.pending();
+    if discarded == SignalSet::default() {
+        return;
+    }
+    // Only blocked signals can be dequeued without being delivered.
+    let blocked = signal.with_blocked_mut(|blocked| {
+        let old = *blocked;
+        *blocked |= discarded;
+        old
+    });
+    while signal
+        .wait_timeout(discarded, Some(Duration::ZERO))
+        .is_some()
+    {}
+    signal.with_blocked_mut(|mask| *mask = blocked);
+}
+
+/// Sends a signal caused by a fault of the current thread, e.g. `SIGSEGV`.
+///
+/// `addr` is reported to the handler in `si_addr`. Like Linux, the signal
+/// cannot be blocked or ignored: the default action is restored in that case,
+/// otherwise the thread would fault again at the same instruction forever.
+pub fn send_fault_signal(signo: Signo, code: u32, addr: VirtAddr) {
+    info!(
+        "Send fault signal {:?} to thread {} at {:#x}",
+        signo,
+        current_thread().get_tid(),
+        addr
+    );
+    let mut sig = SignalInfo::new(signo, code);
+    unsafe {
+        sig.0
+            .__bindgen_anon_1
+            .__bindgen_anon_1
+            ._sifields
+            ._sigfault
+            ._addr = addr.as_mut_ptr() as _;
+    }
+
+    let signal = &current_thread_data().signal;
+    let blocked = signal.with_blocked_mut(|blocked| {
+        let was_blocked = blocked.has(signo);
+        blocked.remove(signo);
+        was_blocked
+    });
+    let mut actions = current_process_data().signal.actions.lock();
+    if blocked || matches!(actions[signo].disposition, SignalDisposition::Ignore) {
+        actions[signo] = SignalAction::default();
+    }
+    drop(actions);
+    signal.send_signal(sig);
+}
+
+#[register_trap_handler(USER_EXCEPTION)]
+fn handle_user_exception(_tf: &mut TrapFrame, exception: UserException) -> bool {
+    let (signo, code, addr) = match exception {
+        UserException::IllegalInstruction(pc) => (Signo::SIGILL, ILL_ILLOPC, pc),
+        UserException::PrivilegedInstruction(pc) => (Signo::SIGILL, ILL_PRVOPC, pc),
+        // Linux reports general protection faults without an address.
+        UserException::GeneralProtection(_) => (Signo::SIGSEGV, SI_KERNEL, VirtAddr::from(0)),
+        UserException::DivideByZero(pc) => (Signo::SIGFPE, FPE_INTDIV, pc),
+        UserException::FloatingPoint(pc) => (Signo::SIGFPE, FPE_FLTINV, pc),
+        UserException::Misaligned(addr) => (Signo::SIGBUS, BUS_ADRALN, addr),
+        UserException::AccessFault(addr) => (Signo::SIGSEGV, SEGV_ACCERR, addr),
+        UserException::Breakpoint(pc) => (Signo::SIGTRAP, TRAP_BRKPT, pc),
+        UserException::SingleStep(pc) => (Signo::SIGTRAP, TRAP_TRACE, pc),
+    };
+    send_fault_signal(signo, code, addr);
+    true
 }
 
 fn check_sigset_size(size: usize) -> LinuxResult<()> {
@@ -135,13 +329,25 @@ pub fn sys_rt_sigpending(set: UserPtr<SignalSet>, sigsetsize: usize) -> LinuxRes
 pub fn send_signal_thread(tid: Pid, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to thread {}", sig.signo(), tid);
     let thread_data = get_thread_data(tid).ok_or(LinuxError::EPERM)?;
+    let signo = sig.signo();
+    let pid = get_thread(tid).map(|thread| thread.get_process().get_pid());
+    if let Some(pid) = pid {
+        prepare_signal(pid, signo);
+    }
+    // The signal is queued before the stopped threads are woken up to check it.
     thread_data.signal.send_signal(sig);
+    if let Some(pid) = pid {
+        complete_signal(pid, signo);
+    }
     Ok(())
 }
 pub fn send_signal_process(pid: Pid, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to process {}", sig.signo(), pid);
     let process_data = get_process_data(pid).ok_or(LinuxError::EPERM)?;
+    let signo = sig.signo();
+    prepare_signal(pid, signo);
     process_data.signal.send_signal(sig);
+    complete_signal(pid, signo);
     Ok(())
 }
 pub fn send_signal_process_group(pgid: Pid, sig: SignalInfo) -> usize {
@@ -1,14 +1,21 @@
-use crate::ptr::UserInPtr;
+use crate::ptr::{UserConstPtr, UserInPtr};
 use crate::{
     ptr::{PtrWrapper, UserPtr},
     syscall_instrument,
 };
-use axerrno::LinuxResult;
+use axerrno::{LinuxError, LinuxResult};
 use axhal::arch::TrapFrame;
+use core::ffi::c_char;
 use core::sync::atomic::Ordering;
+use linux_raw_sys::prctl::{
+    PR_GET_CHILD_SUBREAPER, PR_GET_DUMPABLE, PR_GET_NAME, PR_SET_CHILD_SUBREAPER, PR_SET_DUMPABLE,
+    PR_SET_NAME,
+};
 use macro_rules_attribute::apply;
 use num_enum::TryFromPrimitive;
-use starry_core::task::{current_process, current_thread, current_thread_data};
+use starry_core::task::{
+    current_process, current_process_data, current_thread, current_thread_data,
+};
 use syscall_trace::syscall_trace;
 
 /// ARCH_PRCTL codes
@@ -60,10 +67,47 @@ pub fn sys_set_tid_address(tid_ptd: UserInPtr<i32>) -> LinuxResult<isize> {
     Ok(current_thread().get_tid() as _)
 }
 
+/// The size of the thread names for `PR_SET_NAME` and `PR_GET_NAME`,
+/// including the terminating null byte.
+const TASK_COMM_LEN: usize = 16;
+
+#[syscall_trace]
+pub fn sys_prctl(option: u32, arg2: usize) -> LinuxResult<isize> {
+    match option {
+        PR_SET_CHILD_SUBREAPER => current_process().set_child_subreaper(arg2 != 0),
+        PR_GET_CHILD_SUBREAPER => {
+            UserPtr::<i32>::from(arg2).write(current_process().is_child_subreaper() as _)?
+        }
+        PR_SET_DUMPABLE => match arg2 {
+            0 | 1 => current_process_data().set_dumpable(arg2 != 0),
+            _ => return Err(LinuxError::EINVAL),
+        },
+        PR_GET_DUMPABLE => return Ok(current_process_data().is_dumpable() as _),
+        PR_SET_NAME => {
+            let name = UserConstPtr::<c_char>::from(arg2).read_str()?;
+            let mut len = name.len().min(TASK_COMM_LEN - 1);
+            while !name.is_char_boundary(len) {
+                len -= 1;
+            }
+            axtask::current().set_name(&name[..len]);
+        }
+        PR_GET_NAME => {
+            let mut buf = [0u8; TASK_COMM_LEN];
+            let name = axtask::current().name().as_bytes();
+            let len = name.len().min(TASK_COMM_LEN - 1);
+            buf[..len].copy_from_slice(&name[..len]);
+            UserPtr::<[u8; TASK_COMM_LEN]>::from(arg2).write(buf)?
+        }
+        _ => {
+            warn!("Unsupported prctl option: {}, bypassed", option);
+        }
+    }
+    Ok(0)
+}
+
 #[cfg(target_arch = "x86_64")]
 #[apply(syscall_instrument)]
 pub fn sys_arch_prctl(code: i32, addr: UserPtr<u64>, tf: &mut TrapFrame) -> LinuxResult<isize> {
-    use axerrno::LinuxError;
     debug!(
         "arch_prctl: code = {:?}, addr = {:#x}",
         ArchPrctlCode::try_from(code),
@@ -102,4 +146,3 @@ pub fn sys_arch_prctl(code: i32, addr: UserPtr<u64>, tf: &mut TrapFrame) -> Linu
 }
 
 // TODO: [stub] The method signature is not correct yet
-
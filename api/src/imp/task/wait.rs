@@ -1,10 +1,17 @@
+use super::pidfd::PidFd;
+use crate::imp::task::signal::make_child_info;
 use crate::ptr::{PtrWrapper, UserOutPtr};
 use alloc::{sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
+use axsignal::Signo;
 use bitflags::bitflags;
+use core::mem;
 use linux_raw_sys::general::{
-    __WALL, __WCLONE, __WNOTHREAD, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED,
+    __WALL, __WCLONE, __WNOTHREAD, CLD_CONTINUED, CLD_DUMPED, CLD_EXITED, CLD_KILLED, CLD_STOPPED,
+    P_ALL, P_PGID, P_PID, P_PIDFD, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED, rusage,
+    siginfo,
 };
+use starry_core::process::{CpuTimes, JobStatus, get_process_data};
 use starry_core::task::{current_process, current_process_data};
 use syscall_trace::syscall_trace;
 use undefined_process::Pid;
@@ -16,7 +23,8 @@ bitflags! {
         /// Do not block when there are no processes wishing to report status.
         const WNOHANG = WNOHANG;
         /// Report the status of selected processes which are stopped due to a
-        /// `SIGTTIN`, `SIGTTOU`, `SIGTSTP`, or `SIGSTOP` signal.
+        /// `SIGTTIN`, `SIGTTOU`, `SIGTSTP`, or `SIGSTOP` signal, also known as
+        /// `WSTOPPED`.
         const WUNTRACED = WUNTRACED;
         /// Report the status of selected processes which have terminated.
         const WEXITED = WEXITED;
@@ -35,12 +43,14 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone)]
 enum WaitPid {
     /// Wait for any child process
     Any,
     /// Wait for the child whose process ID is equal to the value.
     Pid(Pid),
+    /// Wait for the child which is the process, e.g. referred to by a pidfd.
+    Process(Arc<Process>),
     /// Wait for any child process whose process group ID is equal to the value.
     Pgid(Pid),
 }
@@ -50,66 +60,213 @@ impl WaitPid {
         match self {
             WaitPid::Any => true,
             WaitPid::Pid(pid) => child.get_pid() == *pid,
+            WaitPid::Process(process) => Arc::ptr_eq(child, process),
             WaitPid::Pgid(pgid) => child.get_group().get_pgid() == *pgid,
         }
     }
 }
 
-#[syscall_trace]
-pub fn sys_wait4(pid: i32, exit_code_ptr: UserOutPtr<i32>, options: u32) -> LinuxResult<isize> {
-    let options = WaitOptions::from_bits_truncate(options);
-    info!("sys_waitpid <= pid: {:?}, options: {:?}", pid, options);
-    error!("{} is waiting", current_process().get_pid());
+/// A state change of a child reported by `wait4` and `waitid`.
+#[derive(Debug, Clone, Copy)]
+enum ChildStatus {
+    /// Exited or killed, with the wait status.
+    Exited(i32),
+    /// Stopped by the signal.
+    Stopped(Signo),
+    /// Continued by `SIGCONT`.
+    Continued,
+}
 
-    let process = current_process();
-    let process_data = current_process_data();
+impl ChildStatus {
+    /// Returns the wait status reported by `wait4`.
+    fn wait_status(self) -> i32 {
+        match self {
+            ChildStatus::Exited(status) => status,
+            ChildStatus::Stopped(signo) => ((signo as i32) << 8) | 0x7f,
+            ChildStatus::Continued => 0xffff,
+        }
+    }
 
-    let pid = if pid == -1 {
-        WaitPid::Any
-    } else if pid == 0 {
-        WaitPid::Pgid(process.get_group().get_pgid())
-    } else if pid > 0 {
-        WaitPid::Pid(pid as _)
-    } else {
-        WaitPid::Pgid(-pid as _)
-    };
+    /// Returns the `si_code` and `si_status` reported by `waitid`.
+    fn info_status(self) -> (u32, i32) {
+        match self {
+            ChildStatus::Exited(status) => child_info_status(status),
+            ChildStatus::Stopped(signo) => (CLD_STOPPED, signo as _),
+            ChildStatus::Continued => (CLD_CONTINUED, Signo::SIGCONT as _),
+        }
+    }
+}
 
-    let children = process
-        .get_children()
-        .into_iter()
-        .filter(|child| pid.apply(child))
-        // .filter(|child| {
-        //     options.contains(WaitOptions::WALL)
-        //         || (options.contains(WaitOptions::WCLONE)
-        //             == get_process_data(child.get_pid()).is_some_and(|x| x.is_clone_child()))
-        // })
-        .collect::<Vec<_>>();
-    if children.is_empty() {
-        return Err(LinuxError::ECHILD);
+/// Returns the `si_code` and `si_status` reporting the exit of a child with
+/// the wait status `status`.
+pub(super) fn child_info_status(status: i32) -> (u32, i32) {
+    match status & 0x7f {
+        0 => (CLD_EXITED, (status >> 8) & 0xff),
+        signo if status & 0x80 != 0 => (CLD_DUMPED, signo),
+        signo => (CLD_KILLED, signo),
     }
+}
+
+/// Returns the resource usage reported for a child with the CPU time `times`.
+fn make_rusage(times: CpuTimes) -> rusage {
+    let mut usage: rusage = unsafe { mem::zeroed() };
+    usage.ru_utime.tv_sec = (times.utime_us / 1_000_000) as _;
+    usage.ru_utime.tv_usec = (times.utime_us % 1_000_000) as _;
+    usage.ru_stime.tv_sec = (times.stime_us / 1_000_000) as _;
+    usage.ru_stime.tv_usec = (times.stime_us % 1_000_000) as _;
+    usage
+}
+
+/// Waits for a state change of a child selected by `pid` and `options`.
+///
+/// Returns the child with its state change and CPU time, or `None` if there is
+/// none yet and `WNOHANG` is set.
+fn wait_child(
+    pid: WaitPid,
+    options: WaitOptions,
+) -> LinuxResult<Option<(Pid, ChildStatus, CpuTimes)>> {
+    let process = current_process();
+    let process_data = current_process_data();
 
-    let exit_code = exit_code_ptr.get();
     loop {
-        if let Some(child) = children.iter().find(|child| child.is_zombie()) {
-            if !options.contains(WaitOptions::WNOWAIT) {
-                child.release();
-            }
-            if let Ok(exit_code) = exit_code {
-                // TODO: other exit signals (low 8 bits)
-                unsafe {
-                    *exit_code = child.get_exit_code() << 8;
+        // Children may be adopted while waiting, so they are collected again
+        // each time.
+        let children = process
+            .get_children()
+            .into_iter()
+            .filter(|child| pid.apply(child))
+            .filter(|child| {
+                options.contains(WaitOptions::WALL)
+                    || (options.contains(WaitOptions::WCLONE)
+                        == get_process_data(child.get_pid()).is_some_and(|x| x.is_clone_child()))
+            })
+            .collect::<Vec<_>>();
+        if children.is_empty() {
+            return Err(LinuxError::ECHILD);
+        }
+
+        if options.contains(WaitOptions::WEXITED) {
+            if let Some(child) = children.iter().find(|child| child.is_zombie()) {
+                let times = get_process_data(child.get_pid())
+                    .map(|data| data.total_times())
+                    .unwrap_or_default();
+                if !options.contains(WaitOptions::WNOWAIT) {
+                    child.release();
+                    process_data.add_children_times(times);
                 }
+                let status = ChildStatus::Exited(child.get_exit_code());
+                return Ok(Some((child.get_pid(), status, times)));
             }
-            let pid = child.get_pid();
-            if pid == 53 {
-                error!("breakpoint");
-            }
-            return Ok(child.get_pid() as _);
-        } else if options.contains(WaitOptions::WNOHANG) {
-            return Ok(0);
+        }
+
+        let job_status = children.iter().find_map(|child| {
+            let data = get_process_data(child.get_pid())?;
+            let status = data.take_job_status(
+                options.contains(WaitOptions::WUNTRACED),
+                options.contains(WaitOptions::WCONTINUED),
+                !options.contains(WaitOptions::WNOWAIT),
+            )?;
+            let status = match status {
+                JobStatus::Stopped(signo) => ChildStatus::Stopped(signo),
+                JobStatus::Continued => ChildStatus::Continued,
+            };
+            Some((child.get_pid(), status, data.total_times()))
+        });
+        if job_status.is_some() {
+            return Ok(job_status);
+        }
+
+        if options.contains(WaitOptions::WNOHANG) {
+            return Ok(None);
         } else {
             // signal
             process_data.child_exit_wq.wait();
         }
     }
 }
+
+#[syscall_trace]
+pub fn sys_wait4(
+    pid: i32,
+    exit_code_ptr: UserOutPtr<i32>,
+    options: u32,
+    rusage: UserOutPtr<rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitOptions::from_bits(options)
+        .filter(|options| !options.intersects(WaitOptions::WEXITED | WaitOptions::WNOWAIT))
+        .ok_or(LinuxError::EINVAL)?
+        | WaitOptions::WEXITED;
+    info!("sys_waitpid <= pid: {:?}, options: {:?}", pid, options);
+
+    let pid = match pid {
+        -1 => WaitPid::Any,
+        0 => WaitPid::Pgid(current_process().get_group().get_pgid()),
+        1.. => WaitPid::Pid(pid as _),
+        i32::MIN => return Err(LinuxError::ESRCH),
+        _ => WaitPid::Pgid(-pid as _),
+    };
+
+    let Some((pid, status, times)) = wait_child(pid, options)? else {
+        return Ok(0);
+    };
+    exit_code_ptr.nullable(|ptr| ptr.write(status.wait_status()))?;
+    rusage.nullable(|ptr| ptr.write(make_rusage(times)))?;
+    Ok(pid as _)
+}
+
+#[syscall_trace]
+pub fn sys_waitid(
+    which: u32,
+    id: i32,
+    info: UserOutPtr<siginfo>,
+    options: u32,
+    rusage: UserOutPtr<rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)?;
+    if !options.intersects(WaitOptions::WEXITED | WaitOptions::WUNTRACED | WaitOptions::WCONTINUED)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    info!(
+        "sys_waitid <= which: {}, id: {}, options: {:?}",
+        which, id, options
+    );
+
+    let mut nonblocking = false;
+    let pid = match which {
+        P_ALL => WaitPid::Any,
+        P_PID if id > 0 => WaitPid::Pid(id as _),
+        P_PGID if id == 0 => WaitPid::Pgid(current_process().get_group().get_pgid()),
+        P_PGID if id > 0 => WaitPid::Pgid(id as _),
+        P_PIDFD => {
+            let pidfd = PidFd::from_fd(id)?;
+            nonblocking = pidfd.is_nonblocking();
+            WaitPid::Process(pidfd.process().clone())
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let wait_options = if nonblocking {
+        options | WaitOptions::WNOHANG
+    } else {
+        options
+    };
+    let result = wait_child(pid, wait_options)?;
+    if result.is_none() && !options.contains(WaitOptions::WNOHANG) {
+        // A nonblocking pidfd whose process has nothing to report yet
+        return Err(LinuxError::EAGAIN);
+    }
+    info.nullable(|ptr| {
+        ptr.write(match result {
+            Some((pid, status, _)) => {
+                let (code, status) = status.info_status();
+                make_child_info(Signo::SIGCHLD, code, pid, status).0
+            }
+            // Nothing to report with `WNOHANG`
+            None => unsafe { mem::zeroed() },
+        })
+    })?;
+    let times = result.map(|(_, _, times)| times).unwrap_or_default();
+    rusage.nullable(|ptr| ptr.write(make_rusage(times)))?;
+    Ok(0)
+}
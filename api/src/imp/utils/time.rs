@@ -1,8 +1,11 @@
 use crate::imp::fs::Kstat;
 use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
-use arceos_posix_api::{self as api, ctypes::timeval};
+use arceos_posix_api::{
+    self as api,
+    ctypes::{time_t, timeval},
+};
 use axerrno::LinuxResult;
-use axhal::time::{monotonic_time_nanos, nanos_to_ticks};
+use axhal::time::{monotonic_time_nanos, nanos_to_ticks, wall_time};
 use core::ffi::c_char;
 use starry_core::{ctypes::Tms, task::time_stat_output};
 
@@ -10,8 +13,24 @@ pub fn sys_clock_gettime(clock_id: i32, tp: UserPtr<api::ctypes::timespec>) -> L
     unsafe { Ok(api::sys_clock_gettime(clock_id, tp.get()?) as _) }
 }
 
+/// Returns the realtime clock, which the vDSO answers with as well.
 pub fn sys_get_time_of_day(ts: UserPtr<timeval>) -> LinuxResult<isize> {
-    unsafe { Ok(api::sys_get_time_of_day(ts.get()?) as _) }
+    let now = wall_time();
+    ts.write(timeval {
+        tv_sec: now.as_secs() as _,
+        tv_usec: now.subsec_micros() as _,
+    })?;
+    Ok(0)
+}
+
+/// Returns the seconds since the epoch, also stored at `tloc` unless it is
+/// null.
+pub fn sys_time(tloc: UserPtr<time_t>) -> LinuxResult<isize> {
+    let now = wall_time().as_secs() as time_t;
+    if !tloc.is_null() {
+        tloc.write(now)?;
+    }
+    Ok(now as _)
 }
 
 pub fn sys_times(tms: UserPtr<Tms>) -> LinuxResult<isize> {
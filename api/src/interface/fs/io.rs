@@ -1,30 +1,49 @@
-use crate::imp::fs::{sys_pread_impl, sys_pwrite_impl, sys_truncate_impl};
-use crate::ptr::{UserInPtr, UserOutPtr};
+use crate::imp::fs::{MemFile, sys_pread_impl, sys_pwrite_impl, sys_truncate_impl};
+use crate::imp::utils::path::resolve_path;
+use crate::ptr::{PtrWrapper, UserInPtr, UserOutPtr};
+use alloc::vec;
 use arceos_posix_api::{File, get_file_like};
 use axerrno::{LinuxError, LinuxResult};
 use axfs::fops;
 use axfs::fops::OpenOptions;
 use core::ffi::{c_char, c_int, c_long};
+use starry_core::mm::{copy_from_user, copy_to_user};
 use syscall_trace::syscall_trace;
 
+/// The size of the kernel buffer that `pread64` and `pwrite64` copy the data
+/// through.
+const RW_BUF_SIZE: usize = 0x10000;
+
 #[syscall_trace]
 pub fn sys_truncate(path: UserInPtr<c_char>, length: c_long) -> LinuxResult<isize> {
     // get params
-    let path = path.get_as_str()?;
+    let path = resolve_path(&path.read_str()?)?;
 
     // open file
     let mut options = OpenOptions::new();
-    options.truncate(true);
+    options.write(true);
 
-    let file = fops::File::open(path, &options)?;
+    let file = fops::File::open(path.as_str(), &options)?;
     sys_truncate_impl(&file, length as _)
 }
 
 #[syscall_trace]
 pub fn sys_ftruncate(fd: c_int, length: c_long) -> LinuxResult<isize> {
+    if length < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if let Ok(file) = MemFile::from_fd(fd) {
+        file.truncate(length as _)?;
+        return Ok(0);
+    }
     let file_like = get_file_like(fd)?.into_any();
     let api_file = file_like.downcast_ref::<File>().ok_or(LinuxError::EINVAL)?;
-    sys_truncate_impl(&api_file.inner().lock(), length as _)
+    let file = api_file.inner().lock();
+    // Unlike `truncate`, the file must be opened for writing.
+    if !file.is_writable() {
+        return Err(LinuxError::EINVAL);
+    }
+    sys_truncate_impl(&file, length as _)
 }
 
 #[syscall_trace]
@@ -34,8 +53,24 @@ pub fn sys_pread64(
     count: usize,
     offset: usize,
 ) -> LinuxResult<isize> {
-    let buf = buf.get_as_mut_slice(count)?;
-    sys_pread_impl(fd, buf, offset as _)
+    let mut kernel_buf = vec![0u8; count.min(RW_BUF_SIZE)];
+    let mut done = 0;
+    loop {
+        let len = (count - done).min(RW_BUF_SIZE);
+        let chunk = &mut kernel_buf[..len];
+        let read = match sys_pread_impl(fd, chunk, (offset + done) as _) {
+            Ok(read) => read as usize,
+            // Like Linux, the data already read is returned.
+            Err(_) if done > 0 => break,
+            Err(err) => return Err(err),
+        };
+        copy_to_user(buf.address() + done, &chunk[..read])?;
+        done += read;
+        if read < len || done == count {
+            break;
+        }
+    }
+    Ok(done as _)
 }
 
 #[syscall_trace]
@@ -45,6 +80,22 @@ pub fn sys_pwrite64(
     count: usize,
     offset: usize,
 ) -> LinuxResult<isize> {
-    let buf = buf.get_as_slice(count)?;
-    sys_pwrite_impl(fd, buf, offset as _)
+    let mut kernel_buf = vec![0u8; count.min(RW_BUF_SIZE)];
+    let mut done = 0;
+    loop {
+        let len = (count - done).min(RW_BUF_SIZE);
+        let chunk = &mut kernel_buf[..len];
+        copy_from_user(chunk, buf.address() + done)?;
+        let written = match sys_pwrite_impl(fd, chunk, (offset + done) as _) {
+            Ok(written) => written as usize,
+            // Like Linux, the data already written is counted.
+            Err(_) if done > 0 => break,
+            Err(err) => return Err(err),
+        };
+        done += written;
+        if written < len || done == count {
+            break;
+        }
+    }
+    Ok(done as _)
 }
@@ -9,9 +9,9 @@ use syscall_trace::syscall_trace;
 pub fn sys_rename(old_path: UserInPtr<c_char>, new_path: UserInPtr<c_char>) -> LinuxResult<isize> {
     sys_rename_impl(
         AT_FDCWD,
-        old_path.get_as_str()?,
+        &old_path.read_str()?,
         AT_FDCWD,
-        new_path.get_as_str()?,
+        &new_path.read_str()?,
         RenameFlags::empty(),
     )
 }
@@ -25,9 +25,9 @@ pub fn sys_renameat(
 ) -> LinuxResult<isize> {
     sys_rename_impl(
         old_dir_fd,
-        old_path.get_as_str()?,
+        &old_path.read_str()?,
         new_dir_fd,
-        new_path.get_as_str()?,
+        &new_path.read_str()?,
         RenameFlags::empty(),
     )
 }
@@ -43,18 +43,18 @@ pub fn sys_renameat2(
     let flags = RenameFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
     sys_rename_impl(
         old_dir_fd,
-        old_path.get_as_str()?,
+        &old_path.read_str()?,
         new_dir_fd,
-        new_path.get_as_str()?,
+        &new_path.read_str()?,
         flags,
     )
 }
 
 #[syscall_trace]
 pub fn sys_mkdir(path_name: UserInPtr<c_char>, mode: c_uint) -> LinuxResult<isize> {
-    let path_name = path_name.get_as_str()?;
+    let path_name = path_name.read_str()?;
     let mode = mode as u16;
-    sys_mkdir_impl(AT_FDCWD, path_name, mode)
+    sys_mkdir_impl(AT_FDCWD, &path_name, mode)
 }
 
 #[syscall_trace]
@@ -63,15 +63,15 @@ pub fn sys_mkdirat(
     path_name: UserInPtr<c_char>,
     mode: c_uint,
 ) -> LinuxResult<isize> {
-    let path_name = path_name.get_as_str()?;
+    let path_name = path_name.read_str()?;
     let mode = mode as u16;
-    sys_mkdir_impl(dir_fd, path_name, mode)
+    sys_mkdir_impl(dir_fd, &path_name, mode)
 }
 
 #[syscall_trace]
 pub fn sys_unlink(path_name: UserInPtr<c_char>) -> LinuxResult<isize> {
-    let path_name = path_name.get_as_str()?;
-    sys_unlink_impl(AT_FDCWD, path_name, UnlinkFlags::NO_REMOVE_DIR)
+    let path_name = path_name.read_str()?;
+    sys_unlink_impl(AT_FDCWD, &path_name, UnlinkFlags::NO_REMOVE_DIR)
 }
 
 #[syscall_trace]
@@ -80,17 +80,17 @@ pub fn sys_unlinkat(
     path_name: UserInPtr<c_char>,
     flags: c_uint,
 ) -> LinuxResult<isize> {
-    let path_name = path_name.get_as_str()?;
+    let path_name = path_name.read_str()?;
     let flags = if flags & AT_REMOVEDIR != 0 {
         UnlinkFlags::empty()
     } else {
         UnlinkFlags::NO_REMOVE_DIR
     };
-    sys_unlink_impl(dir_fd, path_name, flags)
+    sys_unlink_impl(dir_fd, &path_name, flags)
 }
 
 #[syscall_trace]
 pub fn sys_rmdir(path_name: UserInPtr<c_char>) -> LinuxResult<isize> {
-    let path_name = path_name.get_as_str()?;
-    sys_unlink_impl(AT_FDCWD, path_name, UnlinkFlags::NO_REMOVE_FILE)
+    let path_name = path_name.read_str()?;
+    sys_unlink_impl(AT_FDCWD, &path_name, UnlinkFlags::NO_REMOVE_FILE)
 }
@@ -93,7 +93,7 @@ pub fn sys_ppoll(
     if timeout.is_null() {
         block = true;
     } else {
-        let t = timeout.get_as_ref()?;
+        let t = timeout.read()?;
         if t.tv_sec < 0 || t.tv_nsec < 0 || t.tv_nsec > 999_999_999 {
             return Err(LinuxError::EINVAL);
         }
@@ -129,7 +129,7 @@ pub fn sys_select(
     if timeout.is_null() {
         block = true;
     } else {
-        let t = timeout.get_as_ref()?;
+        let t = timeout.read()?;
         if t.tv_sec < 0 || t.tv_usec < 0 {
             return Err(LinuxError::EINVAL);
         }
@@ -160,7 +160,7 @@ pub fn sys_pselect(
     if timeout.is_null() {
         block = true;
     } else {
-        let t = timeout.get_as_ref()?;
+        let t = timeout.read()?;
         if t.tv_sec < 0 || t.tv_nsec < 0 || t.tv_nsec > 999_999_999 {
             return Err(LinuxError::EINVAL);
         }
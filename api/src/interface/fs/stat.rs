@@ -216,11 +216,11 @@ impl From<FileStatus> for UserStatX {
 #[syscall_trace]
 pub fn sys_stat(path: UserInPtr<c_char>, stat_buf: UserOutPtr<UserStat>) -> LinuxResult<isize> {
     // get params
-    let path = path.get_as_str()?;
+    let path = path.read_str()?;
     let stat_buf = stat_buf.get()?;
 
     // perform syscall
-    let file_status = sys_stat_impl(-1, path, false)?;
+    let file_status = sys_stat_impl(-1, &path, false)?;
     unsafe { stat_buf.write(file_status.into()) }
     Ok(0)
 }
@@ -229,11 +229,11 @@ pub fn sys_stat(path: UserInPtr<c_char>, stat_buf: UserOutPtr<UserStat>) -> Linu
 #[syscall_trace]
 pub fn sys_lstat(path: UserInPtr<c_char>, stat_buf: UserOutPtr<UserStat>) -> LinuxResult<isize> {
     // get params
-    let path = path.get_as_str()?;
+    let path = path.read_str()?;
     let stat_buf = stat_buf.get()?;
 
     // perform syscall
-    let file_status = sys_stat_impl(-1, path, true)?;
+    let file_status = sys_stat_impl(-1, &path, true)?;
     unsafe { stat_buf.write(file_status.into()) }
     Ok(0)
 }
@@ -260,7 +260,7 @@ pub fn sys_fstatat(
     flags: c_int,
 ) -> LinuxResult<isize> {
     // get params
-    let path = path.get_as_str().unwrap_or("");
+    let path = path.read_str().unwrap_or_default();
     let stat_buf = stat_buf.get()?;
 
     // perform syscall
@@ -272,7 +272,7 @@ pub fn sys_fstatat(
         return Err(LinuxError::ENOENT);
     }
     let follow_symlinks = flags & AT_SYMLINK_NOFOLLOW == 0;
-    let file_status = sys_stat_impl(dir_fd, path, follow_symlinks)?;
+    let file_status = sys_stat_impl(dir_fd, &path, follow_symlinks)?;
 
     // write result
     unsafe { stat_buf.write(file_status.into()) }
@@ -288,7 +288,7 @@ pub fn sys_statx(
     statx_buf: UserOutPtr<UserStatX>,
 ) -> LinuxResult<isize> {
     // get params
-    let path = path.get_as_str().unwrap_or("");
+    let path = path.read_str().unwrap_or_default();
     let statx_buf = statx_buf.get()?;
 
     // perform syscall
@@ -300,7 +300,7 @@ pub fn sys_statx(
         return Err(LinuxError::ENOENT);
     }
     let follow_symlinks = flags & AT_SYMLINK_NOFOLLOW == 0;
-    let file_status = sys_stat_impl(dir_fd, path, follow_symlinks)?;
+    let file_status = sys_stat_impl(dir_fd, &path, follow_symlinks)?;
     // TODO: add more fields
     unsafe { statx_buf.write(file_status.into()) }
     Ok(0)
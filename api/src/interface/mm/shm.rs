@@ -1,12 +1,12 @@
-use alloc::sync::Arc;
+use crate::ptr::{PtrWrapper, UserPtr};
 use axerrno::{LinuxError, LinuxResult};
 use axhal::mem::virt_to_phys;
 use axhal::paging::MappingFlags;
 use bitflags::bitflags;
 use core::ffi::{c_int, c_ulong};
 use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange, align_down_4k, is_aligned_4k};
-use starry_core::shared_memory::SHARED_MEMORY_MANAGER;
-use starry_core::task::current_process_data;
+use starry_core::shared_memory::{IPC_PRIVATE, SHARED_MEMORY_MANAGER, ShmidDs};
+use starry_core::task::{current_process, current_process_data};
 use syscall_trace::syscall_trace;
 
 bitflags! {
@@ -21,67 +21,101 @@ bitflags! {
     }
 }
 
-const IPC_PRIVATE: c_int = 0;
+const IPC_RMID: c_int = 0;
+const IPC_SET: c_int = 1;
+const IPC_STAT: c_int = 2;
+const SHM_LOCK: c_int = 11;
+const SHM_UNLOCK: c_int = 12;
+/// The flag that older libcs add to `shmctl` commands, to ask for the 64-bit
+/// `shmid_ds`, which is the only one supported.
+const IPC_64: c_int = 0x100;
 
 #[syscall_trace]
 pub fn sys_shmget(key: c_int, size: c_ulong, shm_flag: c_int) -> LinuxResult<isize> {
     let size = size as usize;
     let flags = ShmFlags::from_bits_truncate(shm_flag);
-    // TODO: permission check
-    if key == IPC_PRIVATE {
-        // IPC get private
-        let key = SHARED_MEMORY_MANAGER.next_available_key();
-        let shared_memory = SHARED_MEMORY_MANAGER.create(key, size)?;
-        Ok(shared_memory.key as _)
-    } else {
-        let key = key as u32;
-        if SHARED_MEMORY_MANAGER.get(key).is_none() {
-            if !flags.contains(ShmFlags::IPC_CREAT) {
-                Err(LinuxError::ENOENT)
-            } else {
-                let shared_memory = SHARED_MEMORY_MANAGER.create(key, size)?;
-                Ok(shared_memory.key as _)
-            }
-        } else {
+    let key = key as u32;
+    // Like Linux, the effective IDs are checked and recorded.
+    let credentials = current_process_data().credentials();
+    if key != IPC_PRIVATE {
+        if let Some(shared_memory) = SHARED_MEMORY_MANAGER.find(key) {
             if flags.contains(ShmFlags::IPC_CREAT | ShmFlags::IPC_EXCL) {
-                Err(LinuxError::EEXIST)
-            } else {
-                Ok(key as _)
+                return Err(LinuxError::EEXIST);
+            }
+            if size > shared_memory.size() {
+                return Err(LinuxError::EINVAL);
             }
+            // Only the permissions in `shm_flag` are requested.
+            let access = (shm_flag as u32 >> 6) & 0o7;
+            shared_memory.check_access(credentials.euid, credentials.egid, access)?;
+            return Ok(shared_memory.shmid as _);
+        }
+        if !flags.contains(ShmFlags::IPC_CREAT) {
+            return Err(LinuxError::ENOENT);
         }
     }
+    if size == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let shared_memory = SHARED_MEMORY_MANAGER.create(
+        key,
+        size,
+        shm_flag as u32,
+        credentials.euid,
+        credentials.egid,
+        current_process().get_pid(),
+    )?;
+    Ok(shared_memory.shmid as _)
 }
 
 #[syscall_trace]
 pub fn sys_shmat(shm_id: c_int, shm_addr: c_ulong, shm_flag: c_int) -> LinuxResult<isize> {
     let flags = ShmFlags::from_bits_truncate(shm_flag);
-    let key = shm_id as u32;
-    let shared_memory = SHARED_MEMORY_MANAGER.get(key).ok_or(LinuxError::EINVAL)?;
-    let size = shared_memory.page_count * PAGE_SIZE_4K;
+    let shared_memory = SHARED_MEMORY_MANAGER
+        .get(shm_id as u32)
+        .ok_or(LinuxError::EINVAL)?;
+    let mut access = 0o4;
+    if !flags.contains(ShmFlags::SHM_RDONLY) {
+        access |= 0o2;
+    }
     let process_data = current_process_data();
+    let credentials = process_data.credentials();
+    shared_memory.check_access(credentials.euid, credentials.egid, access)?;
+
+    let size = shared_memory.page_count * PAGE_SIZE_4K;
     let mut addr_space = process_data.addr_space.lock();
     let addr = if shm_addr == 0 {
-        addr_space.find_free_area(
-            addr_space.base(),
-            size,
-            VirtAddrRange::new(addr_space.base(), addr_space.end()),
-        )
-    } else {
-        if flags.contains(ShmFlags::SHM_RND) {
-            let addr = align_down_4k(shm_addr as usize);
-            addr_space.find_free_area(
-                VirtAddr::from(addr),
+        addr_space
+            .find_free_area(
+                VirtAddr::from(process_data.get_mmap_base()),
                 size,
                 VirtAddrRange::new(addr_space.base(), addr_space.end()),
             )
+            .ok_or(LinuxError::ENOMEM)?
+    } else {
+        // `SHMLBA` is the page size.
+        let addr = if flags.contains(ShmFlags::SHM_RND) {
+            align_down_4k(shm_addr as usize)
+        } else if is_aligned_4k(shm_addr as _) {
+            shm_addr as usize
         } else {
-            if !is_aligned_4k(shm_addr as _) {
-                return Err(LinuxError::EINVAL);
-            }
-            Some(VirtAddr::from(shm_addr as usize))
+            return Err(LinuxError::EINVAL);
+        };
+        let addr = VirtAddr::from(addr);
+        if !addr_space.contains_range(addr, size) {
+            return Err(LinuxError::EINVAL);
+        }
+        // With `SHM_REMAP`, the mappings there are replaced below, after the
+        // limits are checked.
+        if !flags.contains(ShmFlags::SHM_REMAP)
+            && addr_space
+                .find_free_area(addr, size, VirtAddrRange::from_start_size(addr, size))
+                .is_none()
+        {
+            return Err(LinuxError::EINVAL);
         }
+        addr
     };
-    let addr = addr.ok_or(LinuxError::ENOMEM)?;
     // permission
     let mut permission = MappingFlags::USER | MappingFlags::READ;
     if !flags.contains(ShmFlags::SHM_RDONLY) {
@@ -90,10 +124,27 @@ pub fn sys_shmat(shm_id: c_int, shm_addr: c_ulong, shm_flag: c_int) -> LinuxResu
     if flags.contains(ShmFlags::SHM_EXEC) {
         permission |= MappingFlags::EXECUTE;
     }
+    let pid = current_process().get_pid();
+    if flags.contains(ShmFlags::SHM_REMAP) {
+        process_data.check_vm_limits_replacing(&addr_space, addr, size, false)?;
+        addr_space.unmap(addr, size)?;
+        process_data.release_shared_memory(&addr_space, pid);
+        // What is left of a larger segment attached at the same address is
+        // detached as well, as the attachments are found by their addresses.
+        let replaced = process_data.shared_memory.lock().remove(&addr);
+        if let Some(replaced) = replaced {
+            for range in replaced.mapped_ranges(&addr_space, addr) {
+                addr_space.unmap(range.start, range.size())?;
+            }
+            SHARED_MEMORY_MANAGER.detach(&replaced, pid);
+        }
+    } else {
+        process_data.check_vm_limits(&addr_space, size, false)?;
+    }
     let paddr = virt_to_phys(VirtAddr::from(shared_memory.addr));
     addr_space.map_linear(addr, paddr, size, permission)?;
+    shared_memory.attach(pid);
     // add to process data
-    let process_data = current_process_data();
     let mut process_shared_memory = process_data.shared_memory.lock();
     process_shared_memory.insert(addr, shared_memory);
 
@@ -101,45 +152,51 @@ pub fn sys_shmat(shm_id: c_int, shm_addr: c_ulong, shm_flag: c_int) -> LinuxResu
 }
 
 #[syscall_trace]
-pub fn sys_shmctl(shm_id: c_int, op: c_int, buf: c_ulong) -> LinuxResult<isize> {
-    let key = shm_id as u32;
-    let shared_memory = SHARED_MEMORY_MANAGER.get(key).ok_or(LinuxError::EINVAL)?;
-    match op {
-        0 => {
-            // IPC_RMID
-            if SHARED_MEMORY_MANAGER.delete(shared_memory.key) {
-                Ok(0)
-            } else {
-                Err(LinuxError::EINVAL)
-            }
+pub fn sys_shmctl(shm_id: c_int, op: c_int, buf: UserPtr<ShmidDs>) -> LinuxResult<isize> {
+    let shared_memory = SHARED_MEMORY_MANAGER
+        .get(shm_id as u32)
+        .ok_or(LinuxError::EINVAL)?;
+    let credentials = current_process_data().credentials();
+    match op & !IPC_64 {
+        IPC_RMID => {
+            shared_memory.check_owner(credentials.euid)?;
+            SHARED_MEMORY_MANAGER.remove(&shared_memory);
         }
-        1 => {
-            // IPC_STAT
-            // let buf: UserOutPtr<shmid_ds> = (buf as usize).into();
-            // let stat = buf.get_as_mut_ref()?;
-            // stat.shm_perm.__key = shared_memory.key as _;
-            // stat.shm_perm.mode = 0o600;
-            // stat.shm_perm.cuid = 1000;
-            // stat.shm_perm.uid = 1000;
-            // stat.shm_perm.gid = 1000;
-            // stat.shm_perm.cgid = 1000;
-            // stat.shm_perm.__seq = 0;
-            // stat.shm_segsz = shared_memory.page_count * PAGE_SIZE_4K;
-            // Ok(0)
-            Err(LinuxError::ENOSYS)
+        IPC_SET => {
+            let ds = buf.read()?;
+            shared_memory.check_owner(credentials.euid)?;
+            shared_memory.set(ds.shm_perm.uid, ds.shm_perm.gid, ds.shm_perm.mode);
         }
-        _ => Err(LinuxError::EINVAL),
+        IPC_STAT => {
+            shared_memory.check_access(credentials.euid, credentials.egid, 0o4)?;
+            buf.write(shared_memory.stat())?;
+        }
+        SHM_LOCK | SHM_UNLOCK => {
+            shared_memory.check_owner(credentials.euid)?;
+            shared_memory.set_locked(op & !IPC_64 == SHM_LOCK);
+        }
+        _ => return Err(LinuxError::EINVAL),
     }
+    Ok(0)
 }
 
 #[syscall_trace]
 pub fn sys_shmdt(shm_addr: c_ulong) -> LinuxResult<isize> {
     let process_data = current_process_data();
+    let mut addr_space = process_data.addr_space.lock();
     let mut shared_memory = process_data.shared_memory.lock();
     let virt_addr = VirtAddr::from(shm_addr as usize);
-    let shm_to_detach = shared_memory.remove(&virt_addr).ok_or(LinuxError::EINVAL)?;
-    let mut addr_space = process_data.addr_space.lock();
-    let size = shm_to_detach.page_count * PAGE_SIZE_4K;
-    addr_space.unmap(virt_addr, size)?;
+    let shm_to_detach = shared_memory.get(&virt_addr).ok_or(LinuxError::EINVAL)?;
+    // Only what is left of the segment's mapping is unmapped, and it must
+    // still start at the address, as on Linux.
+    let ranges = shm_to_detach.mapped_ranges(&addr_space, virt_addr);
+    if ranges.first().is_none_or(|range| range.start != virt_addr) {
+        return Err(LinuxError::EINVAL);
+    }
+    for range in ranges {
+        addr_space.unmap(range.start, range.size())?;
+    }
+    let shm_to_detach = shared_memory.remove(&virt_addr).unwrap();
+    SHARED_MEMORY_MANAGER.detach(&shm_to_detach, current_process().get_pid());
     Ok(0)
 }
@@ -119,6 +119,5 @@ fn sys_clone_(
 #[syscall_trace]
 pub fn sys_fork() -> LinuxResult<isize> {
     // fork is a special case of clone
-    // TODO: exit_signal = SIGCHLD
-    sys_clone_impl(CloneFlags::empty(), 0, 0, 0, None)
+    sys_clone_impl(CloneFlags::empty(), 0, 0, 0, Some(Signo::SIGCHLD))
 }
@@ -15,11 +15,11 @@ pub fn sys_prlimit64(
     let resource = ResourceLimitType::try_from(resource as u32).map_err(|_| LinuxError::EINVAL)?;
     if !old_limit.is_null() {
         let old_value = sys_getrlimit_impl(&resource, pid as _)?;
-        unsafe { old_limit.get()?.write(old_value); }
+        old_limit.write(old_value)?;
     }
     if !new_limit.is_null() {
-        let new_value = new_limit.get_as_ref()?;
-        sys_setrlimit_impl(&resource, new_value, pid as _)?;
+        let new_value = new_limit.read()?;
+        sys_setrlimit_impl(&resource, &new_value, pid as _)?;
     }
     Ok(0)
 }
@@ -30,7 +30,7 @@ pub fn sys_setrlimit(
     resource_limit: UserInPtr<ResourceLimit>,
 ) -> LinuxResult<isize> {
     let resource = ResourceLimitType::try_from(resource as u32).map_err(|_| LinuxError::EINVAL)?;
-    sys_setrlimit_impl(&resource, resource_limit.get_as_ref()?, 0)
+    sys_setrlimit_impl(&resource, &resource_limit.read()?, 0)
 }
 
 #[syscall_trace]
@@ -40,6 +40,6 @@ pub fn sys_getrlimit(
 ) -> LinuxResult<isize> {
     let resource = ResourceLimitType::try_from(resource as u32).map_err(|_| LinuxError::EINVAL)?;
     let old_value = sys_getrlimit_impl(&resource, 0)?;
-    unsafe{resource_limit.get()?.write(old_value);}
+    resource_limit.write(old_value)?;
     Ok(0)
 }
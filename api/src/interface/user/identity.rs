@@ -1,26 +1,23 @@
 use axerrno::LinuxResult;
+use starry_core::task::current_process_data;
 use syscall_trace::syscall_trace;
 
 #[syscall_trace]
 pub fn sys_getgid() -> LinuxResult<isize> {
-    // TODO: Implement the actual syscall logic
-    Ok(1000)
+    Ok(current_process_data().credentials().gid as _)
 }
 
 #[syscall_trace]
 pub fn sys_getegid() -> LinuxResult<isize> {
-    // TODO: Implement the actual syscall logic
-    Ok(1000)
+    Ok(current_process_data().credentials().egid as _)
 }
 
 #[syscall_trace]
 pub fn sys_getuid() -> LinuxResult<isize> {
-    // TODO: Implement the actual syscall logic
-    Ok(1000)
+    Ok(current_process_data().credentials().uid as _)
 }
 
 #[syscall_trace]
 pub fn sys_geteuid() -> LinuxResult<isize> {
-    // TODO: Implement the actual syscall logic
-    Ok(1000)
+    Ok(current_process_data().credentials().euid as _)
 }
@@ -1,12 +1,13 @@
+use alloc::ffi::CString;
 use alloc::format;
 use alloc::string::String;
+use alloc::vec::Vec;
 use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
 use core::fmt::Debug;
-use core::{alloc::Layout, ffi::c_char, mem, slice, str};
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
-use starry_core::mm::access_user_memory;
-use starry_core::task::current_process_data;
+use core::{alloc::Layout, ffi::c_char, mem, slice};
+use memory_addr::VirtAddr;
+use starry_core::mm::{copy_from_user, copy_to_user, fault_in_user};
 
 fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) -> LinuxResult<()> {
     let align = layout.align();
@@ -14,75 +15,41 @@ fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) ->
         return Err(LinuxError::EFAULT);
     }
 
-    let task = current_process_data();
-    let mut aspace = task.addr_space.lock();
-
-    if !aspace.check_region_access(
-        VirtAddrRange::from_start_size(start, layout.size()),
-        access_flags,
-    ) {
-        return Err(LinuxError::EFAULT);
-    }
-
-    let page_start = start.align_down_4k();
-    let page_end = (start + layout.size()).align_up_4k();
-    aspace.populate_area(page_start, page_end - page_start)?;
-
-    Ok(())
+    // Fault in the pages, which checks the permissions as well. The address
+    // space is only locked on page faults, where stacks may also grow.
+    fault_in_user(
+        start,
+        layout.size(),
+        access_flags.contains(MappingFlags::WRITE),
+    )
 }
 
-fn check_null_terminated<T: Eq + Default>(
-    start: VirtAddr,
-    access_flags: MappingFlags,
-) -> LinuxResult<(*const T, usize)> {
+fn read_null_terminated<T: Eq + Default>(start: VirtAddr) -> LinuxResult<Vec<T>> {
     let align = Layout::new::<T>().align();
     if start.as_usize() & (align - 1) != 0 {
         return Err(LinuxError::EFAULT);
     }
 
     let zero = T::default();
-
-    let mut page = start.align_down_4k();
-
-    let start = start.as_ptr_of::<T>();
-    let mut len = 0;
-
-    access_user_memory(|| {
-        loop {
-            // SAFETY: This won't overflow the address space since we'll check
-            // it below.
-            let ptr = unsafe { start.add(len) };
-            while ptr as usize >= page.as_ptr() as usize {
-                // We cannot prepare `aspace` outside of the loop, since holding
-                // aspace requires a mutex which would be required on page
-                // fault, and page faults can trigger inside the loop.
-
-                // TODO: this is inefficient, but we have to do this instead of
-                // querying the page table since the page might has not been
-                // allocated yet.
-                let task = current_process_data();
-                let aspace = task.addr_space.lock();
-                if !aspace.check_region_access(
-                    VirtAddrRange::from_start_size(page, PAGE_SIZE_4K),
-                    access_flags,
-                ) {
-                    return Err(LinuxError::EFAULT);
-                }
-
-                page += PAGE_SIZE_4K;
-            }
-
-            // This might trigger a page fault
-            // SAFETY: The pointer is valid and points to a valid memory region.
-            if unsafe { ptr.read_volatile() } == zero {
-                break;
-            }
-            len += 1;
+    let mut values = Vec::new();
+    loop {
+        let mut value = T::default();
+        // SAFETY: `value` is valid for `size_of::<T>()` bytes.
+        let buf = unsafe {
+            slice::from_raw_parts_mut(&mut value as *mut T as *mut u8, mem::size_of::<T>())
+        };
+        copy_from_user(buf, start + values.len() * mem::size_of::<T>())?;
+        if value == zero {
+            break;
         }
-        Ok(())
-    })?;
+        values.push(value);
+    }
+    Ok(values)
+}
 
-    Ok((start, len))
+fn read_str(start: VirtAddr) -> LinuxResult<String> {
+    let bytes = read_null_terminated::<u8>(start)?;
+    String::from_utf8(bytes).map_err(|_| LinuxError::EILSEQ)
 }
 
 /// A trait representing a pointer in user space, which can be converted to a
@@ -139,6 +106,35 @@ pub trait PtrWrapper<T>: Sized {
         unsafe { Ok(self.get_unchecked()) }
     }
 
+    /// Reads the value pointed to.
+    ///
+    /// Unlike dereferencing the pointer returned by [`PtrWrapper::get`], this
+    /// returns `EFAULT` even if the memory is unmapped by another thread
+    /// meanwhile.
+    fn read(&self) -> LinuxResult<T>
+    where
+        T: Copy,
+    {
+        let mut value = mem::MaybeUninit::<T>::uninit();
+        // SAFETY: `value` is valid for `size_of::<T>()` bytes.
+        let buf = unsafe {
+            slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>())
+        };
+        copy_from_user(buf, self.address())?;
+        // SAFETY: all bytes of `value` are copied.
+        Ok(unsafe { value.assume_init() })
+    }
+
+    /// Reads the values pointed to, up to a null value, which is not included.
+    ///
+    /// See [`PtrWrapper::read`] for how the faults are handled.
+    fn read_null_terminated(&self) -> LinuxResult<Vec<T>>
+    where
+        T: Eq + Default,
+    {
+        read_null_terminated(self.address())
+    }
+
     fn nullable<R>(self, f: impl FnOnce(&Self) -> LinuxResult<R>) -> LinuxResult<Option<R>> {
         if self.address().as_ptr().is_null() {
             Ok(None)
@@ -176,56 +172,27 @@ impl<T> PtrWrapper<T> for UserPtr<T> {
 }
 
 impl<T> UserPtr<T> {
-    /// Get the pointer as `&mut [T]`, terminated by a null value, validating
-    /// the memory region.
-    pub fn get_as_null_terminated(&self) -> LinuxResult<&'static mut [T]>
-    where
-        T: Eq + Default,
-    {
-        let (ptr, len) = check_null_terminated::<T>(self.address(), Self::ACCESS_FLAGS)?;
-        // SAFETY: We've validated the memory region.
-        unsafe { Ok(slice::from_raw_parts_mut(ptr as *mut _, len)) }
-    }
-
-    pub fn get_as_ref(&self) -> LinuxResult<&'static T> {
-        let ptr = self.get()?;
-        // SAFETY: We've validated the memory region.
-        unsafe { Ok(&*ptr) }
-    }
-
-    pub fn get_as_mut_ref(&self) -> LinuxResult<&'static mut T> {
-        let ptr = self.get()?;
-        // SAFETY: We've validated the memory region.
-        unsafe { Ok(&mut *ptr) }
-    }
-
-    pub fn get_as_slice(&self, length: usize) -> LinuxResult<&[T]> {
-        let buf = self.get()?;
-        unsafe { Ok(slice::from_raw_parts(buf, length)) }
-    }
-
-    pub fn get_as_mut_slice(&self, length: usize) -> LinuxResult<&mut [T]> {
-        let buf = self.get()?;
-        unsafe { Ok(slice::from_raw_parts_mut(buf, length)) }
-    }
-
     pub fn is_null(&self) -> bool {
         self.0.is_null()
     }
-}
-
-impl UserPtr<c_char> {
-    /// Get the pointer as `&str`, validating the memory region.
-    pub fn get_as_str(&self) -> LinuxResult<&'static str> {
-        let slice = self.get_as_null_terminated()?;
-        // SAFETY: c_char is u8
-        let slice = unsafe { mem::transmute::<&[c_char], &[u8]>(slice) };
 
-        str::from_utf8(slice).map_err(|_| LinuxError::EILSEQ)
+    /// Writes `value` to the memory pointed to.
+    ///
+    /// See [`PtrWrapper::read`] for how the faults are handled.
+    pub fn write(&self, value: T) -> LinuxResult
+    where
+        T: Copy,
+    {
+        // SAFETY: `value` is valid for `size_of::<T>()` bytes.
+        let buf =
+            unsafe { slice::from_raw_parts(&value as *const T as *const u8, mem::size_of::<T>()) };
+        copy_to_user(self.address(), buf)
     }
+}
 
+impl UserPtr<c_char> {
     pub fn fmt_trace_as_str(&self) -> String {
-        match self.get_as_str() {
+        match read_str(self.address()) {
             Ok(content) => {
                 format!("{:?} @ {:?}", content, self.address())
             }
@@ -294,28 +261,6 @@ impl<T: Debug> UserConstPtr<T> {
 }
 
 impl<T> UserConstPtr<T> {
-    /// Get the pointer as `&[T]`, terminated by a null value, validating the
-    /// memory region.
-    pub fn get_as_null_terminated(&self) -> LinuxResult<&'static [T]>
-    where
-        T: Eq + Default,
-    {
-        let (ptr, len) = check_null_terminated::<T>(self.address(), Self::ACCESS_FLAGS)?;
-        // SAFETY: We've validated the memory region.
-        unsafe { Ok(slice::from_raw_parts(ptr, len)) }
-    }
-
-    pub fn get_as_ref(&self) -> LinuxResult<&'static T> {
-        let ptr = self.get()?;
-        // SAFETY: We've validated the memory region.
-        unsafe { Ok(&*ptr) }
-    }
-
-    pub fn get_as_slice(&self, length: usize) -> LinuxResult<&[T]> {
-        let buf = self.get()?;
-        unsafe { Ok(slice::from_raw_parts(buf, length)) }
-    }
-
     pub fn is_null(&self) -> bool {
         self.0.is_null()
     }
@@ -324,17 +269,23 @@ impl<T> UserConstPtr<T> {
 static_assertions::const_assert_eq!(size_of::<c_char>(), size_of::<u8>());
 
 impl UserConstPtr<c_char> {
-    /// Get the pointer as `&str`, validating the memory region.
-    pub fn get_as_str(&self) -> LinuxResult<&'static str> {
-        let slice = self.get_as_null_terminated()?;
-        // SAFETY: c_char is u8
-        let slice = unsafe { mem::transmute::<&[c_char], &[u8]>(slice) };
+    /// Reads the null-terminated string pointed to.
+    ///
+    /// See [`PtrWrapper::read`] for how the faults are handled.
+    pub fn read_str(&self) -> LinuxResult<String> {
+        read_str(self.address())
+    }
 
-        str::from_utf8(slice).map_err(|_| LinuxError::EILSEQ)
+    /// Reads the null-terminated string pointed to as a C string, for the
+    /// functions taking C strings.
+    pub fn read_c_string(&self) -> LinuxResult<CString> {
+        let bytes = read_null_terminated::<u8>(self.address())?;
+        // The bytes are read up to the first null byte.
+        Ok(CString::new(bytes).unwrap())
     }
 
     pub fn fmt_trace_as_str(&self) -> String {
-        match self.get_as_str() {
+        match self.read_str() {
             Ok(content) => {
                 format!("{:?} @ {:?}", content, self.address())
             }
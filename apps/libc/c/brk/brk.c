@@ -0,0 +1,38 @@
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define TEST "brk"
+#include "../check.h"
+
+static char *brk_to(char *addr)
+{
+    return (char *)syscall(SYS_brk, addr);
+}
+
+int main()
+{
+    // The break is moved before anything is printed, so that it is back at
+    // its start when `printf` may allocate.
+    char *start = brk_to(0);
+
+    // The heap grows far beyond its first pages.
+    char *end = start + (1 << 20);
+    int grow = brk_to(end) == end;
+    end[-1] = 'g';
+    grow = grow && end[-1] == 'g';
+
+    // Shrinking frees the pages, which read zeros when the heap grows again.
+    start[8192] = 'x';
+    int shrink = brk_to(start + 4096) == start + 4096;
+    shrink = shrink && brk_to(start + 16384) == start + 16384 && start[8192] == 0;
+
+    // A break beyond the address space is refused.
+    int refused = brk_to((char *)-4096L) == start + 16384;
+    brk_to(start);
+
+    CHECK("grow", grow);
+    CHECK("shrink", shrink);
+    CHECK("refused", refused);
+    return 0;
+}
@@ -0,0 +1,10 @@
+#ifndef CHECK_H
+#define CHECK_H
+
+#include <stdio.h>
+
+// Prints the result of a check as `<test>: <name> ok` or `<test>: <name> failed`,
+// with `TEST` defined by the test before including this header.
+#define CHECK(name, cond) printf("%s: %s %s\n", TEST, name, (cond) ? "ok" : "failed")
+
+#endif
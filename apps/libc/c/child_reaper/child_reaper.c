@@ -0,0 +1,49 @@
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "child_reaper"
+#include "../check.h"
+
+int main()
+{
+    char name[16] = {0};
+    CHECK("prctl_name", prctl(PR_SET_NAME, "reaper-test") == 0 &&
+                            prctl(PR_GET_NAME, name) == 0 && strcmp(name, "reaper-test") == 0);
+
+    sigset_t set;
+    sigemptyset(&set);
+    sigaddset(&set, SIGCHLD);
+    sigprocmask(SIG_BLOCK, &set, NULL);
+    prctl(PR_SET_CHILD_SUBREAPER, 1);
+
+    int fds[2];
+    pipe(fds);
+    pid_t middle = fork();
+    if (middle == 0) {
+        pid_t grandchild = fork();
+        if (grandchild == 0)
+            _exit(5);
+        // Exit with a zombie child, which is adopted by the subreaper.
+        siginfo_t info;
+        waitid(P_PID, grandchild, &info, WEXITED | WNOWAIT);
+        write(fds[1], &grandchild, sizeof(grandchild));
+        _exit(0);
+    }
+    pid_t grandchild;
+    read(fds[0], &grandchild, sizeof(grandchild));
+
+    // The adopted zombie is reported first, with its own status.
+    siginfo_t info;
+    sigwaitinfo(&set, &info);
+    CHECK("zombie_sigchld", info.si_pid == grandchild && info.si_code == CLD_EXITED &&
+                                info.si_status == 5);
+
+    int status;
+    waitpid(middle, &status, 0);
+    CHECK("adopted", waitpid(grandchild, &status, 0) == grandchild && WEXITSTATUS(status) == 5);
+    return 0;
+}
@@ -0,0 +1,48 @@
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "cow_fork"
+#include "../check.h"
+
+#define SIZE (4 * 4096)
+
+static int all(const char *buf, char c)
+{
+    for (int i = 0; i < SIZE; i++)
+        if (buf[i] != c)
+            return 0;
+    return 1;
+}
+
+int main()
+{
+    char *buf = malloc(SIZE);
+    memset(buf, 'p', SIZE);
+    int pipefd[2];
+    pipe(pipefd);
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        // The child waits for the parent to write, then still sees the pages
+        // as they were at the fork, and its own writes stay its own.
+        char c;
+        read(pipefd[0], &c, 1);
+        int ok = all(buf, 'p');
+        memset(buf, 'c', SIZE);
+        _exit(ok && all(buf, 'c') ? 0 : 1);
+    }
+    memset(buf, 'q', SIZE);
+    write(pipefd[1], "x", 1);
+    int status;
+    waitpid(pid, &status, 0);
+    CHECK("child", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    CHECK("parent", all(buf, 'q'));
+
+    // The pages stay usable once the child is gone.
+    memset(buf, 'r', SIZE);
+    CHECK("after_exit", all(buf, 'r'));
+    free(buf);
+    return 0;
+}
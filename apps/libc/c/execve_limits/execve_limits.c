@@ -0,0 +1,36 @@
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/auxv.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "execve_limits"
+#include "../check.h"
+
+static char big_arg[20000];
+
+int main(int argc, char *argv[])
+{
+    if (argc > 1)
+        return 0;
+
+    CHECK("auxv_ids", getauxval(AT_UID) == getuid() && getauxval(AT_EUID) == geteuid() &&
+                          getauxval(AT_GID) == getgid() && getauxval(AT_EGID) == getegid());
+
+    // The arguments may take up to a quarter of RLIMIT_STACK.
+    pid_t pid = fork();
+    if (pid == 0) {
+        struct rlimit limit = {65536, RLIM_INFINITY};
+        setrlimit(RLIMIT_STACK, &limit);
+        memset(big_arg, 'a', sizeof(big_arg) - 1);
+        char *args[] = {argv[0], big_arg, NULL};
+        execv(argv[0], args);
+        _exit(errno == E2BIG ? 1 : 2);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    CHECK("stack_limit", WIFEXITED(status) && WEXITSTATUS(status) == 1);
+    return 0;
+}
@@ -0,0 +1,26 @@
+#include <errno.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define TEST "madv_free"
+#include "../check.h"
+
+int main()
+{
+    long page = sysconf(_SC_PAGESIZE);
+    char *private = mmap(NULL, page, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    private[0] = 'p';
+    CHECK("private", madvise(private, page, MADV_FREE) == 0);
+
+    char *shared = mmap(NULL, page, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    shared[0] = 's';
+    CHECK("shared", madvise(shared, page, MADV_FREE) < 0 && errno == EINVAL && shared[0] == 's');
+
+    FILE *file = tmpfile();
+    fputc('f', file);
+    fflush(file);
+    char *mapped = mmap(NULL, page, PROT_READ, MAP_PRIVATE, fileno(file), 0);
+    CHECK("file", madvise(mapped, page, MADV_FREE) < 0 && errno == EINVAL && mapped[0] == 'f');
+    return 0;
+}
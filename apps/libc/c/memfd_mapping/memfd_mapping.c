@@ -0,0 +1,71 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <setjmp.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "memfd_mapping"
+#include "../check.h"
+
+static sigjmp_buf env;
+static volatile int fault_signo;
+
+static void handler(int signo)
+{
+    fault_signo = signo;
+    siglongjmp(env, 1);
+}
+
+// Returns the signal raised by reading `addr`, or 0.
+static int read_signal(volatile char *addr)
+{
+    fault_signo = 0;
+    if (sigsetjmp(env, 1) == 0)
+        (void)*addr;
+    return fault_signo;
+}
+
+int main(int argc, char *argv[])
+{
+    if (argc > 1) {
+        // Run by `execve` below, the descriptor must be closed.
+        int fd = atoi(argv[1]);
+        return fcntl(fd, F_GETFD) == -1 && errno == EBADF ? 0 : 1;
+    }
+
+    int fd = memfd_create("cloexec", MFD_CLOEXEC);
+    int plain = memfd_create("plain", 0);
+    CHECK("cloexec_flag", fcntl(fd, F_GETFD) == FD_CLOEXEC && fcntl(plain, F_GETFD) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        char arg[16];
+        snprintf(arg, sizeof(arg), "%d", fd);
+        execl(argv[0], argv[0], arg, NULL);
+        _exit(2);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    CHECK("cloexec_exec", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    signal(SIGBUS, handler);
+    signal(SIGSEGV, handler);
+
+    // The second page is beyond the end of the file.
+    ftruncate(plain, 4096);
+    char *addr = mmap(NULL, 8192, PROT_READ | PROT_WRITE, MAP_SHARED, plain, 0);
+    CHECK("beyond_eof", read_signal(addr) == 0 && read_signal(addr + 4096) == SIGBUS);
+
+    // Truncation removes the pages from the mappings.
+    ftruncate(plain, 8192);
+    addr[4096] = 'x';
+    ftruncate(plain, 4096);
+    int truncated = read_signal(addr + 4096) == SIGBUS;
+    ftruncate(plain, 8192);
+    CHECK("truncate", truncated && read_signal(addr + 4096) == 0 && addr[4096] == 0);
+    return 0;
+}
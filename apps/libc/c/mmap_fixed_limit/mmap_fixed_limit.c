@@ -0,0 +1,29 @@
+#include <errno.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+
+#define TEST "mmap_fixed_limit"
+#include "../check.h"
+
+int main()
+{
+    struct rlimit limit = {4096, 4096};
+    setrlimit(RLIMIT_MEMLOCK, &limit);
+
+    char *page = mmap(NULL, 8192, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    page[0] = 'k';
+    mlock(page, 4096);
+    munmap(page + 4096, 4096);
+
+    // Locking two pages exceeds the limit, the page there must stay mapped.
+    void *ret = mmap(page, 8192, PROT_READ | PROT_WRITE,
+                     MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED | MAP_LOCKED, -1, 0);
+    CHECK("over_limit", ret == MAP_FAILED && errno == EAGAIN && page[0] == 'k');
+
+    // Replacing the locked page is within the limit.
+    ret = mmap(page, 4096, PROT_READ | PROT_WRITE,
+               MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED | MAP_LOCKED, -1, 0);
+    CHECK("replace", ret == page && page[0] == 0);
+    return 0;
+}
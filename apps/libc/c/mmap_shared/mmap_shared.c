@@ -0,0 +1,55 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "mmap_shared"
+#include "../check.h"
+
+int main()
+{
+    // Shared anonymous memory stays shared with the child.
+    char *anon = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    anon[0] = 'a';
+    pid_t pid = fork();
+    if (pid == 0) {
+        anon[0] = 'b';
+        _exit(0);
+    }
+    waitpid(pid, NULL, 0);
+    CHECK("anonymous_fork", anon[0] == 'b');
+    munmap(anon, 4096);
+
+    // A page written and then made read-only is still written back.
+    int fd = open("mmap_shared_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+    write(fd, "xxxx", 4);
+    char *map = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    memcpy(map, "data", 4);
+    mprotect(map, 4096, PROT_READ);
+    munmap(map, 4096);
+    close(fd);
+    char buf[5] = {0};
+    fd = open("mmap_shared_file", O_RDONLY);
+    read(fd, buf, 4);
+    CHECK("mprotect_dirty", strcmp(buf, "data") == 0);
+
+    // Pages beyond the end of the file do not stop the mapping from being
+    // protected or advised, they are faulted in only when accessed.
+    long page = sysconf(_SC_PAGESIZE);
+    int rw = open("mmap_shared_file", O_RDWR);
+    map = mmap(NULL, 4 * page, PROT_READ | PROT_WRITE, MAP_SHARED, rw, 0);
+    CHECK("beyond_eof", mprotect(map, 4 * page, PROT_READ) == 0 &&
+                            madvise(map, 4 * page, MADV_WILLNEED) == 0 && map[0] == 'd');
+    munmap(map, 4 * page);
+    close(rw);
+
+    // A read-only file cannot be mapped shared and writable.
+    map = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    CHECK("readonly_fd", map == MAP_FAILED && errno == EACCES);
+    close(fd);
+    unlink("mmap_shared_file");
+    return 0;
+}
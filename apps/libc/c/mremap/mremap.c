@@ -0,0 +1,68 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/shm.h>
+#include <unistd.h>
+
+#define TEST "mremap"
+#include "../check.h"
+
+int main()
+{
+    // A mapping followed by free space grows in place.
+    char *map = mmap(NULL, 5 * 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    munmap(map + 4096, 3 * 4096);
+    map[0] = 'a';
+    char *grown = mremap(map, 4096, 4 * 4096, 0);
+    CHECK("grow", grown == map && grown[0] == 'a' && grown[3 * 4096] == 0);
+
+    // A mapping followed by another one only grows by moving.
+    char *next = map + 4 * 4096;
+    int blocked = mremap(map, 4 * 4096, 8 * 4096, 0) == MAP_FAILED && errno == ENOMEM;
+    char *moved = mremap(map, 4 * 4096, 8 * 4096, MREMAP_MAYMOVE);
+    CHECK("move", blocked && moved != MAP_FAILED && moved != map && moved[0] == 'a');
+    munmap(next, 4096);
+
+    // A mapping shrinks in place.
+    char *shrunk = mremap(moved, 8 * 4096, 4096, 0);
+    CHECK("shrink", shrunk == moved && shrunk[0] == 'a');
+
+    // The destination of a fixed move is replaced.
+    char *dest = mmap(NULL, 2 * 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    dest[0] = 'd';
+    char *fixed = mremap(shrunk, 4096, 2 * 4096, MREMAP_MAYMOVE | MREMAP_FIXED, dest);
+    CHECK("fixed", fixed == dest && fixed[0] == 'a' && fixed[4096] == 0);
+
+    // A failed fixed move leaves the destination mapped.
+    char *other = mmap(NULL, 4 * 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    other[0] = 'o';
+    char *hole = mmap(NULL, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    munmap(hole, 4096);
+    int einval = mremap(fixed, 4096, 4096, MREMAP_FIXED, other) == MAP_FAILED && errno == EINVAL;
+    int efault = mremap(hole, 4096, 4096, MREMAP_MAYMOVE | MREMAP_FIXED, other) == MAP_FAILED
+        && errno == EFAULT;
+    CHECK("fixed_failure", einval && efault && other[0] == 'o');
+
+    // A fixed move over the limit fails, also without unmapping the destination.
+    struct rlimit limit;
+    getrlimit(RLIMIT_AS, &limit);
+    struct rlimit lowered = {0, limit.rlim_max};
+    setrlimit(RLIMIT_AS, &lowered);
+    int enomem = mremap(fixed, 2 * 4096, 4 * 4096, MREMAP_MAYMOVE | MREMAP_FIXED, other) == MAP_FAILED
+        && errno == ENOMEM;
+    setrlimit(RLIMIT_AS, &limit);
+    CHECK("fixed_limit", enomem && other[0] == 'o' && fixed[0] == 'a');
+
+    // A shared memory segment does not grow over the memory after it.
+    char *free_range = mmap(NULL, 2 * 4096, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    munmap(free_range, 2 * 4096);
+    int shmid = shmget(IPC_PRIVATE, 4096, IPC_CREAT | 0600);
+    char *shm = shmat(shmid, free_range, 0);
+    CHECK("shm_grow", shm == free_range && mremap(shm, 4096, 2 * 4096, 0) == MAP_FAILED);
+    shmdt(shm);
+    shmctl(shmid, IPC_RMID, NULL);
+    return 0;
+}
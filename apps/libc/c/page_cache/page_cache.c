@@ -0,0 +1,110 @@
+#include <errno.h>
+#include <pthread.h>
+#include <fcntl.h>
+#include <setjmp.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define TEST "page_cache"
+#include "../check.h"
+
+static char *shared_map;
+static sigjmp_buf env;
+static volatile int fault_signo;
+
+static void handler(int signo)
+{
+    fault_signo = signo;
+    siglongjmp(env, 1);
+}
+
+// Returns the signal raised by reading `addr`, or 0.
+static int read_signal(volatile char *addr)
+{
+    fault_signo = 0;
+    if (sigsetjmp(env, 1) == 0)
+        (void)*addr;
+    return fault_signo;
+}
+
+static void *fault_pages(void *arg)
+{
+    long ok = 1;
+    for (int i = (long)arg; i < 16; i += 2)
+        ok = ok && shared_map[i * 4096] == 'a' + i;
+    return (void *)ok;
+}
+
+int main()
+{
+    char buf[16] = {0};
+
+    // The same file opened by different paths shares its cached pages.
+    mkdir("pc_dir", 0755);
+    int fd = open("pc_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+    write(fd, "hello", 5);
+    int fd2 = open("pc_dir/../pc_file", O_RDWR);
+    pwrite(fd2, "j", 1, 0);
+    pread(fd, buf, 5, 0);
+    CHECK("dotdot", strcmp(buf, "jello") == 0);
+    close(fd2);
+
+    // A renamed file is seen at its new path.
+    rename("pc_file", "pc_file2");
+    memset(buf, 0, sizeof(buf));
+    fd2 = open("pc_file2", O_RDONLY);
+    read(fd2, buf, 5);
+    CHECK("rename", strcmp(buf, "jello") == 0);
+
+    // `ftruncate` needs a writable file, `truncate` does not.
+    CHECK("ftruncate_rdonly", ftruncate(fd2, 0) == -1 && errno == EINVAL);
+    close(fd2);
+    CHECK("truncate_path", truncate("pc_file2", 3) == 0);
+    close(fd);
+
+    // A removed file keeps its content while it is mapped.
+    fd = open("pc_file2", O_RDWR);
+    char *map = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    unlink("pc_file2");
+    map[0] = 'y';
+    memset(buf, 0, sizeof(buf));
+    pread(fd, buf, 3, 0);
+    CHECK("unlink", strcmp(buf, "yel") == 0);
+    munmap(map, 4096);
+    close(fd);
+
+    // Threads faulting on the pages of a file at once all see its content.
+    fd = open("pc_file3", O_RDWR | O_CREAT | O_TRUNC, 0644);
+    for (int i = 0; i < 16; i++)
+        pwrite(fd, (char[]){'a' + i}, 1, i * 4096);
+    shared_map = mmap(NULL, 16 * 4096, PROT_READ, MAP_SHARED, fd, 0);
+    pthread_t thread;
+    void *thread_ok;
+    pthread_create(&thread, NULL, fault_pages, (void *)1);
+    long main_ok = (long)fault_pages((void *)0);
+    pthread_join(thread, &thread_ok);
+    CHECK("threads", main_ok && (long)thread_ok);
+    munmap(shared_map, 16 * 4096);
+    close(fd);
+    unlink("pc_file3");
+
+    // Truncation removes the pages beyond the new end from the mappings.
+    signal(SIGBUS, handler);
+    fd = open("pc_file4", O_RDWR | O_CREAT | O_TRUNC, 0644);
+    ftruncate(fd, 8192);
+    map = mmap(NULL, 8192, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    map[4096] = 'x';
+    ftruncate(fd, 4096);
+    int truncated = read_signal(map + 4096) == SIGBUS;
+    ftruncate(fd, 8192);
+    CHECK("truncate_mapped", truncated && read_signal(map + 4096) == 0 && map[4096] == 0);
+    munmap(map, 8192);
+    close(fd);
+    unlink("pc_file4");
+    rmdir("pc_dir");
+    return 0;
+}
@@ -0,0 +1,54 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "pid_max"
+#include "../check.h"
+
+static int write_pid_max(const char *value)
+{
+    int fd = open("/proc/sys/kernel/pid_max", O_WRONLY);
+    ssize_t ret = write(fd, value, strlen(value));
+    int err = errno;
+    close(fd);
+    errno = err;
+    return ret < 0 ? -1 : 0;
+}
+
+static long read_pid_max(void)
+{
+    char buf[32] = {0};
+    int fd = open("/proc/sys/kernel/pid_max", O_RDONLY);
+    read(fd, buf, sizeof(buf) - 1);
+    close(fd);
+    return strtol(buf, NULL, 10);
+}
+
+int main()
+{
+    long old = read_pid_max();
+    CHECK("invalid", write_pid_max("abc") < 0 && errno == EINVAL &&
+                         write_pid_max("100") < 0 && errno == EINVAL && read_pid_max() == old);
+
+    CHECK("valid", write_pid_max("4096\n") == 0 && read_pid_max() == 4096);
+
+    // Newly allocated IDs are below the new bound.
+    int ok = 1;
+    for (int i = 0; i < 8; i++) {
+        pid_t pid = fork();
+        if (pid == 0)
+            _exit(0);
+        ok = ok && pid > 0 && pid < 4096;
+        waitpid(pid, NULL, 0);
+    }
+    CHECK("fork", ok);
+
+    char value[32];
+    snprintf(value, sizeof(value), "%ld\n", old);
+    write_pid_max(value);
+    return 0;
+}
@@ -0,0 +1,44 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef P_PIDFD
+#define P_PIDFD 3
+#endif
+
+#define TEST "pidfd_wait"
+#include "../check.h"
+
+int main()
+{
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(7);
+    int pidfd = syscall(SYS_pidfd_open, pid, 0);
+
+    struct stat st;
+    CHECK("stat", fstat(pidfd, &st) == 0 && S_ISREG(st.st_mode));
+
+    siginfo_t info = {0};
+    struct rusage usage;
+    int ret = syscall(SYS_waitid, P_PIDFD, pidfd, &info, WEXITED, &usage);
+    CHECK("waitid", ret == 0 && info.si_pid == pid && info.si_code == CLD_EXITED &&
+                        info.si_status == 7);
+
+    // The process has been reaped, the pidfd does not refer to any child.
+    ret = syscall(SYS_waitid, P_PIDFD, pidfd, &info, WEXITED | WNOHANG, NULL);
+    CHECK("reaped", ret == -1 && errno == ECHILD);
+
+    // Bad status and resource usage pointers are reported instead of crashing.
+    pid = fork();
+    if (pid == 0)
+        _exit(0);
+    ret = syscall(SYS_wait4, pid, (int *)1, 0, NULL);
+    CHECK("efault", ret == -1 && errno == EFAULT);
+    return 0;
+}
@@ -0,0 +1,62 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <sys/prctl.h>
+#include <sys/uio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "process_vm_access"
+#include "../check.h"
+
+static int value = 42;
+
+static ssize_t read_value(pid_t pid, int *out)
+{
+    struct iovec local = {out, sizeof(*out)};
+    struct iovec remote = {&value, sizeof(value)};
+    return process_vm_readv(pid, &local, 1, &remote, 1, 0);
+}
+
+int main()
+{
+    int ready[2], done[2];
+    pipe(ready);
+    pipe(done);
+    pid_t target = fork();
+    if (target == 0) {
+        // Not dumpable, only the ancestors may access the memory.
+        prctl(PR_SET_DUMPABLE, 0);
+        write(ready[1], "x", 1);
+        char c;
+        read(done[0], &c, 1);
+        _exit(0);
+    }
+    char c;
+    read(ready[0], &c, 1);
+
+    int status;
+    pid_t sibling = fork();
+    if (sibling == 0) {
+        int out = 0;
+        _exit(read_value(target, &out) == -1 && errno == EPERM ? 0 : 1);
+    }
+    waitpid(sibling, &status, 0);
+    CHECK("sibling", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    int out = 0;
+    CHECK("ancestor", read_value(target, &out) == sizeof(out) && out == 42);
+
+    // A dumpable process with the same credentials can be accessed.
+    pid_t child = fork();
+    if (child == 0) {
+        int out = 0;
+        _exit(read_value(getppid(), &out) == sizeof(out) && out == 42 ? 0 : 1);
+    }
+    waitpid(child, &status, 0);
+    CHECK("same_credentials", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    write(done[1], "x", 1);
+    waitpid(target, NULL, 0);
+    return 0;
+}
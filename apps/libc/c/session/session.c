@@ -0,0 +1,86 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "session"
+#include "../check.h"
+
+int main(int argc, char *argv[])
+{
+    if (argc > 1) {
+        // Run after exec: wait for the parent to close the pipe.
+        char c;
+        read(atoi(argv[1]), &c, 1);
+        return 0;
+    }
+
+    // A new session is led by the process, which then cannot change it or
+    // its group.
+    pid_t pid = fork();
+    if (pid == 0) {
+        int ok = setsid() == getpid() && getsid(0) == getpid() && getpgid(0) == getpid();
+        ok = ok && setsid() == -1 && errno == EPERM;
+        ok = ok && setpgid(0, 0) == -1 && errno == EPERM;
+        _exit(ok ? 0 : 1);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    CHECK("setsid", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // A group leader cannot start a session.
+    pid = fork();
+    if (pid == 0) {
+        int ok = setpgid(0, 0) == 0 && setsid() == -1 && errno == EPERM;
+        _exit(ok ? 0 : 1);
+    }
+    waitpid(pid, &status, 0);
+    CHECK("group_leader", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // A child in another session cannot be moved.
+    int wait_pipe[2], ready_pipe[2];
+    pipe(wait_pipe);
+    pipe(ready_pipe);
+    pid = fork();
+    if (pid == 0) {
+        setsid();
+        close(wait_pipe[1]);
+        close(ready_pipe[1]);
+        char c;
+        read(wait_pipe[0], &c, 1);
+        _exit(0);
+    }
+    close(ready_pipe[1]);
+    char c;
+    read(ready_pipe[0], &c, 1);
+    close(ready_pipe[0]);
+    CHECK("other_session", setpgid(pid, pid) == -1 && errno == EPERM);
+    close(wait_pipe[1]);
+    waitpid(pid, NULL, 0);
+    close(wait_pipe[0]);
+
+    // A child can be moved until it has run execve.
+    pipe(wait_pipe);
+    pipe(ready_pipe);
+    pid = fork();
+    if (pid == 0) {
+        char fd[16];
+        snprintf(fd, sizeof(fd), "%d", wait_pipe[0]);
+        close(wait_pipe[1]);
+        close(ready_pipe[0]);
+        fcntl(ready_pipe[1], F_SETFD, FD_CLOEXEC);
+        read(wait_pipe[0], &c, 1);
+        execl(argv[0], argv[0], fd, NULL);
+        _exit(1);
+    }
+    int before = setpgid(pid, pid) == 0 && getpgid(pid) == pid;
+    write(wait_pipe[1], "x", 1);
+    close(ready_pipe[1]);
+    // The pipe is closed on exec.
+    read(ready_pipe[0], &c, 1);
+    CHECK("exec", before && setpgid(pid, 0) == -1 && errno == EACCES);
+    close(wait_pipe[1]);
+    waitpid(pid, NULL, 0);
+    return 0;
+}
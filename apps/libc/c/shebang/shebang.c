@@ -0,0 +1,75 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "shebang"
+#include "../check.h"
+
+static char self[256];
+static char script[256];
+
+// Writes an executable file at `path` in the working directory with the content.
+static void write_file(char *path, const char *name, const char *content)
+{
+    getcwd(path, 200);
+    strcat(path, "/");
+    strcat(path, name);
+    int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0755);
+    write(fd, content, strlen(content));
+    close(fd);
+    chmod(path, 0755);
+}
+
+int main(int argc, char *argv[])
+{
+    if (argc > 1) {
+        // Run as the interpreter: the rest of the `#!` line is a single
+        // argument, followed by the script and its own arguments.
+        int ok = argc == 4 && strcmp(argv[1], "-a  b") == 0 &&
+                 strstr(argv[2], "/shebang_script") != NULL && strcmp(argv[3], "arg") == 0;
+        return ok ? 0 : 1;
+    }
+
+    if (argv[0][0] == '/') {
+        strcpy(self, argv[0]);
+    } else {
+        getcwd(self, 200);
+        strcat(self, "/");
+        strcat(self, argv[0]);
+    }
+
+    // The interpreter gets the rest of the line as a single argument.
+    char line[512];
+    snprintf(line, sizeof(line), "#! %s  -a  b \nexit 1\n", self);
+    write_file(script, "shebang_script", line);
+    pid_t pid = fork();
+    if (pid == 0) {
+        execve(script, (char *[]){script, "arg", NULL}, (char *[]){NULL});
+        _exit(2);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    CHECK("argument", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // A script running itself nests too deep.
+    char loop[256];
+    snprintf(line, sizeof(line), "#!%s/shebang_loop\n", getcwd(self, 200));
+    write_file(loop, "shebang_loop", line);
+    int eloop = execve(loop, (char *[]){loop, NULL}, (char *[]){NULL}) == -1 && errno == ELOOP;
+    CHECK("eloop", eloop);
+
+    // Files neither ELF nor scripts are not run.
+    char text[256];
+    write_file(text, "shebang_text", "plain text\n");
+    int enoexec = execve(text, (char *[]){text, NULL}, (char *[]){NULL}) == -1 && errno == ENOEXEC;
+    CHECK("enoexec", enoexec);
+
+    unlink(script);
+    unlink(loop);
+    unlink(text);
+    return 0;
+}
@@ -0,0 +1,34 @@
+#include <errno.h>
+#include <stdio.h>
+#include <sys/ipc.h>
+#include <sys/mman.h>
+#include <sys/shm.h>
+#include <unistd.h>
+
+#define TEST "shm_ctl"
+#include "../check.h"
+
+int main()
+{
+    void *unmapped = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    munmap(unmapped, 4096);
+
+    // The segment is owned by the effective IDs of its creator.
+    int shmid = shmget(IPC_PRIVATE, 4096, IPC_CREAT | 0640);
+    struct shmid_ds ds;
+    int ok = shmctl(shmid, IPC_STAT, &ds) == 0;
+    CHECK("owner", ok && ds.shm_perm.uid == geteuid() && ds.shm_perm.cuid == geteuid() &&
+                       ds.shm_perm.gid == getegid() && (ds.shm_perm.mode & 0777) == 0640);
+
+    // `IPC_SET` changes the permissions.
+    ds.shm_perm.mode = 0600;
+    ok = shmctl(shmid, IPC_SET, &ds) == 0 && shmctl(shmid, IPC_STAT, &ds) == 0;
+    CHECK("set", ok && (ds.shm_perm.mode & 0777) == 0600);
+
+    // Unmapped buffers fail with `EFAULT`.
+    int set_fault = shmctl(shmid, IPC_SET, unmapped) == -1 && errno == EFAULT;
+    int stat_fault = shmctl(shmid, IPC_STAT, unmapped) == -1 && errno == EFAULT;
+    CHECK("fault", set_fault && stat_fault);
+    shmctl(shmid, IPC_RMID, NULL);
+    return 0;
+}
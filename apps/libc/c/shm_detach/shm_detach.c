@@ -0,0 +1,73 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <sys/ipc.h>
+#include <sys/mman.h>
+#include <sys/shm.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "shm_detach"
+#include "../check.h"
+
+static int shmid;
+
+// Attaches and detaches the segment repeatedly, while other threads fork.
+static void *attach_loop(void *arg)
+{
+    (void)arg;
+    for (int i = 0; i < 200; i++) {
+        char *addr = shmat(shmid, NULL, 0);
+        if (addr == (void *)-1)
+            return (void *)1;
+        addr[0]++;
+        if (shmdt(addr) != 0)
+            return (void *)1;
+    }
+    return NULL;
+}
+
+int main()
+{
+    shmid = shmget(IPC_PRIVATE, 4096, IPC_CREAT | 0600);
+    char *addr = shmat(shmid, NULL, 0);
+    addr[1] = 'x';
+
+    pthread_t thread;
+    pthread_create(&thread, NULL, attach_loop, NULL);
+    for (int i = 0; i < 20; i++) {
+        pid_t pid = fork();
+        if (pid == 0)
+            _exit(addr[1] == 'x' ? 0 : 1);
+        waitpid(pid, NULL, 0);
+    }
+    void *ret;
+    pthread_join(thread, &ret);
+
+    CHECK("fork", ret == NULL && addr[1] == 'x' && shmdt(addr) == 0);
+
+    // Unmapping an attachment detaches it.
+    struct shmid_ds ds;
+    addr = shmat(shmid, NULL, 0);
+    munmap(addr, 4096);
+    shmctl(shmid, IPC_STAT, &ds);
+    CHECK("munmap", ds.shm_nattch == 0 && shmdt(addr) == -1 && errno == EINVAL);
+
+    // The mapping replacing an attachment is not unmapped by `shmdt`.
+    addr = shmat(shmid, NULL, 0);
+    char *map = mmap(addr, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1,
+                     0);
+    map[0] = 'm';
+    shmctl(shmid, IPC_STAT, &ds);
+    CHECK("replaced", map == addr && ds.shm_nattch == 0 && shmdt(addr) == -1 && map[0] == 'm');
+    munmap(map, 4096);
+
+    // `SHM_REMAP` replaces the attachment at the address.
+    addr = shmat(shmid, NULL, 0);
+    char *again = shmat(shmid, addr, SHM_REMAP);
+    shmctl(shmid, IPC_STAT, &ds);
+    CHECK("remap", again == addr && ds.shm_nattch == 1 && shmdt(addr) == 0 && shmdt(addr) == -1);
+    shmctl(shmid, IPC_RMID, NULL);
+    return 0;
+}
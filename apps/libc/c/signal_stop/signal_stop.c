@@ -0,0 +1,41 @@
+#include <signal.h>
+#include <stdio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST "signal_stop"
+#include "../check.h"
+
+int main()
+{
+    sigset_t set, pending;
+    sigemptyset(&set);
+    sigaddset(&set, SIGTSTP);
+    sigaddset(&set, SIGCONT);
+    sigprocmask(SIG_BLOCK, &set, NULL);
+
+    // SIGCONT discards the pending stop signals.
+    kill(getpid(), SIGTSTP);
+    kill(getpid(), SIGCONT);
+    sigpending(&pending);
+    CHECK("cont_discards_stop", !sigismember(&pending, SIGTSTP) && sigismember(&pending, SIGCONT));
+
+    // A stop signal discards the pending SIGCONT.
+    kill(getpid(), SIGTSTP);
+    sigpending(&pending);
+    CHECK("stop_discards_cont", sigismember(&pending, SIGTSTP) && !sigismember(&pending, SIGCONT));
+
+    // A stopped process is killed by SIGKILL.
+    pid_t pid = fork();
+    if (pid == 0) {
+        while (1)
+            pause();
+    }
+    int status;
+    kill(pid, SIGSTOP);
+    waitpid(pid, &status, WUNTRACED);
+    kill(pid, SIGKILL);
+    waitpid(pid, &status, 0);
+    CHECK("kill_stopped", WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    return 0;
+}
@@ -0,0 +1,32 @@
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define TEST "stack_grow"
+#include "../check.h"
+
+#define PAGES 16
+
+int main()
+{
+    long page = sysconf(_SC_PAGESIZE);
+    char *top = mmap(NULL, page, PROT_READ | PROT_WRITE,
+                     MAP_PRIVATE | MAP_ANONYMOUS | MAP_GROWSDOWN, -1, 0);
+    top[0] = 'x';
+
+    // Each access right below the stack extends it by a page.
+    for (int i = 1; i <= PAGES; i++)
+        top[-i * page] = (char)i;
+    int ok = top[0] == 'x';
+    for (int i = 1; i <= PAGES; i++)
+        ok = ok && top[-i * page] == (char)i;
+    CHECK("grow", ok);
+
+    // The stack is one mapping, which can be changed as a whole.
+    char *bottom = top - PAGES * page;
+    CHECK("whole", mprotect(bottom, (PAGES + 1) * page, PROT_READ) == 0 &&
+                       top[0] == 'x' && bottom[0] == PAGES &&
+                       munmap(bottom, (PAGES + 1) * page) == 0);
+    return 0;
+}
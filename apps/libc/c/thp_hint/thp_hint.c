@@ -0,0 +1,33 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+
+#define TEST "thp_hint"
+#include "../check.h"
+
+#define SIZE_2M (2UL << 20)
+
+int main()
+{
+    // Find a free range, then map at an unaligned hint in it.
+    char *area = mmap(NULL, 4 * SIZE_2M, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    munmap(area, 4 * SIZE_2M);
+    char *hint = (char *)(((uintptr_t)area + SIZE_2M - 1) & ~(SIZE_2M - 1)) + 4096;
+    char *p = mmap(hint, 2 * SIZE_2M, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1,
+                   0);
+    CHECK("hint", p == hint);
+    munmap(p, 2 * SIZE_2M);
+
+    // Without a hint, the mapping is aligned to be backed by huge pages,
+    // which are split when a part of them is protected.
+    p = mmap(NULL, 2 * SIZE_2M, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    memset(p, 'h', 2 * SIZE_2M);
+    int ok = ((uintptr_t)p & (SIZE_2M - 1)) == 0 && mprotect(p, 4096, PROT_READ) == 0;
+    for (size_t i = 0; i < 2 * SIZE_2M; i += 4096)
+        ok = ok && p[i] == 'h';
+    p[8192] = 's';
+    CHECK("split", ok && p[8192] == 's' && p[0] == 'h');
+    munmap(p, 2 * SIZE_2M);
+    return 0;
+}
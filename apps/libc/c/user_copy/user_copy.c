@@ -0,0 +1,48 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define TEST "user_copy"
+#include "../check.h"
+
+#define LARGE_SIZE (200 * 1024)
+
+int main()
+{
+    char *unmapped = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    munmap(unmapped, 4096);
+
+    // Reads and writes larger than the kernel buffer are copied in whole.
+    char *data = malloc(LARGE_SIZE);
+    char *back = malloc(LARGE_SIZE);
+    for (int i = 0; i < LARGE_SIZE; i++)
+        data[i] = i % 251;
+    int fd = open("user_copy_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+    int written = pwrite(fd, data, LARGE_SIZE, 1);
+    int read = pread(fd, back, LARGE_SIZE, 1);
+    CHECK("large", written == LARGE_SIZE && read == LARGE_SIZE && memcmp(data, back, LARGE_SIZE) == 0);
+
+    // Unmapped buffers fail with `EFAULT`.
+    int pread_fault = pread(fd, unmapped, 16, 0) == -1 && errno == EFAULT;
+    int pwrite_fault = pwrite(fd, unmapped, 16, 0) == -1 && errno == EFAULT;
+    CHECK("buffer_fault", pread_fault && pwrite_fault);
+    close(fd);
+    unlink("user_copy_file");
+
+    // So do unmapped strings.
+    CHECK("path_fault", open(unmapped, O_RDONLY) == -1 && errno == EFAULT);
+
+    // The working directory is copied out with its terminator.
+    char cwd[256];
+    memset(cwd, 'x', sizeof(cwd));
+    long ret = syscall(SYS_getcwd, cwd, sizeof(cwd));
+    int ok = ret > 0 && cwd[0] == '/' && memchr(cwd, 0, sizeof(cwd)) != NULL;
+    CHECK("getcwd", ok && syscall(SYS_getcwd, unmapped, 256) == -1 && errno == EFAULT);
+    return 0;
+}
@@ -0,0 +1,51 @@
+#define _GNU_SOURCE
+#include <setjmp.h>
+#include <signal.h>
+#include <stdio.h>
+
+#define TEST "user_trap"
+#include "../check.h"
+
+#if defined(__x86_64__)
+#define BREAKPOINT "int3"
+#define ILLEGAL "ud2"
+#elif defined(__aarch64__)
+#define BREAKPOINT "brk #0"
+#define ILLEGAL ".inst 0x00000000"
+#elif defined(__riscv)
+#define BREAKPOINT "ebreak"
+#define ILLEGAL ".word 0"
+#else
+#define BREAKPOINT "break 0"
+#define ILLEGAL ".word 0"
+#endif
+
+static sigjmp_buf env;
+static volatile int caught_signo, caught_code;
+
+static void handler(int signo, siginfo_t *info, void *ucontext)
+{
+    caught_signo = signo;
+    caught_code = info->si_code;
+    siglongjmp(env, 1);
+}
+
+int main()
+{
+    struct sigaction sa = {0};
+    sa.sa_sigaction = handler;
+    sa.sa_flags = SA_SIGINFO | SA_NODEFER;
+    sigaction(SIGTRAP, &sa, NULL);
+    sigaction(SIGILL, &sa, NULL);
+
+    if (sigsetjmp(env, 1) == 0)
+        __asm__ volatile(BREAKPOINT);
+    CHECK("breakpoint", caught_signo == SIGTRAP && caught_code == TRAP_BRKPT);
+
+    // Instructions which cannot be executed never raise SIGTRAP.
+    caught_signo = 0;
+    if (sigsetjmp(env, 1) == 0)
+        __asm__ volatile(ILLEGAL);
+    CHECK("illegal", caught_signo == SIGILL);
+    return 0;
+}
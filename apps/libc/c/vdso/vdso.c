@@ -0,0 +1,42 @@
+#include <string.h>
+#include <sys/auxv.h>
+#include <sys/syscall.h>
+#include <sys/time.h>
+#include <time.h>
+#include <unistd.h>
+
+#define TEST "vdso"
+#include "../check.h"
+
+static int later(const struct timespec *a, const struct timespec *b)
+{
+    return b->tv_sec > a->tv_sec || (b->tv_sec == a->tv_sec && b->tv_nsec >= a->tv_nsec);
+}
+
+int main()
+{
+    // The vDSO is advertised as an ELF image.
+    const char *ehdr = (const char *)getauxval(AT_SYSINFO_EHDR);
+    CHECK("auxv", ehdr != NULL && memcmp(ehdr, "\177ELF", 4) == 0);
+
+    // Its clock never runs behind the one of the system call.
+    int monotonic = 1;
+    struct timespec prev, now;
+    syscall(SYS_clock_gettime, CLOCK_MONOTONIC, &prev);
+    for (int i = 0; i < 1000; i++) {
+        clock_gettime(CLOCK_MONOTONIC, &now);
+        monotonic = monotonic && later(&prev, &now);
+        syscall(SYS_clock_gettime, CLOCK_MONOTONIC, &prev);
+        monotonic = monotonic && later(&now, &prev);
+    }
+    CHECK("monotonic", monotonic);
+
+    // The wall clock agrees with the one of the system call.
+    struct timeval tv;
+    struct timespec ts;
+    gettimeofday(&tv, NULL);
+    syscall(SYS_clock_gettime, CLOCK_REALTIME, &ts);
+    time_t t = time(NULL);
+    CHECK("realtime", tv.tv_sec <= ts.tv_sec && ts.tv_sec <= t && t - tv.tv_sec <= 1);
+    return 0;
+}
@@ -0,0 +1,46 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define TEST "zero_page"
+#include "../check.h"
+
+#define SIZE_2M (2UL << 20)
+
+int main()
+{
+    long page = sysconf(_SC_PAGESIZE);
+    char *p = mmap(NULL, 4 * page, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+
+    // Pages only read map the zero frame, and are not resident.
+    volatile char c = p[0];
+    p[page] = 'w';
+    unsigned char vec[4] = {0};
+    CHECK("mincore", c == 0 && mincore(p, 4 * page, vec) == 0 && !(vec[0] & 1) &&
+                         (vec[1] & 1) && !(vec[2] & 1));
+    munmap(p, 4 * page);
+
+    // A huge page range read before it is written still reads zeros.
+    p = mmap(NULL, 2 * SIZE_2M, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    int ok = 1;
+    for (size_t i = 0; i < 2 * SIZE_2M; i += page)
+        ok = ok && p[i] == 0;
+    p[0] = 'w';
+    for (size_t i = page; i < 2 * SIZE_2M; i += page)
+        ok = ok && p[i] == 0;
+    CHECK("collapse", ok && p[0] == 'w');
+    munmap(p, 2 * SIZE_2M);
+
+    // A huge page range written first is mapped at once by a huge page.
+    p = mmap(NULL, 2 * SIZE_2M, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    char *huge = (char *)(((uintptr_t)p + SIZE_2M - 1) & ~(SIZE_2M - 1));
+    huge[0] = 'w';
+    static unsigned char huge_vec[SIZE_2M / 4096];
+    ok = mincore(huge, SIZE_2M, huge_vec) == 0;
+    for (size_t i = 0; i < SIZE_2M / page; i++)
+        ok = ok && (huge_vec[i] & 1);
+    CHECK("huge", ok);
+    munmap(p, 2 * SIZE_2M);
+    return 0;
+}
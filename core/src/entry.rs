@@ -1,5 +1,6 @@
-use crate::mm::{copy_from_kernel, load_user_app, map_trampoline, new_user_aspace_empty};
-use crate::process::{ProcessData, create_thread_data};
+use crate::mm::{copy_from_kernel, load_user_app, new_user_aspace_empty, read_user_app};
+use crate::process::{Credentials, ProcessData, create_thread_data};
+use crate::resource::DEFAULT_STACK_LIMIT;
 use crate::task::{TaskExt, create_user_task};
 use alloc::{string::String, sync::Arc};
 use arceos_posix_api::{FD_TABLE, FilePath};
@@ -15,8 +16,6 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     let mut uspace = new_user_aspace_empty()
         .and_then(|mut it| {
             copy_from_kernel(&mut it)?;
-            // signal trampoline
-            map_trampoline(&mut it)?;
             Ok(it)
         })
         .expect("Failed to create user address space");
@@ -25,9 +24,11 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     let path = FilePath::new(&args[0]).expect("Invalid file path");
     axfs::api::set_current_dir(path.parent().unwrap()).expect("Failed to set current dir");
 
-    // load executable file
-    let (entry_vaddr, ustack_top) = load_user_app(&mut uspace, args, envs)
-        .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
+    // load executable file, along with the signal trampoline
+    let (entry_vaddr, ustack_top, heap_bottom, mmap_base) =
+        read_user_app(&args[0], args, envs, DEFAULT_STACK_LIMIT)
+            .and_then(|app| load_user_app(&mut uspace, &app, Credentials::default()))
+            .unwrap_or_else(|e| panic!("Failed to load user app: {:?}", e));
 
     // create user context
     let uctx = UspaceContext::new(entry_vaddr.into(), ustack_top, 2333);
@@ -48,6 +49,9 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
         Arc::default(),
         Some(Signo::SIGCHLD),
     );
+    process_data.set_heap_bottom(heap_bottom.as_usize());
+    process_data.set_heap_top(heap_bottom.as_usize());
+    process_data.set_mmap_base(mmap_base.as_usize());
     let thread_data = create_thread_data(Arc::new(process_data), thread.get_tid());
 
     FD_TABLE
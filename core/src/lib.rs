@@ -7,7 +7,10 @@ extern crate axlog;
 pub mod ctypes;
 pub mod entry;
 pub mod mm;
+pub mod page_cache;
 pub mod process;
+pub mod random;
 pub mod resource;
 pub mod shared_memory;
 pub mod task;
+pub mod vdso;
@@ -1,13 +1,27 @@
-use core::ffi::CStr;
+mod elf;
+mod stack;
 
-use alloc::{string::String, vec};
-use axerrno::{AxError, AxResult};
+use alloc::collections::BTreeSet;
+use alloc::{string::String, vec, vec::Vec};
+use arceos_posix_api::FilePath;
+use axerrno::{AxResult, LinuxError, LinuxResult};
 use axhal::mem::virt_to_phys;
 use axhal::paging::MappingFlags;
+use axhal::uaccess::{probe_user_write, user_copy};
 use axmm::{AddrSpace, kernel_aspace};
-use kernel_elf_parser::{AuxvEntry, ELFParser, app_stack_region};
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
-use xmas_elf::{ElfFile, program::SegmentData};
+use linux_raw_sys::general::{
+    AT_BASE, AT_CLKTCK, AT_EGID, AT_ENTRY, AT_EUID, AT_FLAGS, AT_GID, AT_HWCAP, AT_PAGESZ, AT_PHDR,
+    AT_PHENT, AT_PHNUM, AT_SECURE, AT_SYSINFO_EHDR, AT_UID,
+};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange};
+use undefined_process::process::get_all_processes;
+use xmas_elf::ElfFile;
+use xmas_elf::program::ProgramHeader64;
+
+use crate::page_cache::PAGE_CACHE;
+use crate::process::{Credentials, get_process_data};
+use crate::random::random_u64;
+use crate::resource::DEFAULT_STACK_LIMIT;
 
 pub fn new_user_aspace_empty() -> AxResult<AddrSpace> {
     AddrSpace::new_empty(
@@ -28,7 +42,8 @@ pub fn copy_from_kernel(aspace: &mut AddrSpace) -> AxResult {
     Ok(())
 }
 
-/// Map the signal trampoline to the user address space.
+/// Map the signal trampoline and the vDSO next to it to the user address
+/// space.
 pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
     let signal_trampoline_paddr = virt_to_phys(axsignal::arch::signal_trampoline_address().into());
     aspace.map_linear(
@@ -37,172 +52,348 @@ pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
         PAGE_SIZE_4K,
         MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
     )?;
-    Ok(())
+    crate::vdso::map_vdso(aspace)
 }
 
-/// Map the elf file to the user address space.
+/// The maximum randomization of the base of `mmap` areas and of
+/// position-independent executables, 256 MiB.
+const MMAP_RND_PAGES: usize = 1 << 16;
+/// The maximum randomization of the stack top, 64 MiB.
+const STACK_RND_PAGES: usize = 1 << 14;
+/// The maximum randomization of the start of the heap, 32 MiB like Linux.
+const BRK_RND_PAGES: usize = 1 << 13;
+
+/// The clock ticks per second reported in `AT_CLKTCK`, i.e. `USER_HZ`.
+const CLOCK_TICKS: usize = 100;
+
+/// Returns whether address space layout randomization is enabled.
 ///
-/// # Arguments
-/// - `uspace`: The address space of the user app.
-/// - `elf`: The elf file.
+/// It can be disabled for reproducible runs by writing 0 to
+/// `/proc/sys/kernel/randomize_va_space`.
+fn aslr_enabled() -> bool {
+    read_file("/proc/sys/kernel/randomize_va_space")
+        .map_or(true, |value| value.trim_ascii() != b"0")
+}
+
+/// Returns a random page aligned offset less than `max_pages` pages, or 0 if
+/// `randomize` is false.
+fn random_offset(randomize: bool, max_pages: usize) -> usize {
+    if randomize {
+        (random_u64() as usize % max_pages) * PAGE_SIZE_4K
+    } else {
+        0
+    }
+}
+
+/// Reads the whole file at `path` through the page cache.
+fn read_file(path: &str) -> LinuxResult<Vec<u8>> {
+    let path = FilePath::new(path)?;
+    Ok(PAGE_CACHE.read_file(path.as_str())?)
+}
+
+/// Reads and checks an ELF image.
+fn read_elf(path: &str) -> LinuxResult<Vec<u8>> {
+    let data = read_file(path)?;
+    let elf = ElfFile::new(&data).map_err(|_| LinuxError::ENOEXEC)?;
+    elf::check_elf(&elf)?;
+    Ok(data)
+}
+
+/// The maximum number of nested `#!` interpreters, the same as Linux.
+const MAX_SCRIPT_DEPTH: usize = 4;
+/// The maximum length of the `#!` line that is looked at, the same as Linux.
+const SCRIPT_LINE_MAX: usize = 256;
+
+/// Parses the `#!` line of a script, returns the interpreter and its
+/// optional argument.
 ///
-/// # Returns
-/// - The entry point of the user app.
-fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEntry; 17])> {
-    let uspace_base = uspace.base().as_usize();
-    let elf_parser = ELFParser::new(
-        elf,
-        axconfig::plat::USER_INTERP_BASE,
-        Some(uspace_base as isize),
-        uspace_base,
-    )
-    .map_err(|_| AxError::InvalidData)?;
-
-    for segment in elf_parser.ph_load() {
-        debug!(
-            "Mapping ELF segment: [{:#x?}, {:#x?}) flags: {:#x?}",
-            segment.vaddr,
-            segment.vaddr + segment.memsz as usize,
-            segment.flags
-        );
-        let seg_pad = segment.vaddr.align_offset_4k();
-        assert_eq!(seg_pad, segment.offset % PAGE_SIZE_4K);
-
-        let seg_align_size =
-            (segment.memsz as usize + seg_pad + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
-        uspace.map_alloc(
-            segment.vaddr.align_down_4k(),
-            seg_align_size,
-            segment.flags,
-            true,
-        )?;
-        let seg_data = elf
-            .input
-            .get(segment.offset..segment.offset + segment.filesz as usize)
-            .ok_or(AxError::InvalidData)?;
-        uspace.write(segment.vaddr, seg_data)?;
-        // TDOO: flush the I-cache
+/// Like Linux, everything after the interpreter is passed as a single
+/// argument.
+fn parse_shebang(data: &[u8]) -> LinuxResult<(String, Option<String>)> {
+    let head = &data[2..data.len().min(SCRIPT_LINE_MAX)];
+    let (line, complete) = match head.iter().position(|&c| c == b'\n') {
+        Some(end) => (&head[..end], true),
+        None => (head, data.len() <= SCRIPT_LINE_MAX),
+    };
+    let line = core::str::from_utf8(line)
+        .map_err(|_| LinuxError::ENOEXEC)?
+        .trim_matches([' ', '\t']);
+    let (interp, arg) = match line.split_once([' ', '\t']) {
+        Some((interp, arg)) => (interp, Some(arg.trim_matches([' ', '\t']))),
+        None => (line, None),
+    };
+    // The interpreter must not be cut off at the end of the line.
+    if interp.is_empty() || (!complete && arg.is_none()) {
+        return Err(LinuxError::ENOEXEC);
     }
+    let arg = arg.filter(|arg| !arg.is_empty()).map(String::from);
+    Ok((String::from(interp), arg))
+}
 
-    Ok((
-        elf_parser.entry().into(),
-        elf_parser.auxv_vector(PAGE_SIZE_4K),
-    ))
+/// A user app read and checked by `read_user_app`, ready to be loaded by
+/// `load_user_app`.
+pub struct UserApp {
+    /// The path the app is run by.
+    path: String,
+    /// The arguments, including the interpreters of scripts.
+    args: Vec<String>,
+    /// The environment variables.
+    envs: Vec<String>,
+    /// The ELF image, of the app or of the interpreter of the script.
+    elf_data: Vec<u8>,
+    /// The ELF image of the interpreter requested by the ELF image, if any.
+    interp_data: Option<Vec<u8>>,
 }
 
-/// Load the user app to the user address space.
+/// Read and check the user app, without touching any address space, so that
+/// errors like `ENOEXEC` can still be returned to the caller of `execve`.
+///
+/// The app may be an ELF executable, or a script starting with `#!`, which is
+/// run by the interpreter given in its first line.
 ///
 /// # Arguments
-/// - `uspace`: The address space of the user app.
-/// - `args`: The arguments of the user app. The first argument is the path of the user app.
+/// - `path`: The path of the user app.
+/// - `args`: The arguments of the user app, starting with its name.
 /// - `envs`: The environment variables of the user app.
+/// - `stack_limit`: The `RLIMIT_STACK` of the process, which bounds the size
+///   of the arguments.
+pub fn read_user_app(
+    path: &str,
+    args: &[String],
+    envs: &[String],
+    stack_limit: u64,
+) -> LinuxResult<UserApp> {
+    let mut elf_path = String::from(path);
+    let mut args = args.to_vec();
+    let mut depth = 0;
+    let elf_data = loop {
+        let data = read_file(&elf_path)?;
+        if !data.starts_with(b"#!") {
+            break data;
+        }
+        depth += 1;
+        if depth > MAX_SCRIPT_DEPTH {
+            return Err(LinuxError::ELOOP);
+        }
+        // The interpreter is run with the path of the script in place of
+        // `argv[0]`.
+        let (interp, arg) = parse_shebang(&data)?;
+        let mut new_args = vec![interp.clone()];
+        new_args.extend(arg);
+        new_args.push(elf_path);
+        new_args.extend(args.into_iter().skip(1));
+        args = new_args;
+        elf_path = interp;
+    };
+
+    // Like Linux, the arguments may take up to a quarter of the stack limit,
+    // and no more than three quarters of the default one.
+    let args_size: usize = args
+        .iter()
+        .chain(envs)
+        .map(|s| s.len() + 1 + size_of::<usize>())
+        .sum();
+    if args_size as u64 > (stack_limit / 4).min(DEFAULT_STACK_LIMIT / 4 * 3) {
+        return Err(LinuxError::E2BIG);
+    }
+
+    let elf = ElfFile::new(&elf_data).map_err(|_| LinuxError::ENOEXEC)?;
+    elf::check_elf(&elf)?;
+    let interp_data = elf::interp_path(&elf).map(read_elf).transpose()?;
+    if let Some(data) = &interp_data {
+        let interp = ElfFile::new(data).map_err(|_| LinuxError::ENOEXEC)?;
+        // The interpreter must be relocatable, and not need an interpreter.
+        if !elf::is_relocatable(&interp) || elf::interp_path(&interp).is_some() {
+            return Err(LinuxError::ENOEXEC);
+        }
+    }
+    Ok(UserApp {
+        path: String::from(path),
+        args,
+        envs: envs.to_vec(),
+        elf_data,
+        interp_data,
+    })
+}
+
+/// Load the user app read by `read_user_app` to the user address space,
+/// replacing everything mapped in it.
+///
+/// Errors are not recoverable, as the old program is gone by then.
+///
+/// # Arguments
+/// - `uspace`: The address space of the user app.
+/// - `app`: The user app.
+/// - `credentials`: The credentials of the process, reported in the auxiliary
+///   vector.
 ///
 /// # Returns
-/// - The entry point of the user app.
+/// - The entry point of the user app, or of its interpreter if any.
 /// - The stack pointer of the user app.
+/// - The start of the heap, after the end of the loaded ELF (bss included).
+///   The heap is not mapped until it is grown by `brk`.
+/// - The lowest address to place `mmap` areas without an address hint.
 pub fn load_user_app(
     uspace: &mut AddrSpace,
-    args: &[String],
-    envs: &[String],
-) -> AxResult<(VirtAddr, VirtAddr)> {
-    if args.is_empty() {
-        return Err(AxError::InvalidInput);
-    }
-    let file_data = axfs::api::read(args[0].as_str())?;
-    let elf = ElfFile::new(&file_data).map_err(|_| AxError::InvalidData)?;
-
-    if let Some(interp) = elf
-        .program_iter()
-        .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
-    {
-        let interp = match interp.get_data(&elf) {
-            Ok(SegmentData::Undefined(data)) => data,
-            _ => panic!("Invalid data in Interp Elf Program Header"),
-        };
+    app: &UserApp,
+    credentials: Credentials,
+) -> LinuxResult<(VirtAddr, VirtAddr, VirtAddr, VirtAddr)> {
+    // Both are checked by `read_user_app`.
+    let elf = ElfFile::new(&app.elf_data).map_err(|_| LinuxError::ENOEXEC)?;
+    let interp = app
+        .interp_data
+        .as_deref()
+        .map(|data| ElfFile::new(data).map_err(|_| LinuxError::ENOEXEC))
+        .transpose()?;
+
+    // No way back from here, the old program is gone.
+    uspace.unmap_user_areas()?;
+    map_trampoline(uspace)?;
 
-        let interp_path = axfs::api::canonicalize(
-            CStr::from_bytes_with_nul(interp)
-                .map_err(|_| AxError::InvalidData)?
-                .to_str()
-                .map_err(|_| AxError::InvalidData)?,
-        )?;
-
-        // if interp_path == "/lib/ld-linux-riscv64-lp64.so.1"
-        //     || interp_path == "/lib/ld-musl-riscv64.so.1"
-        //     || interp_path == "/lib64/ld-linux-loongarch-lp64d.so.1"
-        // // || interp_path == "/lib64/ld-linux-x86-64.so.2"
-        // // || interp_path == "/lib/ld-linux-aarch64.so.1"
-        // {
-        //     // TODO: Use soft link
-        //     interp_path = String::from("/musl/lib/libc.so");
-        // }
-        //
-        // // if interp_path == "/lib64/ld-linux-x86-64.so.2" {
-        //     interp_path = String::from("/glibc/lib/ld-linux-x86-64.so.2")
-        // }
-
-        // Set the first argument to the path of the user app.
-        let mut new_args = vec![interp_path];
-        new_args.extend_from_slice(args);
-        return load_user_app(uspace, &new_args, envs);
+    let randomize = aslr_enabled();
+    let pie_base = axconfig::plat::USER_PIE_BASE + random_offset(randomize, MMAP_RND_PAGES);
+    let image = elf::map_elf(uspace, &elf, pie_base.into())?;
+    if elf::is_static(&elf) {
+        // Nothing is going to relocate the image and protect it afterwards.
+        elf::protect_relro(uspace, &elf)?;
     }
+    let heap_start = image.end + random_offset(randomize, BRK_RND_PAGES);
+    let mmap_base =
+        VirtAddr::from(axconfig::plat::USER_MMAP_BASE + random_offset(randomize, MMAP_RND_PAGES));
+
+    // The interpreter is placed like a `mmap` area.
+    let (entry, interp_base) = match &interp {
+        Some(interp) => {
+            let base = uspace
+                .find_free_area(
+                    mmap_base,
+                    elf::image_size(interp),
+                    VirtAddrRange::new(uspace.base(), uspace.end()),
+                )
+                .ok_or(LinuxError::ENOMEM)?;
+            (elf::map_elf(uspace, interp, base)?.entry, base)
+        }
+        None => (image.entry, VirtAddr::from(0)),
+    };
 
-    let (entry, mut auxv) = map_elf(uspace, &elf)?;
     // The user stack is divided into two parts:
     // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
     // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
     //  When the app starts running, the stack pointer points to `ustack_pointer`.
-    let ustack_end = VirtAddr::from_usize(axconfig::plat::USER_STACK_TOP);
-    let ustack_size = axconfig::plat::USER_STACK_SIZE;
+    let ustack_end =
+        VirtAddr::from(axconfig::plat::USER_STACK_TOP - random_offset(randomize, STACK_RND_PAGES));
+    let auxv = vec![
+        (AT_PHDR, image.phdr.as_usize()),
+        (AT_PHENT, size_of::<ProgramHeader64>()),
+        (AT_PHNUM, image.phnum),
+        (AT_PAGESZ, PAGE_SIZE_4K),
+        (AT_BASE, interp_base.as_usize()),
+        (AT_FLAGS, 0),
+        (AT_ENTRY, image.entry.as_usize()),
+        (AT_UID, credentials.uid as _),
+        (AT_EUID, credentials.euid as _),
+        (AT_GID, credentials.gid as _),
+        (AT_EGID, credentials.egid as _),
+        (AT_HWCAP, stack::HWCAP),
+        (AT_CLKTCK, CLOCK_TICKS),
+        // There are no set-user-ID programs.
+        (AT_SECURE, 0),
+        (AT_SYSINFO_EHDR, crate::vdso::VDSO_BASE),
+    ];
+    let (stack_data, user_sp) =
+        stack::init_stack(ustack_end, &app.args, &app.envs, &app.path, auxv);
+    let ustack_size =
+        axconfig::plat::USER_STACK_SIZE.max((stack_data.len() + PAGE_SIZE_4K).align_up_4k());
     let ustack_start = ustack_end - ustack_size;
     debug!(
         "Mapping user stack: {:#x?} -> {:#x?}",
         ustack_start, ustack_end
     );
 
-    let stack_data = app_stack_region(args, envs, &mut auxv, ustack_start, ustack_size);
-    uspace.map_alloc(
-        ustack_start,
-        ustack_size,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
-    )?;
-
-    let heap_start = VirtAddr::from_usize(axconfig::plat::USER_HEAP_BASE);
-    let heap_size = axconfig::plat::USER_HEAP_SIZE;
-    uspace.map_alloc(
-        heap_start,
-        heap_size,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
-    )?;
-
-    let user_sp = ustack_end - stack_data.len();
-
-    assert!(user_sp.is_aligned(16usize), "user sp is not aligned to 16");
-
+    let mut stack_flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+    if elf::exec_stack(&elf) {
+        stack_flags |= MappingFlags::EXECUTE;
+    }
+    uspace.map_alloc(ustack_start, ustack_size, stack_flags, true)?;
+    // The stack grows down on page faults, up to `RLIMIT_STACK`.
+    uspace.set_growsdown(ustack_start, ustack_size)?;
     uspace.write(user_sp, stack_data.as_slice())?;
 
     debug!("entry: {:#x?}  sp:: {:#x?}", entry, user_sp);
 
-    Ok((entry, user_sp))
+    Ok((entry, user_sp, heap_start, mmap_base))
 }
 
-#[percpu::def_percpu]
-static mut ACCESSING_USER_MEM: bool = false;
+/// Returns whether the range is in user space.
+pub fn is_user_range(start: VirtAddr, size: usize) -> bool {
+    let base = axconfig::plat::USER_SPACE_BASE;
+    start.as_usize() >= base
+        && start
+            .as_usize()
+            .checked_add(size)
+            .is_some_and(|end| end <= base + axconfig::plat::USER_SPACE_SIZE)
+}
 
-/// Enables scoped access into user memory, allowing page faults to occur inside
-/// kernel.
-pub fn access_user_memory<R>(f: impl FnOnce() -> R) -> R {
-    ACCESSING_USER_MEM.with_current(|v| {
-        *v = true;
-        let result = f();
-        *v = false;
-        result
-    })
+/// Copies `dst.len()` bytes from user space at `src`.
+///
+/// The pages are faulted in like the accesses from user space, and `EFAULT` is
+/// returned if any of them cannot be read, even if it is unmapped by another
+/// thread meanwhile.
+pub fn copy_from_user(dst: &mut [u8], src: VirtAddr) -> LinuxResult {
+    if !is_user_range(src, dst.len()) {
+        return Err(LinuxError::EFAULT);
+    }
+    if unsafe { user_copy(dst.as_mut_ptr(), src.as_ptr(), dst.len()) } != 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    Ok(())
 }
 
-/// Check if the current thread is accessing user memory.
-pub fn is_accessing_user_memory() -> bool {
-    ACCESSING_USER_MEM.read_current()
+/// Copies `src` to user space at `dst`.
+///
+/// See [`copy_from_user`] for how the faults are handled.
+pub fn copy_to_user(dst: VirtAddr, src: &[u8]) -> LinuxResult {
+    if !is_user_range(dst, src.len()) {
+        return Err(LinuxError::EFAULT);
+    }
+    if unsafe { user_copy(dst.as_mut_ptr(), src.as_ptr(), src.len()) } != 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    Ok(())
+}
+
+/// Faults in the pages of the range in user space for reading, or writing if
+/// `write` is set, so that they can be accessed by the kernel directly.
+///
+/// Returns `EFAULT` if any of the pages cannot be accessed.
+pub fn fault_in_user(start: VirtAddr, size: usize, write: bool) -> LinuxResult {
+    if !is_user_range(start, size) {
+        return Err(LinuxError::EFAULT);
+    }
+    if size == 0 {
+        return Ok(());
+    }
+    for page in PageIter4K::new(start.align_down_4k(), (start + size).align_up_4k()).unwrap() {
+        let accessible = if write {
+            unsafe { probe_user_write(page.as_mut_ptr()) }
+        } else {
+            let mut byte = 0u8;
+            unsafe { user_copy(&mut byte, page.as_ptr(), 1) == 0 }
+        };
+        if !accessible {
+            return Err(LinuxError::EFAULT);
+        }
+    }
+    Ok(())
+}
+
+/// Unmaps `frames` from the shared file mappings of all processes, e.g. the
+/// pages removed from a file by truncation, so that the next accesses fault on
+/// the file again.
+pub fn unmap_shared_frames(frames: &BTreeSet<PhysAddr>) {
+    for process in get_all_processes() {
+        if let Some(process_data) = get_process_data(process.get_pid()) {
+            process_data.addr_space.lock().unmap_file_frames(frames);
+        }
+    }
 }
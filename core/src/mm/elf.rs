@@ -0,0 +1,306 @@
+//! Checking ELF images and mapping them into user address spaces.
+
+use core::ffi::CStr;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
+use xmas_elf::ElfFile;
+use xmas_elf::header::{Class, Data, Machine, Type as ElfType};
+use xmas_elf::program::{Flags, ProgramHeader, ProgramHeader64, Type};
+
+/// The segment whose flags tell whether the stack should be executable.
+const PT_GNU_STACK: u32 = 0x6474_e551;
+
+#[cfg(target_arch = "x86_64")]
+const ELF_MACHINE: Machine = Machine::X86_64;
+#[cfg(target_arch = "riscv64")]
+const ELF_MACHINE: Machine = Machine::RISC_V;
+#[cfg(target_arch = "aarch64")]
+const ELF_MACHINE: Machine = Machine::AArch64;
+/// `EM_LOONGARCH`, which is not known by `xmas_elf`.
+#[cfg(target_arch = "loongarch64")]
+const ELF_MACHINE: Machine = Machine::Other(0x102);
+
+/// An ELF image mapped into an address space.
+pub(super) struct LoadedElf {
+    /// The entry point.
+    pub entry: VirtAddr,
+    /// The address of the program headers in memory, or 0 if they are not
+    /// loaded.
+    pub phdr: VirtAddr,
+    /// The number of program headers.
+    pub phnum: usize,
+    /// The offset added to the addresses in the image, i.e. 0 for
+    /// non-relocatable executables.
+    pub bias: usize,
+    /// The end of the highest segment, page aligned.
+    pub end: VirtAddr,
+}
+
+fn program_headers<'a>(elf: &'a ElfFile) -> impl Iterator<Item = &'a ProgramHeader64> + 'a {
+    elf.program_iter().filter_map(|ph| match ph {
+        ProgramHeader::Ph64(ph) => Some(ph),
+        ProgramHeader::Ph32(_) => None,
+    })
+}
+
+fn load_segments<'a>(elf: &'a ElfFile) -> impl Iterator<Item = &'a ProgramHeader64> + 'a {
+    program_headers(elf).filter(|ph| ph.get_type() == Ok(Type::Load))
+}
+
+/// Returns the bytes of the file covered by a segment.
+fn segment_data<'a>(elf: &ElfFile<'a>, ph: &ProgramHeader64) -> LinuxResult<&'a [u8]> {
+    let start = ph.offset as usize;
+    let end = start
+        .checked_add(ph.file_size as usize)
+        .ok_or(LinuxError::ENOEXEC)?;
+    elf.input.get(start..end).ok_or(LinuxError::ENOEXEC)
+}
+
+/// Returns the page aligned range covering all loadable segments, before
+/// relocation.
+fn image_range(elf: &ElfFile) -> VirtAddrRange {
+    let start = load_segments(elf)
+        .map(|ph| ph.virtual_addr as usize)
+        .min()
+        .unwrap_or(0);
+    let end = load_segments(elf)
+        .map(|ph| (ph.virtual_addr + ph.mem_size) as usize)
+        .max()
+        .unwrap_or(0);
+    VirtAddrRange::new(
+        VirtAddr::from(start).align_down_4k(),
+        VirtAddr::from(end).align_up_4k(),
+    )
+}
+
+/// Checks the headers of an ELF image, so that a malformed image is rejected
+/// with `ENOEXEC` before anything is mapped.
+///
+/// It also makes sure that the image can be walked by `xmas_elf` without
+/// panicking.
+pub(super) fn check_elf(elf: &ElfFile) -> LinuxResult {
+    let pt2 = &elf.header.pt2;
+    let ph_table_size = pt2.ph_entry_size() as u64 * pt2.ph_count() as u64;
+    if elf.header.pt1.class() != Class::SixtyFour
+        || elf.header.pt1.data() != Data::LittleEndian
+        || !matches!(
+            pt2.type_().as_type(),
+            ElfType::Executable | ElfType::SharedObject
+        )
+        || pt2.machine().as_machine() != ELF_MACHINE
+        || pt2.ph_count() == 0
+        || pt2.ph_entry_size() as usize != size_of::<ProgramHeader64>()
+        || pt2.ph_offset() as usize % align_of::<ProgramHeader64>() != 0
+        || pt2
+            .ph_offset()
+            .checked_add(ph_table_size)
+            .is_none_or(|end| end > elf.input.len() as u64)
+    {
+        return Err(LinuxError::ENOEXEC);
+    }
+
+    let mut prev_end = 0;
+    for ph in program_headers(elf) {
+        match ph.get_type() {
+            Ok(Type::Load) => {
+                let end = ph
+                    .virtual_addr
+                    .checked_add(ph.mem_size)
+                    .ok_or(LinuxError::ENOEXEC)?;
+                // Segments must be sorted and must not overlap, so that at most
+                // one page is shared by two adjacent segments.
+                if ph.file_size > ph.mem_size
+                    || ph.virtual_addr % PAGE_SIZE_4K as u64 != ph.offset % PAGE_SIZE_4K as u64
+                    || ph.virtual_addr < prev_end
+                {
+                    return Err(LinuxError::ENOEXEC);
+                }
+                segment_data(elf, ph)?;
+                prev_end = end;
+            }
+            Ok(Type::Interp) => {
+                let path = segment_data(elf, ph)?;
+                CStr::from_bytes_with_nul(path)
+                    .ok()
+                    .and_then(|path| path.to_str().ok())
+                    .ok_or(LinuxError::ENOEXEC)?;
+            }
+            Ok(Type::Tls) => {
+                if ph.file_size > ph.mem_size || (ph.align != 0 && !ph.align.is_power_of_two()) {
+                    return Err(LinuxError::ENOEXEC);
+                }
+                segment_data(elf, ph)?;
+            }
+            Ok(Type::GnuRelro) => {
+                let end = ph
+                    .virtual_addr
+                    .checked_add(ph.mem_size)
+                    .ok_or(LinuxError::ENOEXEC)?;
+                if !load_segments(elf).any(|seg| {
+                    seg.virtual_addr <= ph.virtual_addr && end <= seg.virtual_addr + seg.mem_size
+                }) {
+                    return Err(LinuxError::ENOEXEC);
+                }
+            }
+            _ => {}
+        }
+    }
+    if load_segments(elf).next().is_none() {
+        return Err(LinuxError::ENOEXEC);
+    }
+
+    let range = image_range(elf);
+    let user_space = VirtAddrRange::from_start_size(
+        VirtAddr::from(axconfig::plat::USER_SPACE_BASE),
+        axconfig::plat::USER_SPACE_SIZE,
+    );
+    let fits = if is_relocatable(elf) {
+        range.size() < user_space.size()
+    } else {
+        user_space.contains_range(range)
+    };
+    if !fits {
+        return Err(LinuxError::ENOEXEC);
+    }
+    Ok(())
+}
+
+/// Returns whether the image can be loaded at any address.
+pub(super) fn is_relocatable(elf: &ElfFile) -> bool {
+    elf.header.pt2.type_().as_type() == ElfType::SharedObject
+}
+
+/// Returns the path of the program interpreter, if any.
+///
+/// The image must have been checked by [`check_elf`].
+pub(super) fn interp_path<'a>(elf: &ElfFile<'a>) -> Option<&'a str> {
+    let ph = program_headers(elf).find(|ph| ph.get_type() == Ok(Type::Interp))?;
+    let path = segment_data(elf, ph).ok()?;
+    CStr::from_bytes_with_nul(path).ok()?.to_str().ok()
+}
+
+/// Returns whether the image asks for an executable stack.
+pub(super) fn exec_stack(elf: &ElfFile) -> bool {
+    program_headers(elf)
+        .find(|ph| ph.get_type() == Ok(Type::OsSpecific(PT_GNU_STACK)))
+        .is_some_and(|ph| ph.flags.is_execute())
+}
+
+/// Returns the size of the memory needed to map the image.
+pub(super) fn image_size(elf: &ElfFile) -> usize {
+    image_range(elf).size()
+}
+
+fn mapping_flags(flags: Flags) -> MappingFlags {
+    let mut mapping_flags = MappingFlags::USER;
+    if flags.is_read() {
+        mapping_flags |= MappingFlags::READ;
+    }
+    if flags.is_write() {
+        mapping_flags |= MappingFlags::WRITE;
+    }
+    if flags.is_execute() {
+        mapping_flags |= MappingFlags::EXECUTE;
+    }
+    mapping_flags
+}
+
+/// Maps an ELF image into the address space, with the permissions of its
+/// segments.
+///
+/// A relocatable image is mapped with its lowest segment at `base`, others are
+/// mapped at the addresses they are linked at. The image must have been
+/// checked by [`check_elf`].
+pub(super) fn map_elf(
+    uspace: &mut AddrSpace,
+    elf: &ElfFile,
+    base: VirtAddr,
+) -> LinuxResult<LoadedElf> {
+    let bias = if is_relocatable(elf) {
+        base.as_usize()
+            .wrapping_sub(image_range(elf).start.as_usize())
+    } else {
+        0
+    };
+
+    let mut mapped_end = VirtAddr::from(0);
+    let mut last_flags = MappingFlags::empty();
+    for ph in load_segments(elf) {
+        let vaddr = VirtAddr::from((ph.virtual_addr as usize).wrapping_add(bias));
+        let start = vaddr.align_down_4k();
+        let end = (vaddr + ph.mem_size as usize).align_up_4k();
+        let flags = mapping_flags(ph.flags);
+        debug!(
+            "Mapping ELF segment: [{:#x?}, {:#x?}) flags: {:#x?}",
+            vaddr,
+            vaddr + ph.mem_size as usize,
+            flags
+        );
+
+        let mut map_start = start;
+        if start < mapped_end {
+            // The first page is shared with the previous segment, which must
+            // be accessible with the permissions of both.
+            uspace.protect(start, mapped_end - start, last_flags | flags)?;
+            map_start = mapped_end;
+        }
+        if map_start < end {
+            uspace.map_alloc(map_start, end - map_start, flags, true)?;
+        }
+        // The rest of the segment (bss) is left zero-filled.
+        uspace.write(vaddr, segment_data(elf, ph)?)?;
+        // TDOO: flush the I-cache
+        mapped_end = end;
+        last_flags = flags;
+    }
+
+    let phdr = program_headers(elf)
+        .find(|ph| ph.get_type() == Ok(Type::Phdr))
+        .map(|ph| ph.virtual_addr)
+        .or_else(|| {
+            // Find the program headers in the segment that covers them.
+            let ph_offset = elf.header.pt2.ph_offset();
+            load_segments(elf)
+                .find(|ph| ph.offset <= ph_offset && ph_offset < ph.offset + ph.file_size)
+                .map(|ph| ph_offset - ph.offset + ph.virtual_addr)
+        })
+        .map_or(VirtAddr::from(0), |phdr| {
+            VirtAddr::from((phdr as usize).wrapping_add(bias))
+        });
+
+    Ok(LoadedElf {
+        entry: VirtAddr::from((elf.header.pt2.entry_point() as usize).wrapping_add(bias)),
+        phdr,
+        phnum: elf.header.pt2.ph_count() as usize,
+        bias,
+        end: mapped_end,
+    })
+}
+
+/// Makes the `PT_GNU_RELRO` part of a mapped image read-only.
+///
+/// This is only done for images checked by [`is_static`]. Otherwise the image
+/// has to be relocated first, and the dynamic linker does it afterwards.
+pub(super) fn protect_relro(uspace: &mut AddrSpace, elf: &ElfFile) -> LinuxResult {
+    let Some(ph) = program_headers(elf).find(|ph| ph.get_type() == Ok(Type::GnuRelro)) else {
+        return Ok(());
+    };
+    // Like the dynamic linker, the partial page at the end stays writable.
+    let start = VirtAddr::from(ph.virtual_addr as usize).align_down_4k();
+    let end = VirtAddr::from((ph.virtual_addr + ph.mem_size) as usize).align_down_4k();
+    if start < end {
+        uspace.protect(start, end - start, MappingFlags::READ | MappingFlags::USER)?;
+    }
+    Ok(())
+}
+
+/// Returns whether the image needs no relocation at runtime, i.e. it is a
+/// statically linked, non-relocatable executable.
+pub(super) fn is_static(elf: &ElfFile) -> bool {
+    !is_relocatable(elf)
+        && !program_headers(elf)
+            .any(|ph| matches!(ph.get_type(), Ok(Type::Dynamic) | Ok(Type::Interp)))
+}
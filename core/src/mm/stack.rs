@@ -0,0 +1,145 @@
+//! Building the initial user stack of a new program.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use linux_raw_sys::general::{AT_EXECFN, AT_NULL, AT_PLATFORM, AT_RANDOM};
+use memory_addr::{MemoryAddr, VirtAddr};
+
+use crate::random::fill_random;
+
+/// The platform string passed in `AT_PLATFORM`.
+#[cfg(target_arch = "x86_64")]
+const PLATFORM: &str = "x86_64";
+#[cfg(target_arch = "riscv64")]
+const PLATFORM: &str = "riscv64";
+#[cfg(target_arch = "aarch64")]
+const PLATFORM: &str = "aarch64";
+#[cfg(target_arch = "loongarch64")]
+const PLATFORM: &str = "loongarch";
+
+/// The hardware capabilities passed in `AT_HWCAP`, i.e. the features that
+/// the kernel requires from the CPU.
+// FPU | TSC | CX8 | CMOV | MMX | FXSR | SSE | SSE2
+#[cfg(target_arch = "x86_64")]
+pub(super) const HWCAP: usize =
+    (1 << 0) | (1 << 4) | (1 << 8) | (1 << 15) | (1 << 23) | (1 << 24) | (1 << 25) | (1 << 26);
+// One bit for each single-letter extension: I, M, A, F, D and C.
+#[cfg(target_arch = "riscv64")]
+pub(super) const HWCAP: usize = {
+    const fn ext(c: u8) -> usize {
+        1 << (c - b'a')
+    }
+    ext(b'i') | ext(b'm') | ext(b'a') | ext(b'f') | ext(b'd') | ext(b'c')
+};
+// HWCAP_FP | HWCAP_ASIMD
+#[cfg(target_arch = "aarch64")]
+pub(super) const HWCAP: usize = (1 << 0) | (1 << 1);
+// HWCAP_LOONGARCH_CPUCFG | LAM | UAL | FPU
+#[cfg(target_arch = "loongarch64")]
+pub(super) const HWCAP: usize = (1 << 0) | (1 << 1) | (1 << 2) | (1 << 3);
+
+/// Writes data downwards from the top of a stack.
+struct StackWriter {
+    top: VirtAddr,
+    /// The data written so far, in reverse order.
+    data: Vec<u8>,
+}
+
+impl StackWriter {
+    fn new(top: VirtAddr) -> Self {
+        Self {
+            top,
+            data: Vec::new(),
+        }
+    }
+
+    fn sp(&self) -> VirtAddr {
+        self.top - self.data.len()
+    }
+
+    /// Pushes `bytes` onto the stack, returns the address where they start.
+    fn push(&mut self, bytes: &[u8]) -> usize {
+        self.data.extend(bytes.iter().rev());
+        self.sp().as_usize()
+    }
+
+    /// Pushes a NUL-terminated string, returns its address.
+    fn push_str(&mut self, s: &str) -> usize {
+        self.push(&[0]);
+        self.push(s.as_bytes())
+    }
+
+    fn push_usize(&mut self, value: usize) {
+        self.push(&value.to_ne_bytes());
+    }
+
+    fn align(&mut self, align: usize) {
+        while !self.sp().is_aligned(align) {
+            self.push(&[0]);
+        }
+    }
+
+    /// Returns the content of the stack, from the stack pointer to the top.
+    fn finish(mut self) -> (Vec<u8>, VirtAddr) {
+        let sp = self.sp();
+        self.data.reverse();
+        (self.data, sp)
+    }
+}
+
+/// Builds the initial stack of a new program, which holds its arguments,
+/// environment variables and auxiliary vector.
+///
+/// `auxv` does not need to include `AT_RANDOM`, `AT_EXECFN`, `AT_PLATFORM`
+/// and `AT_NULL`, which are added here since they point into the stack.
+///
+/// Returns the content of the stack and the initial stack pointer, where the
+/// content is to be written. The stack pointer points to `argc` and is aligned
+/// to 16 bytes.
+pub(super) fn init_stack(
+    top: VirtAddr,
+    args: &[String],
+    envs: &[String],
+    execfn: &str,
+    mut auxv: Vec<(u32, usize)>,
+) -> (Vec<u8>, VirtAddr) {
+    let mut stack = StackWriter::new(top);
+    // An end marker, then the strings.
+    stack.push_usize(0);
+    let execfn = stack.push_str(execfn);
+    let envp: Vec<_> = envs.iter().rev().map(|env| stack.push_str(env)).collect();
+    let argv: Vec<_> = args.iter().rev().map(|arg| stack.push_str(arg)).collect();
+    let platform = stack.push_str(PLATFORM);
+    let mut random = [0u8; 16];
+    fill_random(&mut random);
+    let random = stack.push(&random);
+
+    auxv.extend([
+        (AT_RANDOM, random),
+        (AT_EXECFN, execfn),
+        (AT_PLATFORM, platform),
+        (AT_NULL, 0),
+    ]);
+    // Keep the final stack pointer aligned, there are 3 words besides the
+    // pointers and auxv: `argc` and the NULLs terminating `argv` and `envp`.
+    stack.align(16);
+    if (argv.len() + envp.len() + 3) % 2 != 0 {
+        stack.push_usize(0);
+    }
+    for &(key, value) in auxv.iter().rev() {
+        stack.push_usize(value);
+        stack.push_usize(key as usize);
+    }
+    stack.push_usize(0);
+    // The pointers were collected in reverse order.
+    for &env in &envp {
+        stack.push_usize(env);
+    }
+    stack.push_usize(0);
+    for &arg in &argv {
+        stack.push_usize(arg);
+    }
+    stack.push_usize(args.len());
+    stack.finish()
+}
@@ -0,0 +1,566 @@
+//! The page cache of regular files.
+//!
+//! Pages are cached by the file system node of the file and their index in
+//! it. File reads and writes, and file mappings, all go through the same
+//! cached pages, so they see the same content. Shared mappings map the cached
+//! pages directly.
+//!
+//! Writes that extend a file are written through at once, so that its size
+//! on the file system stays current. Other writes only mark the pages dirty,
+//! which are written back by `fsync`, `sync`, the periodic writeback, or
+//! before they are evicted.
+//!
+//! A file removed while it is still open or mapped keeps its content in the
+//! cache until it is closed, and is no longer written back.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use axalloc::global_allocator;
+use axerrno::{AxError, AxResult, ax_err};
+use axfs_vfs::VfsNodeRef;
+use axhal::mem::phys_to_virt;
+use axsync::Mutex;
+use core::time::Duration;
+use memory_addr::{PAGE_SIZE_4K, PhysAddr};
+
+use crate::mm::unmap_shared_frames;
+
+/// The identifier of a cached file.
+pub type InodeId = u64;
+
+/// The cache is shrunk when fewer pages than this are free in the system.
+const LOW_WATERMARK_PAGES: usize = axconfig::plat::PHYS_MEMORY_SIZE / PAGE_SIZE_4K / 16;
+/// The number of pages evicted at a time.
+const EVICT_BATCH: usize = 64;
+/// The interval of the periodic writeback of dirty pages.
+const WRITEBACK_INTERVAL: Duration = Duration::from_secs(5);
+
+const PAGE_SIZE: u64 = PAGE_SIZE_4K as u64;
+
+struct CachedPage {
+    frame: PhysAddr,
+    /// Whether the page is modified and not written back yet
+    dirty: bool,
+    /// The time of the last access, which is its key in `PageCacheInner::lru`
+    accessed: u64,
+}
+
+impl CachedPage {
+    fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(phys_to_virt(self.frame).as_ptr(), PAGE_SIZE_4K) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn as_mut_slice(&self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(phys_to_virt(self.frame).as_mut_ptr(), PAGE_SIZE_4K)
+        }
+    }
+
+    /// Returns whether the page is mapped by any address space.
+    fn is_mapped(&self) -> bool {
+        axmm::frame_ref_count(self.frame) > 1
+    }
+}
+
+impl Drop for CachedPage {
+    fn drop(&mut self) {
+        axmm::dealloc_frame(self.frame);
+    }
+}
+
+struct Inode {
+    /// The file system node to read and write back the pages
+    node: VfsNodeRef,
+    /// The size of the file in bytes
+    size: u64,
+    /// Whether the file is removed, in which case all its pages are cached
+    /// and never written back
+    unlinked: bool,
+}
+
+impl Inode {
+    /// Returns whether the file is open or mapped, i.e. its node is held by
+    /// others than the cache.
+    fn is_used(&self) -> bool {
+        Arc::strong_count(&self.node) > 1
+    }
+
+    /// Writes back the page at `index`, without extending the file.
+    fn write_back(&self, index: u64, page: &CachedPage) -> AxResult {
+        let offset = index * PAGE_SIZE;
+        if self.unlinked || offset >= self.size {
+            return Ok(());
+        }
+        let len = PAGE_SIZE.min(self.size - offset) as usize;
+        write_all(&self.node, offset, &page.as_slice()[..len])
+    }
+}
+
+fn write_all(node: &VfsNodeRef, mut offset: u64, mut buf: &[u8]) -> AxResult {
+    while !buf.is_empty() {
+        let len = node.write_at(offset, buf)?;
+        if len == 0 {
+            return ax_err!(Io, "short write to the file");
+        }
+        offset += len as u64;
+        buf = &buf[len..];
+    }
+    Ok(())
+}
+
+/// Returns the key of the file of `node` in `PageCacheInner::nodes`.
+fn node_key(node: &VfsNodeRef) -> usize {
+    Arc::as_ptr(node) as *const () as usize
+}
+
+struct PageCacheInner {
+    inodes: BTreeMap<InodeId, Inode>,
+    /// The inodes by the addresses of their file system nodes, which are kept
+    /// alive by the inodes
+    nodes: BTreeMap<usize, InodeId>,
+    /// The pages by their inodes and indices
+    pages: BTreeMap<(InodeId, u64), CachedPage>,
+    /// The pages by the time of their last access, least recently used first
+    lru: BTreeMap<u64, (InodeId, u64)>,
+    next_inode: InodeId,
+    /// Incremented on each access to a page
+    clock: u64,
+}
+
+impl PageCacheInner {
+    fn inode(&self, inode: InodeId) -> AxResult<&Inode> {
+        self.inodes.get(&inode).ok_or(AxError::NotFound)
+    }
+
+    /// Returns the inode of the file of `node`, which is added if it is not
+    /// cached yet.
+    fn inode_of(&mut self, node: &VfsNodeRef) -> AxResult<InodeId> {
+        if let Some(&inode) = self.nodes.get(&node_key(node)) {
+            return Ok(inode);
+        }
+        let size = node.get_attr()?.size();
+        let inode = self.next_inode;
+        self.next_inode += 1;
+        self.nodes.insert(node_key(node), inode);
+        self.inodes.insert(
+            inode,
+            Inode {
+                node: node.clone(),
+                size,
+                unlinked: false,
+            },
+        );
+        Ok(inode)
+    }
+
+    /// Returns the page at `index`, which is read from the file if it is not
+    /// cached.
+    fn page(&mut self, inode: InodeId, index: u64) -> AxResult<&mut CachedPage> {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(page) = self.pages.get_mut(&(inode, index)) {
+            self.lru.remove(&page.accessed);
+            self.lru.insert(clock, (inode, index));
+            page.accessed = clock;
+            return Ok(self.pages.get_mut(&(inode, index)).unwrap());
+        }
+        if global_allocator().available_pages() < LOW_WATERMARK_PAGES {
+            self.evict(EVICT_BATCH, false);
+        }
+        let frame = axmm::alloc_frame(true).ok_or(AxError::NoMemory)?;
+        let page = CachedPage {
+            frame,
+            dirty: false,
+            accessed: clock,
+        };
+        // The part beyond the end of file is left zero-filled, and so are the
+        // pages of removed files, which are only added when they grow.
+        let file = self.inode(inode)?;
+        if !file.unlinked {
+            let buf = page.as_mut_slice();
+            let mut read = 0;
+            while read < PAGE_SIZE_4K {
+                let len = file
+                    .node
+                    .read_at(index * PAGE_SIZE + read as u64, &mut buf[read..])?;
+                if len == 0 {
+                    break;
+                }
+                read += len;
+            }
+        }
+        self.lru.insert(clock, (inode, index));
+        Ok(self.pages.entry((inode, index)).or_insert(page))
+    }
+
+    /// Drops the pages of the inode from the index `start` on, without
+    /// writing them back.
+    fn drop_pages(&mut self, inode: InodeId, start: u64) -> BTreeMap<(InodeId, u64), CachedPage> {
+        let mut dropped = self.pages.split_off(&(inode, start));
+        let mut after = dropped.split_off(&(inode + 1, 0));
+        self.pages.append(&mut after);
+        for page in dropped.values() {
+            self.lru.remove(&page.accessed);
+        }
+        dropped
+    }
+
+    /// Drops the page, without writing it back.
+    fn drop_page(&mut self, key: (InodeId, u64)) {
+        if let Some(page) = self.pages.remove(&key) {
+            self.lru.remove(&page.accessed);
+        }
+    }
+
+    /// Writes back the dirty pages of the inode.
+    fn sync(&mut self, inode: InodeId) -> AxResult {
+        let file = self.inodes.get(&inode).ok_or(AxError::NotFound)?;
+        for (&(_, index), page) in self.pages.range_mut((inode, 0)..=(inode, u64::MAX)) {
+            if page.dirty {
+                file.write_back(index, page)?;
+                page.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops all pages of the inode and forgets it, without writing them back.
+    fn remove(&mut self, inode: InodeId) {
+        self.drop_pages(inode, 0);
+        if let Some(file) = self.inodes.remove(&inode) {
+            self.nodes.remove(&node_key(&file.node));
+        }
+    }
+
+    /// Evicts at most `count` least recently used pages, which are not
+    /// mapped and not of removed files. Dirty pages are written back first,
+    /// or skipped if `clean_only` is true. Returns the number of pages
+    /// evicted.
+    fn evict(&mut self, count: usize, clean_only: bool) -> usize {
+        let victims: Vec<_> = self
+            .lru
+            .values()
+            .copied()
+            .filter(|key| {
+                let page = &self.pages[key];
+                !page.is_mapped()
+                    && !(clean_only && page.dirty)
+                    && self.inodes.get(&key.0).is_some_and(|file| !file.unlinked)
+            })
+            .take(count)
+            .collect();
+        let mut evicted = 0;
+        for key in victims {
+            let page = &self.pages[&key];
+            if page.dirty {
+                if let Err(err) = self.inodes[&key.0].write_back(key.1, page) {
+                    warn!(
+                        "[page cache] failed to write back inode {} page {}: {:?}",
+                        key.0, key.1, err
+                    );
+                    continue;
+                }
+            }
+            self.drop_page(key);
+            evicted += 1;
+        }
+        self.release();
+        evicted
+    }
+
+    /// Forgets the files which are no longer used and have no cached pages,
+    /// and the removed files which are no longer used.
+    fn release(&mut self) {
+        let unused: Vec<_> = self
+            .inodes
+            .iter()
+            .filter(|&(&inode, file)| {
+                !file.is_used()
+                    && (file.unlinked
+                        || self
+                            .pages
+                            .range((inode, 0)..=(inode, u64::MAX))
+                            .next()
+                            .is_none())
+            })
+            .map(|(&inode, _)| inode)
+            .collect();
+        for inode in unused {
+            self.remove(inode);
+        }
+    }
+}
+
+/// The page cache of regular files.
+pub struct PageCache {
+    inner: Mutex<PageCacheInner>,
+}
+
+impl PageCache {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(PageCacheInner {
+                inodes: BTreeMap::new(),
+                nodes: BTreeMap::new(),
+                pages: BTreeMap::new(),
+                lru: BTreeMap::new(),
+                next_inode: 1,
+                clock: 0,
+            }),
+        }
+    }
+
+    /// Returns the inode of the file whose file system node is `node`.
+    pub fn inode(&self, node: &VfsNodeRef) -> AxResult<InodeId> {
+        self.inner.lock().inode_of(node)
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn size(&self, inode: InodeId) -> AxResult<u64> {
+        Ok(self.inner.lock().inode(inode)?.size)
+    }
+
+    /// Reads the file at `offset`, returns the number of bytes read.
+    pub fn read_at(&self, inode: InodeId, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let mut inner = self.inner.lock();
+        let size = inner.inode(inode)?.size;
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = (buf.len() as u64).min(size - offset) as usize;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let page_offset = (pos % PAGE_SIZE) as usize;
+            let count = (PAGE_SIZE_4K - page_offset).min(len - done);
+            let page = inner.page(inode, pos / PAGE_SIZE)?;
+            buf[done..done + count]
+                .copy_from_slice(&page.as_slice()[page_offset..page_offset + count]);
+            done += count;
+        }
+        Ok(len)
+    }
+
+    /// Writes `buf` into the file at `offset`, returns the number of bytes
+    /// written.
+    pub fn write_at(&self, inode: InodeId, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        let mut inner = self.inner.lock();
+        let file = inner.inode(inode)?;
+        let end = offset + buf.len() as u64;
+        // Removed files only grow in the cache.
+        let extend = end > file.size && !file.unlinked;
+        if extend {
+            write_all(&file.node, offset, buf)?;
+        }
+        if end > file.size {
+            inner.inodes.get_mut(&inode).unwrap().size = end;
+        }
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let page_offset = (pos % PAGE_SIZE) as usize;
+            let count = (PAGE_SIZE_4K - page_offset).min(buf.len() - done);
+            let index = pos / PAGE_SIZE;
+            let page = if extend {
+                // Only update the pages already cached.
+                inner.pages.get_mut(&(inode, index))
+            } else {
+                let page = inner.page(inode, index)?;
+                page.dirty = true;
+                Some(page)
+            };
+            if let Some(page) = page {
+                page.as_mut_slice()[page_offset..page_offset + count]
+                    .copy_from_slice(&buf[done..done + count]);
+            }
+            done += count;
+        }
+        Ok(buf.len())
+    }
+
+    /// Changes the size of the file.
+    pub fn truncate(&self, inode: InodeId, size: u64) -> AxResult {
+        let mut inner = self.inner.lock();
+        let file = inner.inode(inode)?;
+        if !file.unlinked {
+            file.node.truncate(size)?;
+        }
+        let old_size = file.size;
+        let mut dropped = BTreeMap::new();
+        if size < old_size {
+            // Drop the pages beyond the end, and clear the rest of the last
+            // page, so that the file reads zeros if it grows again.
+            dropped = inner.drop_pages(inode, size.div_ceil(PAGE_SIZE));
+            let page_offset = (size % PAGE_SIZE) as usize;
+            if page_offset != 0 {
+                if let Some(page) = inner.pages.get(&(inode, size / PAGE_SIZE)) {
+                    page.as_mut_slice()[page_offset..].fill(0);
+                }
+            }
+        }
+        inner.inodes.get_mut(&inode).unwrap().size = size;
+        // The address spaces are locked before the cache when pages are mapped.
+        drop(inner);
+        // The dropped pages are unmapped before they are freed, so that the
+        // accesses beyond the new end fault, and the stale pages are not
+        // written back over the file if it grows again.
+        let frames: BTreeSet<_> = dropped
+            .values()
+            .filter(|page| page.is_mapped())
+            .map(|page| page.frame)
+            .collect();
+        if !frames.is_empty() {
+            unmap_shared_frames(&frames);
+        }
+        Ok(())
+    }
+
+    /// Returns the frame holding the page at `offset`, to be mapped by shared
+    /// mappings.
+    pub fn frame(&self, inode: InodeId, offset: u64) -> AxResult<PhysAddr> {
+        let mut inner = self.inner.lock();
+        if offset >= inner.inode(inode)?.size {
+            return ax_err!(InvalidInput, "page beyond the end of file");
+        }
+        Ok(inner.page(inode, offset / PAGE_SIZE)?.frame)
+    }
+
+    /// Marks the page at `offset` dirty, after it is modified through a
+    /// shared mapping.
+    pub fn set_dirty(&self, inode: InodeId, offset: u64) {
+        let mut inner = self.inner.lock();
+        if let Some(page) = inner.pages.get_mut(&(inode, offset / PAGE_SIZE)) {
+            page.dirty = true;
+        }
+    }
+
+    /// Writes back the dirty pages of the file, for `fsync`.
+    pub fn sync(&self, inode: InodeId) -> AxResult {
+        self.inner.lock().sync(inode)
+    }
+
+    /// Writes back all dirty pages, for `sync`.
+    pub fn sync_all(&self) -> AxResult {
+        let mut inner = self.inner.lock();
+        let inodes: Vec<_> = inner.inodes.keys().copied().collect();
+        for inode in inodes {
+            inner.sync(inode)?;
+        }
+        Ok(())
+    }
+
+    /// Writes back the dirty pages of the file and drops its pages that are
+    /// not mapped, after it is modified bypassing the cache.
+    pub fn invalidate(&self, inode: InodeId) -> AxResult {
+        let mut inner = self.inner.lock();
+        if inner.inode(inode)?.unlinked {
+            return Ok(());
+        }
+        inner.sync(inode)?;
+        let clean: Vec<_> = inner
+            .pages
+            .range((inode, 0)..=(inode, u64::MAX))
+            .filter(|(_, page)| !page.is_mapped())
+            .map(|(&key, _)| key)
+            .collect();
+        for key in clean {
+            inner.drop_page(key);
+        }
+        let file = inner.inode(inode)?;
+        let size = file.node.get_attr()?.size();
+        inner.inodes.get_mut(&inode).unwrap().size = size;
+        Ok(())
+    }
+
+    /// Forgets the file of `node` before it is removed, its pages are dropped
+    /// without being written back.
+    ///
+    /// If the file is still open or mapped by others than the caller, which
+    /// holds `node` as well, all its pages are read into the cache instead,
+    /// and kept until it is closed.
+    pub fn unlink(&self, node: &VfsNodeRef) -> AxResult {
+        let mut inner = self.inner.lock();
+        let cached = inner.nodes.contains_key(&node_key(node));
+        if Arc::strong_count(node) <= 1 + cached as usize {
+            if let Some(&inode) = inner.nodes.get(&node_key(node)) {
+                inner.remove(inode);
+            }
+            return Ok(());
+        }
+        let inode = inner.inode_of(node)?;
+        let size = inner.inode(inode)?.size;
+        for index in 0..size.div_ceil(PAGE_SIZE) {
+            inner.page(inode, index)?;
+        }
+        inner.inodes.get_mut(&inode).unwrap().unlinked = true;
+        Ok(())
+    }
+
+    /// Writes back the dirty pages of the file of `node` before it is
+    /// renamed, and forgets it if it is not used by others than the caller,
+    /// which holds `node` as well.
+    ///
+    /// The file is looked up as a new node after it is renamed, so the files
+    /// still open keep using their own pages.
+    pub fn rename(&self, node: &VfsNodeRef) -> AxResult {
+        let mut inner = self.inner.lock();
+        if let Some(&inode) = inner.nodes.get(&node_key(node)) {
+            inner.sync(inode)?;
+            if Arc::strong_count(node) <= 2 {
+                inner.remove(inode);
+            }
+        }
+        Ok(())
+    }
+
+    /// Evicts at most `count` clean pages which are not in use, to free
+    /// memory for other allocations. Returns the number of pages evicted.
+    ///
+    /// Does nothing if the cache is in use, possibly by the current task which
+    /// is allocating memory for it.
+    pub fn reclaim(&self, count: usize) -> usize {
+        match self.inner.try_lock() {
+            Some(mut inner) => inner.evict(count, true),
+            None => 0,
+        }
+    }
+
+    /// Reads the whole file at the absolute `path`.
+    pub fn read_file(&self, path: &str) -> AxResult<Vec<u8>> {
+        let mut options = axfs::fops::OpenOptions::new();
+        options.read(true);
+        let file = axfs::fops::File::open(path, &options)?;
+        let inode = self.inode(file.get_node())?;
+        let mut data = vec![0; self.size(inode)? as usize];
+        let len = self.read_at(inode, 0, &mut data)?;
+        data.truncate(len);
+        Ok(data)
+    }
+}
+
+pub static PAGE_CACHE: PageCache = PageCache::new();
+
+fn reclaim_pages(count: usize) -> usize {
+    PAGE_CACHE.reclaim(count)
+}
+
+/// Starts the periodic writeback of dirty pages, and lets the page cache be
+/// shrunk when memory runs out.
+pub fn init() {
+    axmm::set_reclaimer(reclaim_pages);
+    axtask::spawn_raw(
+        || {
+            loop {
+                axtask::sleep(WRITEBACK_INTERVAL);
+                if let Err(err) = PAGE_CACHE.sync_all() {
+                    warn!("[page cache] periodic writeback failed: {:?}", err);
+                }
+            }
+        },
+        "writeback".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
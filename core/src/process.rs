@@ -1,21 +1,73 @@
-use crate::resource::ResourceLimits;
-use crate::shared_memory::SharedMemory;
+use crate::resource::{ResourceLimitType, ResourceLimits};
+use crate::shared_memory::{SHARED_MEMORY_MANAGER, SharedMemory};
 use crate::task::WaitQueueWrapper;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
 use axmm::{AddrSpace, kernel_aspace};
 use axns::AxNamespace;
-use axsignal::Signo;
 use axsignal::api::{ProcessSignalManager, SignalActions, ThreadSignalManager};
+use axsignal::{SignalSet, Signo};
 use axsync::RawMutex;
 use axtask::WaitQueue;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use memory_addr::{VirtAddr, VirtAddrRange};
 use spin::Mutex;
 use undefined_process::Pid;
 
+/// A job control state change of a process, until it is reported to the
+/// parent by `wait4` with `WUNTRACED` or `WCONTINUED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Stopped by the signal.
+    Stopped(Signo),
+    /// Continued by `SIGCONT`.
+    Continued,
+}
+
+/// The user and group IDs of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    /// The real user ID.
+    pub uid: u32,
+    /// The effective user ID.
+    pub euid: u32,
+    /// The real group ID.
+    pub gid: u32,
+    /// The effective group ID.
+    pub egid: u32,
+}
+
+impl Default for Credentials {
+    /// All processes run as the same unprivileged user for now.
+    fn default() -> Self {
+        Self {
+            uid: 1000,
+            euid: 1000,
+            gid: 1000,
+            egid: 1000,
+        }
+    }
+}
+
+/// The CPU time spent by threads, reported to the parent by `wait4`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CpuTimes {
+    /// The time spent in user mode, in microseconds.
+    pub utime_us: usize,
+    /// The time spent in kernel mode, in microseconds.
+    pub stime_us: usize,
+}
+
+impl core::ops::AddAssign for CpuTimes {
+    fn add_assign(&mut self, other: Self) {
+        self.utime_us += other.utime_us;
+        self.stime_us += other.stime_us;
+    }
+}
+
 pub struct ProcessData {
     /// The command line arguments
     pub command_line: Mutex<Vec<String>>,
@@ -23,10 +75,12 @@ pub struct ProcessData {
     // address space related are shared with all threads
     /// The virtual memory address space.
     pub addr_space: Arc<Mutex<AddrSpace>>,
-    /// The user heap bottom
+    /// The user heap bottom, i.e. the initial program break
     heap_bottom: AtomicUsize,
-    /// The user heap top
+    /// The user heap top, i.e. the current program break
     heap_top: AtomicUsize,
+    /// The lowest address to place `mmap` areas without an address hint
+    mmap_base: AtomicUsize,
     /// resource limits
     pub resource_limits: Arc<Mutex<ResourceLimits>>,
     /// The child exit wait queue
@@ -37,8 +91,35 @@ pub struct ProcessData {
     pub signal: Arc<ProcessSignalManager<RawMutex, WaitQueueWrapper>>,
     /// The futex table
     pub futex_table: Mutex<BTreeMap<usize, Arc<WaitQueue>>>,
-    /// Shared memory
+    /// The attached shared memory segments by their addresses.
+    ///
+    /// When both are locked, `addr_space` is locked first.
     pub shared_memory: Mutex<BTreeMap<VirtAddr, Arc<SharedMemory>>>,
+    /// The user and group IDs, inherited from the parent.
+    credentials: Mutex<Credentials>,
+    /// Whether other processes with the same credentials may access the
+    /// memory of the process, changed by `prctl(PR_SET_DUMPABLE)` and reset by
+    /// `execve`.
+    dumpable: AtomicBool,
+    /// Whether the process has called `execve` since it was forked, after
+    /// which its parent can no longer change its process group.
+    did_exec: AtomicBool,
+    /// Whether the process is stopped by a job control signal.
+    stopped: AtomicBool,
+    /// The last job control state change not reported to the parent yet.
+    job_status: Mutex<Option<JobStatus>>,
+    /// The pending signals discarded by a signal sent later, e.g. the stop
+    /// signals by `SIGCONT`, which each thread removes from its pending set.
+    discarded_signals: Mutex<SignalSet>,
+    /// The wait queue of the threads parked while the process is stopped
+    pub stop_wq: WaitQueue,
+    /// The wait status set by the first thread exiting the whole process,
+    /// which the other threads exit with.
+    group_exit_status: Mutex<Option<i32>>,
+    /// The CPU time of the exited threads.
+    thread_times: Mutex<CpuTimes>,
+    /// The CPU time of the children waited for, including their own children.
+    children_times: Mutex<CpuTimes>,
 }
 
 impl ProcessData {
@@ -51,8 +132,9 @@ impl ProcessData {
         Self {
             command_line: Mutex::new(command_line),
             addr_space,
-            heap_bottom: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
-            heap_top: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
+            heap_bottom: AtomicUsize::new(0),
+            heap_top: AtomicUsize::new(0),
+            mmap_base: AtomicUsize::new(axconfig::plat::USER_MMAP_BASE),
             resource_limits: Arc::new(Mutex::new(ResourceLimits::new())),
             futex_table: Mutex::new(BTreeMap::new()),
             child_exit_wq: WaitQueue::new(),
@@ -62,6 +144,16 @@ impl ProcessData {
                 axconfig::plat::SIGNAL_TRAMPOLINE,
             )),
             shared_memory: Mutex::new(BTreeMap::new()),
+            credentials: Mutex::new(Credentials::default()),
+            dumpable: AtomicBool::new(true),
+            did_exec: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            job_status: Mutex::new(None),
+            discarded_signals: Mutex::new(SignalSet::default()),
+            stop_wq: WaitQueue::new(),
+            group_exit_status: Mutex::new(None),
+            thread_times: Mutex::new(CpuTimes::default()),
+            children_times: Mutex::new(CpuTimes::default()),
         }
     }
 
@@ -81,6 +173,303 @@ impl ProcessData {
         self.heap_top.store(top, Ordering::Release)
     }
 
+    pub fn get_mmap_base(&self) -> usize {
+        self.mmap_base.load(Ordering::Acquire)
+    }
+
+    pub fn set_mmap_base(&self, base: usize) {
+        self.mmap_base.store(base, Ordering::Release)
+    }
+
+    pub fn credentials(&self) -> Credentials {
+        *self.credentials.lock()
+    }
+
+    pub fn set_credentials(&self, credentials: Credentials) {
+        *self.credentials.lock() = credentials;
+    }
+
+    pub fn is_dumpable(&self) -> bool {
+        self.dumpable.load(Ordering::Acquire)
+    }
+
+    pub fn set_dumpable(&self, dumpable: bool) {
+        self.dumpable.store(dumpable, Ordering::Release)
+    }
+
+    pub fn did_exec(&self) -> bool {
+        self.did_exec.load(Ordering::Acquire)
+    }
+
+    pub fn set_did_exec(&self) {
+        self.did_exec.store(true, Ordering::Release)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Stops the process by `signo`.
+    ///
+    /// Returns `true` if it was running, in which case the parent is to be
+    /// notified.
+    pub fn stop(&self, signo: Signo) -> bool {
+        let mut job_status = self.job_status.lock();
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        *job_status = Some(JobStatus::Stopped(signo));
+        true
+    }
+
+    /// Continues the process if it is stopped, waking up its parked threads.
+    ///
+    /// The change is reported to the parent only if `report` is `true`, which
+    /// is not the case when the process is woken up to be killed. Returns
+    /// `true` if the process was stopped.
+    pub fn resume(&self, report: bool) -> bool {
+        let mut job_status = self.job_status.lock();
+        if !self.stopped.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        *job_status = report.then_some(JobStatus::Continued);
+        drop(job_status);
+        self.stop_wq.notify_all(false);
+        true
+    }
+
+    /// Discards the pending signals in `discarded` as `signo` is sent, which
+    /// itself is no longer discarded.
+    pub fn discard_signals(&self, signo: Signo, discarded: SignalSet) {
+        let mut signals = self.discarded_signals.lock();
+        *signals |= discarded;
+        signals.remove(signo);
+    }
+
+    /// Returns the pending signals discarded by a signal sent later.
+    pub fn discarded_signals(&self) -> SignalSet {
+        *self.discarded_signals.lock()
+    }
+
+    /// Returns the job control state change not reported to the parent yet,
+    /// if it is a stop and `stopped` is `true`, or a continue and `continued`
+    /// is `true`.
+    ///
+    /// The change is consumed if `consume` is `true`, i.e. unless `WNOWAIT`.
+    pub fn take_job_status(
+        &self,
+        stopped: bool,
+        continued: bool,
+        consume: bool,
+    ) -> Option<JobStatus> {
+        let mut job_status = self.job_status.lock();
+        let status = job_status.filter(|status| match status {
+            JobStatus::Stopped(_) => stopped,
+            JobStatus::Continued => continued,
+        })?;
+        if consume {
+            *job_status = None;
+        }
+        Some(status)
+    }
+
+    /// Returns the wait status a thread exiting with `status` exits with,
+    /// which is the one of the first thread exiting the whole process if any.
+    ///
+    /// If `exit_group` is `true`, the thread exits the whole process.
+    pub fn exit_status(&self, status: i32, exit_group: bool) -> i32 {
+        let mut group_exit_status = self.group_exit_status.lock();
+        if exit_group {
+            *group_exit_status.get_or_insert(status)
+        } else {
+            group_exit_status.unwrap_or(status)
+        }
+    }
+
+    /// Accounts the CPU time of an exited thread.
+    pub fn add_thread_times(&self, times: CpuTimes) {
+        *self.thread_times.lock() += times;
+    }
+
+    /// Accounts the CPU time of a child waited for, see [`Self::total_times`].
+    pub fn add_children_times(&self, times: CpuTimes) {
+        *self.children_times.lock() += times;
+    }
+
+    /// Returns the CPU time of the exited threads and of the children waited
+    /// for.
+    pub fn total_times(&self) -> CpuTimes {
+        let mut times = *self.thread_times.lock();
+        times += *self.children_times.lock();
+        times
+    }
+
+    /// Inherits the shared memory segments attached by `parent`, for `fork`.
+    ///
+    /// The mappings are already copied to the address space of this process,
+    /// except those excluded by `MADV_DONTFORK`, which are not inherited.
+    pub fn inherit_shared_memory(&self, parent: &ProcessData, pid: Pid) {
+        let addr_space = self.addr_space.lock();
+        let mut shared_memory = self.shared_memory.lock();
+        for (&addr, shm) in parent.shared_memory.lock().iter() {
+            if !shm.mapped_ranges(&addr_space, addr).is_empty() {
+                shm.attach(pid);
+                shared_memory.insert(addr, shm.clone());
+            }
+        }
+    }
+
+    /// Detaches all shared memory segments of the process `pid`, for `execve`
+    /// and `exit`.
+    ///
+    /// `addr_space` is the locked address space of this process.
+    pub fn detach_shared_memory(&self, addr_space: &mut AddrSpace, pid: Pid) {
+        let shared_memory = core::mem::take(&mut *self.shared_memory.lock());
+        for (addr, shm) in shared_memory {
+            for range in shm.mapped_ranges(addr_space, addr) {
+                if let Err(err) = addr_space.unmap(range.start, range.size()) {
+                    warn!(
+                        "failed to unmap shared memory at {:#x}: {:?}",
+                        range.start, err
+                    );
+                }
+            }
+            SHARED_MEMORY_MANAGER.detach(&shm, pid);
+        }
+    }
+
+    /// Detaches the shared memory segments of the process `pid` that are no
+    /// longer mapped at all, after their mappings are unmapped or replaced,
+    /// e.g. by `munmap`, `mremap` or `MAP_FIXED`.
+    ///
+    /// `addr_space` is the locked address space of this process. Segments
+    /// partly unmapped stay attached, until `shmdt` unmaps the rest.
+    pub fn release_shared_memory(&self, addr_space: &AddrSpace, pid: Pid) {
+        self.shared_memory.lock().retain(|&addr, shm| {
+            let mapped = !shm.mapped_ranges(addr_space, addr).is_empty();
+            if !mapped {
+                SHARED_MEMORY_MANAGER.detach(shm, pid);
+            }
+            mapped
+        });
+    }
+
+    /// Checks whether `size` more bytes can be mapped in `addr_space`, which is
+    /// the locked address space of this process.
+    ///
+    /// All mappings must fit in `RLIMIT_AS`, and data mappings (see
+    /// [`AddrSpace::is_data`]) in `RLIMIT_DATA`, or `ENOMEM` is returned. If
+    /// new mappings are locked by `mlockall(MCL_FUTURE)`, they must also fit
+    /// in `RLIMIT_MEMLOCK`, or `EAGAIN` is returned.
+    pub fn check_vm_limits(&self, addr_space: &AddrSpace, size: usize, data: bool) -> LinuxResult {
+        self.check_vm_usage(
+            addr_space,
+            [
+                addr_space.mapped_size(),
+                addr_space.data_size(),
+                addr_space.locked_size(),
+            ],
+            size,
+            data,
+        )
+    }
+
+    /// Checks like [`check_vm_limits`](Self::check_vm_limits) whether `size`
+    /// bytes can be mapped at `start` in `addr_space`, replacing the mappings
+    /// there, e.g. by `MAP_FIXED`.
+    pub fn check_vm_limits_replacing(
+        &self,
+        addr_space: &AddrSpace,
+        start: VirtAddr,
+        size: usize,
+        data: bool,
+    ) -> LinuxResult {
+        let locked = size - addr_space.unlocked_size(start, size);
+        self.check_vm_usage(
+            addr_space,
+            [
+                addr_space.mapped_size() - addr_space.mapped_size_in(start, size),
+                addr_space.data_size() - addr_space.data_size_in(start, size),
+                addr_space.locked_size() - locked,
+            ],
+            size,
+            data,
+        )
+    }
+
+    /// Checks like [`check_vm_limits_replacing`](Self::check_vm_limits_replacing)
+    /// whether the mapping of `old_size` bytes at `old_start` can be moved to
+    /// `new_start` and resized to `new_size` bytes by `mremap`, replacing the
+    /// mappings there.
+    pub fn check_vm_limits_moving(
+        &self,
+        addr_space: &AddrSpace,
+        (old_start, old_size): (VirtAddr, usize),
+        (new_start, new_size): (VirtAddr, usize),
+        data: bool,
+    ) -> LinuxResult {
+        let locked = |start, size| size - addr_space.unlocked_size(start, size);
+        self.check_vm_usage(
+            addr_space,
+            [
+                addr_space.mapped_size()
+                    - addr_space.mapped_size_in(old_start, old_size)
+                    - addr_space.mapped_size_in(new_start, new_size),
+                addr_space.data_size()
+                    - addr_space.data_size_in(old_start, old_size)
+                    - addr_space.data_size_in(new_start, new_size),
+                addr_space.locked_size()
+                    - locked(old_start, old_size)
+                    - locked(new_start, new_size),
+            ],
+            new_size,
+            data,
+        )
+    }
+
+    /// Checks whether `size` more bytes fit in the limits, given the mapped,
+    /// data and locked sizes in use.
+    fn check_vm_usage(
+        &self,
+        addr_space: &AddrSpace,
+        [mapped, data_mapped, locked]: [usize; 3],
+        size: usize,
+        data: bool,
+    ) -> LinuxResult {
+        let limits = self.resource_limits.lock();
+        let exceeds = |used: usize, resource| {
+            (used as u64).saturating_add(size as u64) > limits.get_soft(&resource)
+        };
+        if exceeds(mapped, ResourceLimitType::AS)
+            || (data && exceeds(data_mapped, ResourceLimitType::DATA))
+        {
+            return Err(LinuxError::ENOMEM);
+        }
+        if addr_space.lock_future() && exceeds(locked, ResourceLimitType::MEMLOCK) {
+            return Err(LinuxError::EAGAIN);
+        }
+        Ok(())
+    }
+
+    /// Grows the stack right above `vaddr` in `addr_space`, which is the locked
+    /// address space of this process, within `RLIMIT_STACK` and `RLIMIT_AS`.
+    ///
+    /// Returns `true` if the stack is grown to cover `vaddr`.
+    pub fn grow_stack(&self, addr_space: &mut AddrSpace, vaddr: VirtAddr) -> bool {
+        let (stack_limit, as_limit) = {
+            let limits = self.resource_limits.lock();
+            (
+                limits.get_soft(&ResourceLimitType::STACK),
+                limits.get_soft(&ResourceLimitType::AS),
+            )
+        };
+        addr_space.grow_down(
+            vaddr,
+            stack_limit.try_into().unwrap_or(usize::MAX),
+            as_limit.try_into().unwrap_or(usize::MAX),
+        )
+    }
+
     /// Linux manual: A "clone" child is one which delivers no signal, or a
     /// signal other than SIGCHLD to its parent upon termination.
     pub fn is_clone_child(&self) -> bool {
@@ -138,7 +527,14 @@ impl Drop for ThreadData {
     fn drop(&mut self) {
         // remove form the thread data table
         trace!("thread data drop: tid={}", self.tid);
-        assert!(!THREAD_DATA_TABLE.lock().remove(&self.tid).is_none())
+        let mut thread_data_table = THREAD_DATA_TABLE.lock();
+        // the tid may have been reused by a new thread already
+        if thread_data_table
+            .get(&self.tid)
+            .is_some_and(|entry| core::ptr::eq(entry.as_ptr(), self))
+        {
+            thread_data_table.remove(&self.tid);
+        }
     }
 }
 
@@ -0,0 +1,28 @@
+//! A pseudo-random number generator for the kernel.
+//!
+//! It is used where unpredictability is nice to have, e.g. address space
+//! layout randomization and `AT_RANDOM`. It is NOT cryptographically secure.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a pseudo-random 64-bit number.
+pub fn random_u64() -> u64 {
+    // SplitMix64, mixed with the time so that each boot gives a different
+    // sequence.
+    let mut z = STATE
+        .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+        .wrapping_add(axhal::time::monotonic_time_nanos());
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Fills `buf` with pseudo-random bytes.
+pub fn fill_random(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(size_of::<u64>()) {
+        let bytes = random_u64().to_ne_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
@@ -8,6 +8,9 @@ use num_enum::TryFromPrimitive;
 pub const RLIMIT_INFINITY: u64 = u64::MAX;
 const _: () = assert!(RLIMIT_INFINITY == RLIM_INFINITY as i64 as u64);
 
+/// The default soft limit of the stack size, the same as Linux.
+pub const DEFAULT_STACK_LIMIT: u64 = 8 * 1024 * 1024;
+
 #[repr(u32)]
 #[derive(TryFromPrimitive, Clone, Copy, Debug)]
 pub enum ResourceLimitType {
@@ -62,7 +65,7 @@ impl ResourceLimits {
     pub fn new() -> Self {
         let mut limits = [ResourceLimit::new_infinite(); RLIM_NLIMITS as usize];
         limits[ResourceLimitType::STACK as usize] =
-            ResourceLimit::new(axconfig::plat::USER_STACK_SIZE as u64, RLIMIT_INFINITY);
+            ResourceLimit::new(DEFAULT_STACK_LIMIT, RLIMIT_INFINITY);
         limits[ResourceLimitType::CORE as usize] = ResourceLimit::new(0, RLIMIT_INFINITY);
         limits[ResourceLimitType::NPROC as usize] = ResourceLimit::new(10000, 10000);
         limits[ResourceLimitType::NOFILE as usize] = ResourceLimit::new(1024, 1024 * 1024); // 1024 files, 1M files max
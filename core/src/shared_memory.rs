@@ -1,18 +1,164 @@
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use axalloc::global_allocator;
 use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::virt_to_phys;
+use axmm::AddrSpace;
 use axsync::Mutex;
-use core::sync::atomic::{AtomicU32, Ordering};
-use memory_addr::PAGE_SIZE_4K;
+use core::ffi::{c_int, c_long, c_uint, c_ulong, c_ushort};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
+use undefined_process::Pid;
+
+/// The key to create a new segment that can not be found by key.
+pub const IPC_PRIVATE: u32 = 0;
+
+/// The mode bit set when the segment is removed but still attached.
+pub const SHM_DEST: c_uint = 0o1000;
+/// The mode bit set when the segment is locked in memory by `SHM_LOCK`.
+pub const SHM_LOCKED: c_uint = 0o2000;
+
+/// The permissions of an IPC object, i.e. `struct ipc64_perm`.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct IpcPerm {
+    /// The key given to `shmget`
+    pub key: c_int,
+    /// The user ID of the owner
+    pub uid: c_uint,
+    /// The group ID of the owner
+    pub gid: c_uint,
+    /// The user ID of the creator
+    pub cuid: c_uint,
+    /// The group ID of the creator
+    pub cgid: c_uint,
+    /// The permissions, and `SHM_DEST` and `SHM_LOCKED`
+    pub mode: c_uint,
+    /// The sequence number
+    pub seq: c_ushort,
+    _pad: c_ushort,
+    _unused: [c_ulong; 2],
+}
+
+/// The state of a shared memory segment, i.e. `struct shmid64_ds`.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct ShmidDs {
+    /// The ownership and permissions
+    pub shm_perm: IpcPerm,
+    /// The size of the segment in bytes
+    pub shm_segsz: usize,
+    /// The time of the last `shmat`
+    pub shm_atime: c_long,
+    /// The time of the last `shmdt`
+    pub shm_dtime: c_long,
+    /// The time of the creation or the last `IPC_SET`
+    pub shm_ctime: c_long,
+    /// The process ID of the creator
+    pub shm_cpid: c_int,
+    /// The process ID of the last `shmat` or `shmdt`
+    pub shm_lpid: c_int,
+    /// The number of current attaches
+    pub shm_nattch: c_ulong,
+    _unused: [c_ulong; 2],
+}
+
+fn now() -> c_long {
+    axhal::time::wall_time().as_secs() as c_long
+}
 
 pub struct SharedMemory {
-    /// The key of the shared memory segment
-    pub key: u32,
+    /// The identifier of the shared memory segment
+    pub shmid: u32,
     /// Virtual kernel address of the shared memory segment
     pub addr: usize,
     /// Page count of the shared memory segment
     pub page_count: usize,
+    /// The state reported by `IPC_STAT`
+    ds: Mutex<ShmidDs>,
+}
+
+impl SharedMemory {
+    /// Returns the size of the segment in bytes, as given to `shmget`.
+    pub fn size(&self) -> usize {
+        self.ds.lock().shm_segsz
+    }
+
+    /// Returns the state of the segment.
+    pub fn stat(&self) -> ShmidDs {
+        *self.ds.lock()
+    }
+
+    /// Returns whether the segment has been removed by `IPC_RMID`.
+    pub fn is_removed(&self) -> bool {
+        self.ds.lock().shm_perm.mode & SHM_DEST != 0
+    }
+
+    /// Checks whether the user can access the segment with the requested
+    /// permission bits, i.e. `0o4` to read and `0o2` to write.
+    pub fn check_access(&self, uid: u32, gid: u32, access: u32) -> LinuxResult {
+        let perm = self.ds.lock().shm_perm;
+        let granted = if uid == perm.uid || uid == perm.cuid {
+            perm.mode >> 6
+        } else if gid == perm.gid || gid == perm.cgid {
+            perm.mode >> 3
+        } else {
+            perm.mode
+        };
+        if granted & access != access {
+            return Err(LinuxError::EACCES);
+        }
+        Ok(())
+    }
+
+    /// Checks whether the user can change or remove the segment.
+    pub fn check_owner(&self, uid: u32) -> LinuxResult {
+        let perm = self.ds.lock().shm_perm;
+        if uid != perm.uid && uid != perm.cuid {
+            return Err(LinuxError::EPERM);
+        }
+        Ok(())
+    }
+
+    /// Changes the owner and permissions of the segment, for `IPC_SET`.
+    pub fn set(&self, uid: u32, gid: u32, mode: u32) {
+        let mut ds = self.ds.lock();
+        ds.shm_perm.uid = uid;
+        ds.shm_perm.gid = gid;
+        ds.shm_perm.mode = (ds.shm_perm.mode & !0o777) | (mode & 0o777);
+        ds.shm_ctime = now();
+    }
+
+    /// Marks the segment locked or unlocked, for `SHM_LOCK` and `SHM_UNLOCK`.
+    ///
+    /// The pages of a segment are never swapped out, so this only changes
+    /// what `IPC_STAT` reports.
+    pub fn set_locked(&self, locked: bool) {
+        let mut ds = self.ds.lock();
+        if locked {
+            ds.shm_perm.mode |= SHM_LOCKED;
+        } else {
+            ds.shm_perm.mode &= !SHM_LOCKED;
+        }
+    }
+
+    /// Returns the ranges of `addr_space` that still map the segment attached
+    /// at `addr`, which can be partly unmapped or replaced after `shmat`.
+    pub fn mapped_ranges(&self, addr_space: &AddrSpace, addr: VirtAddr) -> Vec<VirtAddrRange> {
+        addr_space.linear_ranges(
+            addr,
+            self.page_count * PAGE_SIZE_4K,
+            virt_to_phys(VirtAddr::from(self.addr)),
+        )
+    }
+
+    /// Records that the segment is attached by the process `pid`.
+    pub fn attach(&self, pid: Pid) {
+        let mut ds = self.ds.lock();
+        ds.shm_nattch += 1;
+        ds.shm_atime = now();
+        ds.shm_lpid = pid as _;
+    }
 }
 
 impl Drop for SharedMemory {
@@ -20,53 +166,145 @@ impl Drop for SharedMemory {
         let allocator = global_allocator();
         allocator.dealloc_pages(self.addr, self.page_count);
         info!(
-            "[SharedMemory] dealloc pages: addr: {:#x}, page_count: {}, key: {}",
-            self.addr, self.page_count, self.key
+            "[SharedMemory] dealloc pages: addr: {:#x}, page_count: {}, shmid: {}",
+            self.addr, self.page_count, self.shmid
         );
     }
 }
 
+struct SharedMemoryTable {
+    /// The segments by their identifiers
+    segments: BTreeMap<u32, Arc<SharedMemory>>,
+    /// The identifiers of the segments by their keys, except `IPC_PRIVATE`
+    keys: BTreeMap<u32, u32>,
+    /// The identifier to try first for the next segment
+    next_shmid: u32,
+}
+
 pub struct SharedMemoryManager {
-    mem_map: Mutex<BTreeMap<u32, Arc<SharedMemory>>>,
-    next_key: AtomicU32,
+    table: Mutex<SharedMemoryTable>,
 }
 
 impl SharedMemoryManager {
     pub const fn new() -> Self {
         SharedMemoryManager {
-            mem_map: Mutex::new(BTreeMap::new()),
-            next_key: AtomicU32::new(1),
+            table: Mutex::new(SharedMemoryTable {
+                segments: BTreeMap::new(),
+                keys: BTreeMap::new(),
+                next_shmid: 0,
+            }),
         }
     }
 
-    pub fn next_available_key(&self) -> u32 {
-        let next_key = self.next_key.fetch_add(1, Ordering::Relaxed);
-        self.mem_map.lock().keys().max().unwrap_or(&0) + next_key
+    /// Returns the segment with the identifier `shmid`.
+    ///
+    /// Removed segments can be still found until they are detached by all
+    /// processes, like Linux.
+    pub fn get(&self, shmid: u32) -> Option<Arc<SharedMemory>> {
+        self.table.lock().segments.get(&shmid).cloned()
     }
 
-    pub fn get(&self, key: u32) -> Option<Arc<SharedMemory>> {
-        self.mem_map.lock().get(&key).cloned()
+    /// Returns the segment with the key `key`.
+    pub fn find(&self, key: u32) -> Option<Arc<SharedMemory>> {
+        let table = self.table.lock();
+        let shmid = table.keys.get(&key)?;
+        table.segments.get(shmid).cloned()
     }
 
-    pub fn create(&self, key: u32, size: usize) -> LinuxResult<Arc<SharedMemory>> {
+    /// Creates a zero-filled segment of `size` bytes.
+    ///
+    /// `mode` gives its permissions, and `uid`, `gid` and `pid` are those of
+    /// the creator.
+    pub fn create(
+        &self,
+        key: u32,
+        size: usize,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+        pid: Pid,
+    ) -> LinuxResult<Arc<SharedMemory>> {
+        let mut table = self.table.lock();
+        if key != IPC_PRIVATE && table.keys.contains_key(&key) {
+            return Err(LinuxError::EEXIST);
+        }
+        // Identifiers are positive `int`s, which are reused after wrapping around.
+        let mut shmid = table.next_shmid;
+        while table.segments.contains_key(&shmid) {
+            shmid = (shmid + 1) % c_int::MAX as u32;
+            if shmid == table.next_shmid {
+                return Err(LinuxError::ENOSPC);
+            }
+        }
+
         let page_count = size.div_ceil(PAGE_SIZE_4K);
         let allocator = global_allocator();
-        // TODO: more error checking
         let vaddr = allocator
             .alloc_pages(page_count, PAGE_SIZE_4K)
             .map_err(|_| LinuxError::ENOMEM)?;
-        let shared_memory = SharedMemory {
-            key,
-            addr: vaddr,
-            page_count: size,
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, page_count * PAGE_SIZE_4K) };
+        let ds = ShmidDs {
+            shm_perm: IpcPerm {
+                key: key as _,
+                uid,
+                gid,
+                cuid: uid,
+                cgid: gid,
+                mode: mode & 0o777,
+                ..Default::default()
+            },
+            shm_segsz: size,
+            shm_ctime: now(),
+            shm_cpid: pid as _,
+            ..Default::default()
         };
-        let shared_memory = Arc::new(shared_memory);
-        self.mem_map.lock().insert(key, shared_memory.clone());
+        let shared_memory = Arc::new(SharedMemory {
+            shmid,
+            addr: vaddr,
+            page_count,
+            ds: Mutex::new(ds),
+        });
+        table.segments.insert(shmid, shared_memory.clone());
+        if key != IPC_PRIVATE {
+            table.keys.insert(key, shmid);
+        }
+        table.next_shmid = (shmid + 1) % c_int::MAX as u32;
         Ok(shared_memory)
     }
 
-    pub fn delete(&self, key: u32) -> bool {
-        self.mem_map.lock().remove(&key).is_some()
+    /// Removes the segment, for `IPC_RMID`.
+    ///
+    /// The key can be used for a new segment at once, but the segment is only
+    /// destroyed after it is detached by all processes.
+    pub fn remove(&self, shm: &SharedMemory) {
+        let destroy = {
+            let mut ds = shm.ds.lock();
+            ds.shm_perm.mode |= SHM_DEST;
+            ds.shm_nattch == 0
+        };
+        let mut table = self.table.lock();
+        let key = shm.stat().shm_perm.key as u32;
+        if table.keys.get(&key) == Some(&shm.shmid) {
+            table.keys.remove(&key);
+        }
+        if destroy {
+            table.segments.remove(&shm.shmid);
+        }
+    }
+
+    /// Records that the segment is detached by the process `pid`, and
+    /// destroys it if it is removed and no longer attached.
+    pub fn detach(&self, shm: &SharedMemory, pid: Pid) {
+        let destroy = {
+            let mut ds = shm.ds.lock();
+            ds.shm_nattch = ds.shm_nattch.saturating_sub(1);
+            ds.shm_dtime = now();
+            ds.shm_lpid = pid as _;
+            ds.shm_nattch == 0 && ds.shm_perm.mode & SHM_DEST != 0
+        };
+        if destroy {
+            self.table.lock().segments.remove(&shm.shmid);
+        }
     }
 }
 
@@ -0,0 +1,208 @@
+//! The virtual dynamic shared object (vDSO).
+//!
+//! It is a small ELF shared object mapped into each address space and
+//! advertised with `AT_SYSINFO_EHDR`, which answers `clock_gettime`,
+//! `gettimeofday`, `time` (x86_64 only) and `getcpu` in user mode. The time is
+//! computed from the counter the kernel time is based on, using a data page
+//! mapped right before the image, which the kernel updates on the way back to
+//! user mode. The functions fall back to the syscall when they cannot answer,
+//! e.g. for other clocks or before the first update.
+
+use core::mem::offset_of;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+
+use axerrno::AxResult;
+use axhal::arch::TrapFrame;
+use axhal::mem::virt_to_phys;
+use axhal::paging::MappingFlags;
+use axhal::time::{current_ticks, epochoffset_nanos, nanos_to_ticks, ticks_to_nanos};
+use axhal::trap::{POST_TRAP, register_trap_handler};
+use axmm::AddrSpace;
+use linux_raw_sys::general::{__NR_clock_gettime, __NR_gettimeofday};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr};
+
+/// The address of the data page, right after the signal trampoline.
+pub const VDSO_DATA_BASE: usize = axconfig::plat::SIGNAL_TRAMPOLINE + PAGE_SIZE_4K;
+/// The address of the vDSO image, right after its data page.
+pub const VDSO_BASE: usize = VDSO_DATA_BASE + PAGE_SIZE_4K;
+
+/// The data page is updated at most this often, which keeps the time computed
+/// from the counter far from overflowing.
+const UPDATE_INTERVAL_NANOS: u64 = 10_000_000;
+
+/// The data page read by the vDSO, protected by a sequence lock.
+///
+/// The time at counter `counter` is `mono_ns + (counter - cycle_last) * mult >> 32`,
+/// where the difference must not exceed `max_delta`.
+#[repr(C, align(4096))]
+struct VdsoData {
+    /// Odd while the page is updated. It must be the first field.
+    seq: AtomicU32,
+    /// The counter at the last update.
+    cycle_last: AtomicU64,
+    /// The monotonic time at the last update, in nanoseconds.
+    mono_ns: AtomicU64,
+    /// The offset of the realtime clock to the monotonic one, in nanoseconds.
+    wall_offset_ns: AtomicU64,
+    /// The nanoseconds per counter tick, in 32.32 fixed point.
+    mult: AtomicU64,
+    /// The largest counter difference that does not overflow with `mult`.
+    max_delta: AtomicU64,
+}
+
+static VDSO_DATA: VdsoData = VdsoData {
+    seq: AtomicU32::new(0),
+    cycle_last: AtomicU64::new(0),
+    mono_ns: AtomicU64::new(0),
+    wall_offset_ns: AtomicU64::new(0),
+    mult: AtomicU64::new(0),
+    // Nothing can be answered before the first update.
+    max_delta: AtomicU64::new(0),
+};
+
+#[cfg(target_arch = "x86_64")]
+core::arch::global_asm!(
+    concat!(include_str!("vdso/image.S"), include_str!("vdso/x86_64.S")),
+    page_size = const PAGE_SIZE_4K,
+    machine = const 62, // EM_X86_64
+    elf_flags = const 0,
+    seq = const offset_of!(VdsoData, seq),
+    cycle_last = const offset_of!(VdsoData, cycle_last),
+    mono_ns = const offset_of!(VdsoData, mono_ns),
+    wall_offset_ns = const offset_of!(VdsoData, wall_offset_ns),
+    mult = const offset_of!(VdsoData, mult),
+    max_delta = const offset_of!(VdsoData, max_delta),
+    sys_clock_gettime = const __NR_clock_gettime,
+    sys_gettimeofday = const __NR_gettimeofday,
+    sys_time = const linux_raw_sys::general::__NR_time,
+    cpu_number_selector = const axhal::arch::GdtStruct::CPU_NUMBER_SELECTOR.0,
+);
+
+#[cfg(target_arch = "riscv64")]
+core::arch::global_asm!(
+    concat!(include_str!("vdso/image.S"), include_str!("vdso/riscv64.S")),
+    page_size = const PAGE_SIZE_4K,
+    machine = const 243, // EM_RISCV
+    elf_flags = const 0x5, // EF_RISCV_RVC | EF_RISCV_FLOAT_ABI_DOUBLE
+    seq = const offset_of!(VdsoData, seq),
+    cycle_last = const offset_of!(VdsoData, cycle_last),
+    mono_ns = const offset_of!(VdsoData, mono_ns),
+    wall_offset_ns = const offset_of!(VdsoData, wall_offset_ns),
+    mult = const offset_of!(VdsoData, mult),
+    max_delta = const offset_of!(VdsoData, max_delta),
+    sys_clock_gettime = const __NR_clock_gettime,
+    sys_gettimeofday = const __NR_gettimeofday,
+    sys_getcpu = const linux_raw_sys::general::__NR_getcpu,
+);
+
+#[cfg(target_arch = "aarch64")]
+core::arch::global_asm!(
+    concat!(include_str!("vdso/image.S"), include_str!("vdso/aarch64.S")),
+    page_size = const PAGE_SIZE_4K,
+    machine = const 183, // EM_AARCH64
+    elf_flags = const 0,
+    cycle_last = const offset_of!(VdsoData, cycle_last),
+    mono_ns = const offset_of!(VdsoData, mono_ns),
+    wall_offset_ns = const offset_of!(VdsoData, wall_offset_ns),
+    mult = const offset_of!(VdsoData, mult),
+    max_delta = const offset_of!(VdsoData, max_delta),
+    sys_clock_gettime = const __NR_clock_gettime,
+    sys_gettimeofday = const __NR_gettimeofday,
+    sys_getcpu = const linux_raw_sys::general::__NR_getcpu,
+);
+
+#[cfg(target_arch = "loongarch64")]
+core::arch::global_asm!(
+    concat!(include_str!("vdso/image.S"), include_str!("vdso/loongarch64.S")),
+    page_size = const PAGE_SIZE_4K,
+    machine = const 258, // EM_LOONGARCH
+    elf_flags = const 0x43, // EF_LOONGARCH_OBJABI_V1 | EF_LOONGARCH_ABI_DOUBLE_FLOAT
+    seq = const offset_of!(VdsoData, seq),
+    cycle_last = const offset_of!(VdsoData, cycle_last),
+    mono_ns = const offset_of!(VdsoData, mono_ns),
+    wall_offset_ns = const offset_of!(VdsoData, wall_offset_ns),
+    mult = const offset_of!(VdsoData, mult),
+    max_delta = const offset_of!(VdsoData, max_delta),
+    sys_clock_gettime = const __NR_clock_gettime,
+    sys_gettimeofday = const __NR_gettimeofday,
+);
+
+unsafe extern "C" {
+    fn __vdso_image_start();
+    fn __vdso_image_end();
+}
+
+/// Reads the counter the vDSO reads, along with the current ticks.
+fn read_counter() -> (u64, u64) {
+    let ticks = current_ticks();
+    // The time stamp counter is not offset like the ticks.
+    #[cfg(target_arch = "x86_64")]
+    let counter = unsafe { core::arch::x86_64::_rdtsc() };
+    #[cfg(not(target_arch = "x86_64"))]
+    let counter = ticks;
+    (counter, ticks)
+}
+
+/// Updates the data page if it is older than `UPDATE_INTERVAL_NANOS`.
+///
+/// It gives up if another CPU is updating it.
+fn update_vdso_data() {
+    let data = &VDSO_DATA;
+    let (counter, ticks) = read_counter();
+    let seq = data.seq.load(Ordering::Relaxed);
+    if seq & 1 != 0
+        || (data.max_delta.load(Ordering::Relaxed) != 0
+            && counter.wrapping_sub(data.cycle_last.load(Ordering::Relaxed))
+                < nanos_to_ticks(UPDATE_INTERVAL_NANOS))
+    {
+        return;
+    }
+    if data
+        .seq
+        .compare_exchange(
+            seq,
+            seq.wrapping_add(1),
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        )
+        .is_err()
+    {
+        return;
+    }
+    fence(Ordering::Release);
+    let mult = ticks_to_nanos(1 << 32).max(1);
+    data.cycle_last.store(counter, Ordering::Relaxed);
+    data.mono_ns.store(ticks_to_nanos(ticks), Ordering::Relaxed);
+    data.wall_offset_ns
+        .store(epochoffset_nanos(), Ordering::Relaxed);
+    data.mult.store(mult, Ordering::Relaxed);
+    data.max_delta.store(u64::MAX / mult, Ordering::Relaxed);
+    data.seq.store(seq.wrapping_add(2), Ordering::Release);
+}
+
+#[register_trap_handler(POST_TRAP)]
+fn vdso_post_trap(_tf: &mut TrapFrame, from_user: bool) {
+    if from_user {
+        update_vdso_data();
+    }
+}
+
+/// Maps the data page and the vDSO image to the user address space, at
+/// `VDSO_DATA_BASE` and `VDSO_BASE`.
+pub fn map_vdso(aspace: &mut AddrSpace) -> AxResult {
+    let data_paddr = virt_to_phys(VirtAddr::from_ptr_of(&VDSO_DATA));
+    aspace.map_linear(
+        VDSO_DATA_BASE.into(),
+        data_paddr,
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::USER,
+    )?;
+    let image_start = __vdso_image_start as usize;
+    aspace.map_linear(
+        VDSO_BASE.into(),
+        virt_to_phys(image_start.into()),
+        __vdso_image_end as usize - image_start,
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+    )?;
+    Ok(())
+}
@@ -6,6 +6,7 @@
 #![no_std]
 
 extern crate alloc;
+pub mod pid;
 pub mod process;
 pub mod process_group;
 pub mod session;
@@ -0,0 +1,72 @@
+//! Allocation of the IDs shared by threads, processes, process groups and
+//! sessions, like Linux.
+//!
+//! IDs are allocated in increasing order below `pid_max`, then wrap around to
+//! `RESERVED_PIDS`. An ID is not reused while it is still used as the ID of a
+//! thread, process (including zombies), process group or session.
+
+use crate::Pid;
+use crate::process::get_process;
+use crate::process_group::get_process_group;
+use crate::session::get_session;
+use crate::thread::get_thread;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+
+/// The default `pid_max`.
+pub const PID_MAX_DEFAULT: Pid = 0x8000;
+/// The largest `pid_max`.
+pub const PID_MAX_LIMIT: Pid = 0x400000;
+/// IDs below this are not reused once the IDs wrap around, as they usually
+/// belong to long-running daemons.
+pub const RESERVED_PIDS: Pid = 300;
+
+/// IDs are allocated below this.
+static PID_MAX: AtomicU32 = AtomicU32::new(PID_MAX_DEFAULT);
+
+/// The last allocated ID.
+static LAST_PID: Mutex<Pid> = Mutex::new(0);
+
+/// Get the upper bound (exclusive) of the allocated IDs.
+pub fn pid_max() -> Pid {
+    PID_MAX.load(Ordering::Relaxed)
+}
+
+/// Set the upper bound (exclusive) of the allocated IDs, which must be greater
+/// than `RESERVED_PIDS` and no more than `PID_MAX_LIMIT`.
+/// Return `false` if it is out of range.
+///
+/// IDs already allocated above it are kept.
+pub fn set_pid_max(pid_max: Pid) -> bool {
+    if !(RESERVED_PIDS + 1..=PID_MAX_LIMIT).contains(&pid_max) {
+        return false;
+    }
+    PID_MAX.store(pid_max, Ordering::Relaxed);
+    true
+}
+
+fn is_pid_used(pid: Pid) -> bool {
+    get_thread(pid).is_some()
+        || get_process(pid).is_some()
+        || get_process_group(pid).is_some()
+        || get_session(pid).is_some()
+}
+
+/// Allocate the next unused ID after the last allocated one.
+/// Return `None` if all IDs below `pid_max` are used.
+pub(crate) fn alloc_pid() -> Option<Pid> {
+    let pid_max = pid_max();
+    let mut last_pid = LAST_PID.lock();
+    let mut pid = *last_pid;
+    for _ in 0..pid_max {
+        pid += 1;
+        if pid >= pid_max {
+            pid = RESERVED_PIDS;
+        }
+        if !is_pid_used(pid) {
+            *last_pid = pid;
+            return Some(pid);
+        }
+    }
+    None
+}
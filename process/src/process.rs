@@ -1,11 +1,12 @@
 use crate::Pid;
-use crate::process_group::{ProcessGroup, create_process_group};
+use crate::pid::alloc_pid;
+use crate::process_group::{ProcessGroup, create_process_group, get_process_group};
 use crate::session::{Session, create_session};
 use crate::thread::{Thread, create_thread};
 use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use spin::Mutex;
 
 pub struct Process {
@@ -16,7 +17,9 @@ pub struct Process {
     parent: Mutex<Weak<Process>>,
     is_zombie: AtomicBool,
     exit_code: AtomicI32,
-    // TODO: sub reaper
+    /// Whether orphaned descendants are reparented to this process rather
+    /// than to `init`, set by `prctl(PR_SET_CHILD_SUBREAPER)`.
+    child_subreaper: AtomicBool,
 }
 
 impl Process {
@@ -42,6 +45,9 @@ impl Process {
 
     fn change_group(self: &Arc<Self>, new_group: &Arc<ProcessGroup>) {
         let origin_group = self.get_group();
+        if Arc::ptr_eq(&origin_group, new_group) {
+            return;
+        }
         origin_group.remove_process(self.pid);
         new_group.add_process(self.clone());
         *self.process_group.lock() = Arc::downgrade(&new_group);
@@ -55,8 +61,13 @@ impl Process {
             // if current process is the leader of the group, return the current group
             return origin_group;
         }
-        // create a new process group with current pid
-        let new_group = create_process_group(self.pid, origin_group.session.clone());
+        // the group with current pid may still exist after the process left it,
+        // then it is joined again
+        let new_group = match origin_group.get_session().get_process_group(self.pid) {
+            Some(group) => group,
+            // create a new process group with current pid
+            None => create_process_group(self.pid, origin_group.session.clone()),
+        };
         self.change_group(&new_group);
         new_group
     }
@@ -81,8 +92,9 @@ impl Process {
     /// Current process will become the leader of the new session, and the process group leader of
     /// a new process group in the session.
     pub fn create_session(self: &Arc<Self>) -> Option<Arc<Session>> {
-        if self.is_group_leader() {
-            // if current process is the leader of the group, fails
+        if self.is_group_leader() || get_process_group(self.pid).is_some() {
+            // if current process is the leader of the group, or the group with
+            // current pid still exists, fails
             return None;
         }
         // create a new session with current pid
@@ -111,22 +123,24 @@ impl Process {
             parent: Mutex::new(parent),
             is_zombie: AtomicBool::new(false),
             exit_code: AtomicI32::new(0),
+            child_subreaper: AtomicBool::new(false),
         })
     }
 
     /// only used to spawn a "newborn" process without parent, like `init` process
     pub fn spawn_process() -> Arc<Process> {
-        let pid = generate_next_pid();
+        let pid = alloc_pid().expect("[process] no process id available");
         let new_session = create_session(pid);
         let new_group = create_process_group(pid, Arc::downgrade(&new_session));
         create_process(pid, Weak::new(), Arc::downgrade(&new_group))
     }
 
-    pub fn fork(self: &Arc<Self>) -> Arc<Process> {
-        let pid = generate_next_pid();
+    /// Return `None` if no process id is available, see [`crate::pid`].
+    pub fn fork(self: &Arc<Self>) -> Option<Arc<Process>> {
+        let pid = alloc_pid()?;
         let new_group = self.get_group();
         let new_process = create_process(pid, Arc::downgrade(self), Arc::downgrade(&new_group));
-        new_process
+        Some(new_process)
     }
 
     pub fn is_zombie(&self) -> bool {
@@ -137,32 +151,92 @@ impl Process {
         self.is_zombie.store(true, Ordering::Release);
     }
 
-    fn get_child_reaper(&self) -> Option<Arc<Process>> {
-        // TODO: child reaper
-        if self.pid == 1 {
-            // TODO: check if the reaper is zombie
-            return None;
+    pub fn is_child_subreaper(&self) -> bool {
+        self.child_subreaper.load(Ordering::Acquire)
+    }
+
+    pub fn set_child_subreaper(&self, child_subreaper: bool) {
+        self.child_subreaper
+            .store(child_subreaper, Ordering::Release);
+    }
+
+    /// Move `children` to the process which adopts the children of this
+    /// process when it exits: the nearest living ancestor marked as a child
+    /// subreaper, or `init`.
+    ///
+    /// A reaper is checked alive under the lock of its children, which `exit`
+    /// holds while the process becomes a zombie, so the children are never
+    /// moved to a process which has already handed over its own children.
+    ///
+    /// Return the reaper, or give the children back if there is no such
+    /// process, e.g. `init` itself exits.
+    fn adopt_children(
+        &self,
+        children: BTreeMap<Pid, Arc<Process>>,
+    ) -> Result<Arc<Process>, BTreeMap<Pid, Arc<Process>>> {
+        let mut reapers = Vec::new();
+        let mut ancestor = self.get_parent();
+        while let Some(process) = ancestor {
+            ancestor = process.get_parent();
+            if process.is_child_subreaper() {
+                reapers.push(process);
+            }
         }
-        PROCESS_TABLE.lock().get(&1).cloned()
+        let init = PROCESS_TABLE.lock().get(&1).cloned();
+        reapers.extend(init.filter(|init| init.pid != self.pid));
+
+        for reaper in reapers {
+            let mut reaper_children = reaper.children.lock();
+            if reaper.is_zombie() {
+                continue;
+            }
+            let weak_reaper = Arc::downgrade(&reaper);
+            for (pid, child) in children {
+                *child.parent.lock() = weak_reaper.clone();
+                reaper_children.insert(pid, child);
+            }
+            drop(reaper_children);
+            return Ok(reaper);
+        }
+        Err(children)
     }
 
-    pub(crate) fn exit(self: &Arc<Self>) {
+    /// Return the process which adopted zombie children, if any, along with
+    /// the zombies, as they will not notify it again.
+    pub(crate) fn exit(self: &Arc<Self>) -> Option<(Arc<Process>, Vec<Arc<Process>>)> {
         assert!(
             !self.is_zombie(),
             "[process] process {} is already exited",
             self.pid
         );
-        self.set_zombie();
         // move children to reaper process
-        let reaper = self.get_child_reaper();
-        if let Some(reaper) = reaper {
+        // `children` of the origin process will be cleared
+        let children = {
             let mut children = self.children.lock();
-            let mut reaper_children = reaper.children.lock();
-            let weak_reaper = Arc::downgrade(&reaper);
-            // `children` of the origin process will be cleared
-            for (pid, child) in core::mem::take(&mut *children) {
-                *child.parent.lock() = weak_reaper.clone();
-                reaper_children.insert(pid, child);
+            // Under the lock, see `adopt_children`.
+            self.set_zombie();
+            core::mem::take(&mut *children)
+        };
+        let zombies = children
+            .values()
+            .filter(|child| child.is_zombie())
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut notify = None;
+        match self.adopt_children(children) {
+            Ok(reaper) => {
+                if !zombies.is_empty() {
+                    notify = Some((reaper, zombies));
+                }
+            }
+            Err(children) => {
+                // no one can wait for the children any more
+                for child in children.into_values() {
+                    *child.parent.lock() = Weak::new();
+                    if child.is_zombie() {
+                        child.release();
+                    }
+                }
             }
         }
 
@@ -170,6 +244,7 @@ impl Process {
             // will become parentless zombie, release itself
             self.release();
         }
+        notify
     }
 
     pub fn release(self: &Arc<Self>) {
@@ -194,18 +269,26 @@ impl Process {
         self.threads.lock().insert(thread.get_tid(), thread);
     }
 
-    pub(crate) fn remove_thread(self: &Arc<Self>, tid: Pid, exit_code: i32) {
+    /// Return the process to be notified of adopted zombie children if the
+    /// process exits, along with the zombies, see `exit`.
+    pub(crate) fn remove_thread(
+        self: &Arc<Self>,
+        tid: Pid,
+        exit_code: i32,
+    ) -> Option<(Arc<Process>, Vec<Arc<Process>>)> {
         self.threads.lock().remove(&tid);
         if self.threads.lock().is_empty() {
             self.exit_code.store(exit_code, Ordering::Relaxed);
-            self.exit()
+            return self.exit();
         }
+        None
     }
 
-    pub fn create_thread(self: &Arc<Self>) -> Arc<Thread> {
-        let tid = generate_next_pid();
+    /// Return `None` if no thread id is available, see [`crate::pid`].
+    pub fn create_thread(self: &Arc<Self>) -> Option<Arc<Thread>> {
+        let tid = alloc_pid()?;
         // `create_thread` will add the thread to the process
-        create_thread(tid, Arc::downgrade(self))
+        Some(create_thread(tid, Arc::downgrade(self)))
     }
 
     pub fn get_main_thread(&self) -> Option<Arc<Thread>> {
@@ -225,12 +308,6 @@ impl Process {
 static PROCESS_TABLE: Mutex<BTreeMap<Pid, Arc<Process>>> =
     Mutex::new(BTreeMap::<Pid, Arc<Process>>::new());
 
-static NEXT_PID: AtomicU32 = AtomicU32::new(1);
-
-fn generate_next_pid() -> Pid {
-    NEXT_PID.fetch_add(1, Ordering::Acquire)
-}
-
 /// Create a new process if the process does not exist
 fn create_process(pid: Pid, parent: Weak<Process>, group: Weak<ProcessGroup>) -> Arc<Process> {
     let mut process_table = PROCESS_TABLE.lock();
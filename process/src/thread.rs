@@ -2,6 +2,7 @@ use crate::Pid;
 use crate::process::Process;
 use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use spin::Mutex;
 
 pub struct Thread {
@@ -27,9 +28,14 @@ impl Thread {
         self.tid == self.get_process().get_pid()
     }
 
-    pub fn exit(self: &Arc<Self>, exit_code: i32) {
-        self.get_process().remove_thread(self.tid, exit_code);
+    /// Remove the thread from its process, the process exits if it is the
+    /// last thread.
+    /// Return the process which adopted zombie children of the exited process,
+    /// which has to be notified of them, if any, along with the zombies.
+    pub fn exit(self: &Arc<Self>, exit_code: i32) -> Option<(Arc<Process>, Vec<Arc<Process>>)> {
+        let reaper = self.get_process().remove_thread(self.tid, exit_code);
         THREAD_TABLE.lock().remove(&self.tid);
+        reaper
     }
 
     fn new(tid: Pid, process: Weak<Process>) -> Arc<Self> {
@@ -13,6 +13,7 @@ use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use starry_core::entry::run_user_app;
+use starry_core::page_cache::{self, PAGE_CACHE};
 
 #[unsafe(no_mangle)]
 fn main() {
@@ -31,8 +32,12 @@ fn main() {
         // "LD_DEBUG=all".to_string(),
     ];
 
+    page_cache::init();
     let exit_code = run_user_app(&args, &envs);
     info!("[task manager] Shell exited with code: {:?}", exit_code);
+    if let Err(err) = PAGE_CACHE.sync_all() {
+        warn!("[task manager] Failed to write back the page cache: {:?}", err);
+    }
     // for testcase in testcases {
     //     let testcase = testcase.trim();
     //     if testcase.is_empty() {
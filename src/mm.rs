@@ -1,14 +1,18 @@
-use axerrno::LinuxError;
 use axhal::mem::VirtAddr;
 use axhal::paging::MappingFlags;
-use axhal::trap::{PAGE_FAULT, register_trap_handler};
-use starry_api::imp::task::sys_exit_impl;
-use starry_core::mm::is_accessing_user_memory;
+use axhal::trap::{PAGE_FAULT, UNFIXED_USER_FAULT, register_trap_handler};
+use axsignal::Signo;
+use linux_raw_sys::general::{BUS_ADRERR, SEGV_ACCERR, SEGV_MAPERR};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddrRange};
+use starry_api::imp::task::signal::send_fault_signal;
+use starry_api::imp::task::exit_by_signal;
+use starry_core::mm::is_user_range;
 use starry_core::task::current_process_data;
 
 #[register_trap_handler(PAGE_FAULT)]
 fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
-    if !is_user && !is_accessing_user_memory() {
+    // The kernel only faults on user memory when accessing it for syscalls.
+    if !is_user && !is_user_range(vaddr, 1) {
         warn!(
             "Page fault at {:#x}, access_flags: {:#x?}",
             vaddr, access_flags
@@ -16,18 +20,72 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
         return false;
     }
 
-    if !current_process_data()
+    // The pages of files are read before the address space is locked, since
+    // reading them may block on I/O for long, while the other threads spin on
+    // the lock. The fault is handled then, with the page in memory.
+    let file_page = current_process_data()
         .addr_space
         .lock()
-        .handle_page_fault(vaddr, access_flags)
-    {
+        .unmapped_file_page(vaddr);
+    if let Some((file, offset)) = file_page {
+        // Errors are left to the fault handling below.
+        let _ = file.prefetch(offset);
+    }
+
+    let (handled, mapped, beyond_eof) = {
+        let process_data = current_process_data();
+        let mut aspace = process_data.addr_space.lock();
+        // If the address is below a stack, grow the stack and try again.
+        let handled = aspace.handle_page_fault(vaddr, access_flags)
+            || (process_data.grow_stack(&mut aspace, vaddr)
+                && aspace.handle_page_fault(vaddr, access_flags));
+        let page = VirtAddrRange::from_start_size(vaddr.align_down_4k(), PAGE_SIZE_4K);
+        (
+            handled,
+            aspace.check_region_access(page, MappingFlags::empty()),
+            !handled && aspace.is_beyond_file_end(vaddr),
+        )
+    };
+    if !handled {
+        // The access from the kernel is fixed up to fail with `EFAULT`, or
+        // handled by `handle_unfixed_user_fault`.
+        if !is_user {
+            return false;
+        }
         warn!(
-            "{}: segmentation fault at {:#x}, exit!",
+            "{}: segmentation fault at {:#x}, access_flags: {:?}",
             axtask::current().id_name(),
-            vaddr
+            vaddr,
+            access_flags
         );
-        // TODO: correct exit code and signal
-        sys_exit_impl(LinuxError::EFAULT as _, false);
+        if beyond_eof {
+            // Like Linux, the page of the file mapping is beyond the end of
+            // the file.
+            send_fault_signal(Signo::SIGBUS, BUS_ADRERR, vaddr);
+        } else {
+            let code = if mapped { SEGV_ACCERR } else { SEGV_MAPERR };
+            send_fault_signal(Signo::SIGSEGV, code, vaddr);
+        }
     }
     true
 }
+
+/// Kills the current process by `SIGSEGV` when the kernel accesses user memory
+/// through a raw pointer, rather than `copy_from_user` or `copy_to_user`, and
+/// the memory is unmapped by another thread after it has been checked.
+///
+/// The syscall cannot go on, and the signal could not be delivered before it
+/// returns, so the whole process is killed as if the signal were not caught.
+#[register_trap_handler(UNFIXED_USER_FAULT)]
+fn handle_unfixed_user_fault(vaddr: VirtAddr, access_flags: MappingFlags) -> bool {
+    if !is_user_range(vaddr, 1) {
+        return false;
+    }
+    warn!(
+        "{}: unchecked access to user memory at {:#x}, access_flags: {:?}, killed!",
+        axtask::current().id_name(),
+        vaddr,
+        access_flags
+    );
+    exit_by_signal(Signo::SIGSEGV, true);
+}
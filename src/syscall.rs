@@ -39,9 +39,16 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::ioctl => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
         Sysno::writev => sys_writev(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         Sysno::sched_yield => sys_sched_yield(),
+        Sysno::getcpu => sys_getcpu(tf.arg0().into(), tf.arg1().into()),
         Sysno::nanosleep => sys_nanosleep(tf.arg0().into(), tf.arg1().into()),
         Sysno::getpid => sys_getpid(),
         Sysno::getppid => sys_getppid(),
+        Sysno::setpgid => sys_setpgid(tf.arg0() as _, tf.arg1() as _),
+        Sysno::getpgid => sys_getpgid(tf.arg0() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::getpgrp => sys_getpgrp(),
+        Sysno::setsid => sys_setsid(),
+        Sysno::getsid => sys_getsid(tf.arg0() as _),
         Sysno::exit => sys_exit(tf.arg0() as _),
         Sysno::gettimeofday => sys_get_time_of_day(tf.arg0().into()),
         Sysno::getcwd => sys_getcwd(tf.arg0().into(), tf.arg1() as _),
@@ -64,8 +71,22 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg3().into(),
             tf.arg4() as _,
         ),
-        Sysno::wait4 => sys_wait4(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::wait4 => sys_wait4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::waitid => sys_waitid(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::pidfd_open => sys_pidfd_open(tf.arg0() as _, tf.arg1() as _),
         Sysno::pipe2 => sys_pipe2(tf.arg0().into(), tf.arg1() as _),
+        Sysno::memfd_create => sys_memfd_create(tf.arg0().into(), tf.arg1() as _),
         Sysno::close => sys_close(tf.arg0() as _),
         Sysno::chdir => sys_chdir(tf.arg0().into()),
         Sysno::execve => sys_execve(tf, tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
@@ -118,8 +139,42 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         ),
         Sysno::munmap => sys_munmap(tf.arg0().into(), tf.arg1() as _),
         Sysno::mprotect => sys_mprotect(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::mremap => sys_mremap(
+            tf.arg0().into(),
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::madvise => sys_madvise(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::mincore => sys_mincore(tf.arg0().into(), tf.arg1() as _, tf.arg2().into()),
+        Sysno::msync => sys_msync(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::mlock => sys_mlock(tf.arg0().into(), tf.arg1() as _),
+        Sysno::mlock2 => sys_mlock2(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::munlock => sys_munlock(tf.arg0().into(), tf.arg1() as _),
+        Sysno::mlockall => sys_mlockall(tf.arg0() as _),
+        Sysno::munlockall => sys_munlockall(),
+        Sysno::process_vm_readv => sys_process_vm_readv(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+            tf.arg4() as _,
+            tf.arg5() as _,
+        ),
+        Sysno::process_vm_writev => sys_process_vm_writev(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+            tf.arg4() as _,
+            tf.arg5() as _,
+        ),
         Sysno::times => sys_times(tf.arg0().into()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::time => sys_time(tf.arg0().into()),
         Sysno::brk => sys_brk(tf.arg0() as _),
+        Sysno::prctl => sys_prctl(tf.arg0() as _, tf.arg1() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::arch_prctl => sys_arch_prctl(tf.arg0() as _, tf.arg1().into(), tf),
         Sysno::set_tid_address => sys_set_tid_address(tf.arg0().into()),
@@ -254,7 +309,7 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg3() as _,
         ),
         Sysno::shmat => sys_shmat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
-        Sysno::shmctl => sys_shmctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::shmctl => sys_shmctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
         Sysno::shmdt => sys_shmdt(tf.arg0() as _),
         Sysno::shmget => sys_shmget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::sigaltstack => sys_sigaltstack(tf.arg0().into(), tf.arg1().into()),
@@ -305,8 +360,8 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         #[cfg(target_arch = "x86_64")]
         Sysno::access => stub_bypass(syscall_num),
         Sysno::faccessat => stub_bypass(syscall_num),
-        Sysno::sync => stub_bypass(syscall_num),
-        Sysno::fsync => stub_bypass(syscall_num),
+        Sysno::sync => sys_sync(),
+        Sysno::fsync | Sysno::fdatasync => sys_fsync(tf.arg0() as _),
         Sysno::truncate => sys_truncate(tf.arg0().into(), tf.arg1() as _),
         Sysno::ftruncate => sys_ftruncate(tf.arg0() as _, tf.arg1() as _),
         Sysno::syslog => stub_bypass(syscall_num),